@@ -0,0 +1,144 @@
+// Benchmark reading data entries from a local server, reporting the allocations per entry and,
+// with the metrics feature, the reads from the socket per entry for several read ahead sizes
+use datastreamer_rs::stream_client::{
+    Entry, EntryType, HeaderEntry, PacketType, ResultEntry, StreamClient,
+};
+use futures::StreamExt;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+const ENTRIES: u64 = 100_000;
+const DATA_SIZE: usize = 256;
+const READ_AHEAD_SIZES: [usize; 3] = [1, 8 << 10, 64 << 10];
+
+// CountingAlloc type counting the allocations made by the process
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+// serve answers the header and start commands and streams the encoded entries
+async fn serve(listener: TcpListener, entries: Vec<u8>) {
+    let (mut conn, _) = listener.accept().await.unwrap();
+    let ok = ResultEntry {
+        packet_type: PacketType::PtResult as u8,
+        ..Default::default()
+    }
+    .encode_to_binary();
+    let header = HeaderEntry {
+        packet_type: PacketType::PtHeader as u8,
+        version: 1,
+        total_entries: ENTRIES,
+        ..Default::default()
+    }
+    .encode_to_binary();
+
+    // Header command
+    conn.read_exact(&mut [0u8; 16]).await.unwrap();
+    conn.write_all(&[ok.clone(), header].concat())
+        .await
+        .unwrap();
+    // Start command
+    conn.read_exact(&mut [0u8; 24]).await.unwrap();
+    conn.write_all(&ok).await.unwrap();
+    conn.write_all(&entries).await.unwrap();
+    let _ = conn.read(&mut [0u8; 1]).await;
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    #[cfg(feature = "metrics")]
+    let snapshotter = {
+        let recorder = metrics_util::debugging::DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        recorder.install().unwrap();
+        snapshotter
+    };
+    #[cfg(feature = "metrics")]
+    let socket_reads = || {
+        snapshotter
+            .snapshot()
+            .into_vec()
+            .into_iter()
+            .find(|(key, _, _, _)| {
+                key.key().name() == datastreamer_rs::stream_client::METRIC_SOCKET_READS
+            })
+            .map_or(0, |(_, _, _, value)| match value {
+                metrics_util::debugging::DebugValue::Counter(n) => n,
+                _ => 0,
+            })
+    };
+
+    let entries: Vec<u8> = (0..ENTRIES)
+        .flat_map(|number| {
+            Entry {
+                packet_type: PacketType::PtData as u8,
+                entry_type: EntryType::Event1,
+                number,
+                data: vec![0xab; DATA_SIZE],
+                ..Default::default()
+            }
+            .encode_to_binary()
+        })
+        .collect();
+
+    for read_ahead_size in READ_AHEAD_SIZES {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+        tokio::spawn(serve(listener, entries.clone()));
+
+        let client = StreamClient::builder()
+            .server(server)
+            .read_ahead_size(read_ahead_size)
+            .build()
+            .unwrap();
+        let mut stream = Box::pin(client.into_entry_stream());
+
+        // Skip the first entry so the connection setup isn't counted
+        stream.next().await.unwrap().unwrap();
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        let reads = socket_reads();
+        let start = Instant::now();
+
+        let mut read = 0;
+        while read < ENTRIES - 1 {
+            stream.next().await.unwrap().unwrap();
+            read += 1;
+        }
+
+        let elapsed = start.elapsed();
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+        println!(
+            "read ahead {} bytes: read {} entries of {} bytes in {:?} ({:.0} entries/s), {:.2} allocations per entry",
+            read_ahead_size,
+            read,
+            DATA_SIZE,
+            elapsed,
+            read as f64 / elapsed.as_secs_f64(),
+            allocations as f64 / read as f64
+        );
+        #[cfg(feature = "metrics")]
+        println!(
+            "read ahead {} bytes: {:.3} socket reads per entry",
+            read_ahead_size,
+            (socket_reads() - reads) as f64 / read as f64
+        );
+    }
+}
@@ -0,0 +1,27 @@
+// Example reading the header and the first entries of a data stream without an async runtime
+use datastreamer_rs::blocking::BlockingStreamClient;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let server = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "stream.zkevm-rpc.com:6900".to_string());
+
+    let mut client = BlockingStreamClient::new(server)?;
+    client.connect_server()?;
+
+    let header = client.exec_command_get_header()?;
+    println!("Header: {:?}", header);
+
+    for number in 0..header.total_entries.min(5) {
+        let entry = client.exec_command_get_entry(number)?;
+        println!(
+            "Entry {}: type {:?}, {} bytes",
+            entry.number,
+            entry.entry_type,
+            entry.data.len()
+        );
+    }
+
+    client.close_connection();
+    Ok(())
+}
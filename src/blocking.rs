@@ -0,0 +1,105 @@
+use crate::stream_client::{Bookmark, ClientError, Entry, HeaderEntry, StreamClient};
+use tokio::runtime::{Builder, Runtime};
+
+// BlockingStreamClient type to use a StreamClient from synchronous code without an async runtime.
+// It owns a single threaded runtime to drive the client, so its methods must not be called from
+// within an async context.
+#[derive(Debug)]
+pub struct BlockingStreamClient {
+    client: StreamClient,
+    runtime: Runtime,
+}
+
+impl BlockingStreamClient {
+    pub fn new(server: String) -> Result<BlockingStreamClient, Box<dyn std::error::Error>> {
+        let client = StreamClient::new(server)?;
+
+        Ok(BlockingStreamClient::with_client(client)?)
+    }
+
+    // with_client wraps a StreamClient configured with the StreamClientBuilder
+    pub fn with_client(client: StreamClient) -> std::io::Result<BlockingStreamClient> {
+        let runtime = Builder::new_current_thread().enable_all().build()?;
+
+        Ok(BlockingStreamClient { client, runtime })
+    }
+
+    // connect_server blocks until the server connection is established
+    pub fn connect_server(&mut self) -> Result<bool, ClientError> {
+        self.runtime.block_on(self.client.connect_server())
+    }
+
+    // start connects to the server and blocks processing the streamed entries with the
+    // process_entry_hook until the streaming is stopped
+    pub fn start(&mut self) -> Result<(), ClientError> {
+        self.runtime.block_on(self.client.start())
+    }
+
+    // exec_command_get_header executes client TCP command to get the header
+    pub fn exec_command_get_header(&mut self) -> Result<HeaderEntry, ClientError> {
+        self.runtime.block_on(self.client.exec_command_get_header())
+    }
+
+    // exec_command_get_entry executes client TCP command to get an entry
+    pub fn exec_command_get_entry(&mut self, from_entry: u64) -> Result<Entry, ClientError> {
+        self.runtime
+            .block_on(self.client.exec_command_get_entry(from_entry))
+    }
+
+    // exec_command_get_bookmark executes client TCP command to get a bookmark
+    pub fn exec_command_get_bookmark(
+        &mut self,
+        from_bookmark: impl Into<Bookmark>,
+    ) -> Result<Entry, ClientError> {
+        self.runtime
+            .block_on(self.client.exec_command_get_bookmark(from_bookmark))
+    }
+
+    // close_connection closes the connection to the server
+    pub fn close_connection(&mut self) {
+        self.client.close_connection();
+    }
+
+    // into_inner returns the wrapped StreamClient
+    pub fn into_inner(self) -> StreamClient {
+        self.client
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream_client::{EntryType, StreamType};
+    use crate::stream_server::StreamServer;
+
+    #[test]
+    fn test_blocking_client() {
+        // The server runs on its own runtime, the client doesn't need one
+        let server_runtime = tokio::runtime::Runtime::new().unwrap();
+        let mut server = StreamServer::new(0, 1, 1101, StreamType::Sequencer);
+        server.start_atomic_op().unwrap();
+        for i in 0..3u64 {
+            server
+                .add_stream_entry(EntryType::Event1, i.to_be_bytes().to_vec())
+                .unwrap();
+        }
+        server.commit_atomic_op().unwrap();
+        server_runtime.block_on(server.start()).unwrap();
+        let addr = format!("127.0.0.1:{}", server.local_addr().unwrap().port());
+
+        let mut client = BlockingStreamClient::new(addr).unwrap();
+        client.connect_server().unwrap();
+
+        let header = client.exec_command_get_header().unwrap();
+        assert_eq!(header.total_entries, 3);
+
+        let e = client.exec_command_get_entry(2).unwrap();
+        assert_eq!(e.number, 2);
+        assert_eq!(e.data, 2u64.to_be_bytes().to_vec());
+
+        assert!(matches!(
+            client.exec_command_get_entry(10),
+            Err(ClientError::EntryNotFound)
+        ));
+    }
+}
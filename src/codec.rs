@@ -0,0 +1,214 @@
+//! Decoding of the data stream packets from raw bytes, e.g. captured from the network, without
+//! a client connection. All the integers are big endian.
+//!
+//! The decoders are lenient like the client by default, keeping the entry types not defined by
+//! the protocol as [`EntryType::Event`](crate::protocol::EntryType::Event). The strict variants
+//! fail on them with [`DecodeError::UnknownType`], as a client built with `strict(true)` does.
+
+use crate::entry_data::DecodeError;
+use crate::protocol::PacketType;
+use crate::stream_client::{self, ClientError, Entry, HeaderEntry, ResultEntry};
+
+// Sizes of the fixed size fields of the packets
+pub const HEADER_SIZE: usize = stream_client::HEADER_SIZE;
+pub const FIXED_SIZE_DATA_ENTRY: usize = stream_client::FIXED_SIZE_FILE_ENTRY;
+pub const FIXED_SIZE_RESULT_ENTRY: usize = stream_client::FIXED_SIZE_RESULT_ENTRY;
+
+/// Decodes a header entry, exactly [`HEADER_SIZE`] bytes:
+///
+/// | bytes  | field                     |
+/// |--------|---------------------------|
+/// | 0      | packet type (1: header)   |
+/// | 1..5   | head length (u32, 38)     |
+/// | 5      | version                   |
+/// | 6..14  | system id (u64)           |
+/// | 14..22 | stream type (u64)         |
+/// | 22..30 | total length (u64)        |
+/// | 30..38 | total entries (u64)       |
+///
+/// ```
+/// use datastreamer_rs::codec::decode_binary_to_header_entry;
+///
+/// let b = [
+///     0x01, // packet type
+///     0x00, 0x00, 0x00, 0x26, // head length
+///     0x03, // version
+///     0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x4d, // system id
+///     0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, // stream type
+///     0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00, // total length
+///     0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0a, // total entries
+/// ];
+/// let header = decode_binary_to_header_entry(&b).unwrap();
+/// assert_eq!(header.version, 3);
+/// assert_eq!(header.system_id.to_string(), "1101");
+/// assert_eq!(header.total_length, 4096);
+/// assert_eq!(header.total_entries, 10);
+/// ```
+pub fn decode_binary_to_header_entry(b: &[u8]) -> Result<HeaderEntry, DecodeError> {
+    stream_client::decode_binary_to_header_entry(b).map_err(decode_error)
+}
+
+/// Decodes a data entry, [`FIXED_SIZE_DATA_ENTRY`] bytes followed by the entry data:
+///
+/// | bytes  | field                                      |
+/// |--------|--------------------------------------------|
+/// | 0      | packet type (2: data, 0xfe: data response) |
+/// | 1..5   | length (u32, 17 + data length)             |
+/// | 5..9   | entry type (u32)                           |
+/// | 9..17  | entry number (u64)                         |
+/// | 17..   | data                                       |
+///
+/// ```
+/// use datastreamer_rs::codec::decode_binary_to_entry;
+///
+/// let b = [
+///     0x02, // packet type
+///     0x00, 0x00, 0x00, 0x13, // length
+///     0x00, 0x00, 0x00, 0x01, // entry type
+///     0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x07, // entry number
+///     0xca, 0xfe, // data
+/// ];
+/// let entry = decode_binary_to_entry(&b).unwrap();
+/// assert_eq!(entry.number, 7);
+/// assert_eq!(entry.data, vec![0xca, 0xfe]);
+/// ```
+pub fn decode_binary_to_entry(b: &[u8]) -> Result<Entry, DecodeError> {
+    stream_client::decode_binary_to_entry(b).map_err(decode_error)
+}
+
+/// Decodes a data entry like [`decode_binary_to_entry`], failing with
+/// [`DecodeError::UnknownType`] if its packet type or entry type isn't defined by the protocol.
+///
+/// ```
+/// use datastreamer_rs::codec::decode_binary_to_entry_strict;
+/// use datastreamer_rs::entry_data::DecodeError;
+///
+/// let b = [
+///     0x02, // packet type
+///     0x00, 0x00, 0x00, 0x11, // length
+///     0x00, 0x00, 0x00, 0x07, // entry type
+///     0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x07, // entry number
+/// ];
+/// assert_eq!(decode_binary_to_entry_strict(&b), Err(DecodeError::UnknownType(7)));
+/// ```
+pub fn decode_binary_to_entry_strict(b: &[u8]) -> Result<Entry, DecodeError> {
+    // Unknown packet types are converted to PtPadding, whose value is zero
+    if let Some(&packet) = b.first() {
+        if PacketType::from(packet) as u8 != packet {
+            return Err(DecodeError::UnknownType(packet as u32));
+        }
+    }
+    let e = decode_binary_to_entry(b)?;
+    stream_client::check_known_entry_type(e.entry_type).map_err(decode_error)?;
+    Ok(e)
+}
+
+/// Decodes a result entry, [`FIXED_SIZE_RESULT_ENTRY`] bytes followed by the error string:
+///
+/// | bytes | field                                  |
+/// |-------|----------------------------------------|
+/// | 0     | packet type (0xff: result)             |
+/// | 1..5  | length (u32, 9 + error string length)  |
+/// | 5..9  | error number (u32, 0: no error)        |
+/// | 9..   | error string                           |
+///
+/// ```
+/// use datastreamer_rs::codec::decode_binary_to_result_entry;
+///
+/// let b = [0xff, 0x00, 0x00, 0x00, 0x0b, 0x00, 0x00, 0x00, 0x00, b'O', b'K'];
+/// let result = decode_binary_to_result_entry(&b).unwrap();
+/// assert_eq!(result.error_num, 0);
+/// assert_eq!(result.error_str, b"OK");
+/// ```
+pub fn decode_binary_to_result_entry(b: &[u8]) -> Result<ResultEntry, DecodeError> {
+    stream_client::decode_binary_to_result_entry(b).map_err(decode_error)
+}
+
+// decode_error converts the client decoding errors to a DecodeError
+fn decode_error(e: ClientError) -> DecodeError {
+    match e {
+        ClientError::DecodeError(msg) => DecodeError::InvalidData(msg),
+        ClientError::Decode(e) => e,
+        e => DecodeError::InvalidData(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::EntryType;
+
+    #[test]
+    fn test_decode_round_trip() {
+        let header = HeaderEntry {
+            packet_type: PacketType::PtHeader as u8,
+            head_length: HEADER_SIZE as u32,
+            version: 3,
+            total_entries: 5,
+            ..Default::default()
+        };
+        assert_eq!(
+            decode_binary_to_header_entry(&header.encode_to_binary()),
+            Ok(header)
+        );
+
+        let entry = Entry {
+            packet_type: PacketType::PtData as u8,
+            length: FIXED_SIZE_DATA_ENTRY as u32 + 3,
+            entry_type: EntryType::Event2,
+            number: 42,
+            data: vec![1, 2, 3],
+            raw: None,
+            received_at: None,
+        };
+        assert_eq!(decode_binary_to_entry(&entry.encode_to_binary()), Ok(entry));
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        assert!(matches!(
+            decode_binary_to_header_entry(&[PacketType::PtHeader as u8; 20]),
+            Err(DecodeError::InvalidData(msg)) if msg.contains("expected 38 bytes, got 20")
+        ));
+        assert!(matches!(
+            decode_binary_to_entry(&[PacketType::PtData as u8; 4]),
+            Err(DecodeError::InvalidData(_))
+        ));
+        assert!(matches!(
+            decode_binary_to_result_entry(&[PacketType::PtData as u8; 9]),
+            Err(DecodeError::InvalidData(msg)) if msg.contains("unexpected packet type 0x02")
+        ));
+    }
+
+    #[test]
+    fn test_decode_strict() {
+        let mut entry = Entry {
+            packet_type: PacketType::PtData as u8,
+            length: FIXED_SIZE_DATA_ENTRY as u32 + 1,
+            entry_type: EntryType::Event2,
+            number: 3,
+            data: vec![1],
+            raw: None,
+            received_at: None,
+        };
+        assert_eq!(
+            decode_binary_to_entry_strict(&entry.encode_to_binary()),
+            Ok(entry.clone())
+        );
+
+        entry.entry_type = EntryType::Event(3);
+        let b = entry.encode_to_binary();
+        assert_eq!(decode_binary_to_entry(&b), Ok(entry));
+        assert_eq!(
+            decode_binary_to_entry_strict(&b),
+            Err(DecodeError::UnknownType(3))
+        );
+
+        let mut b = b;
+        b[0] = 0x07;
+        assert_eq!(
+            decode_binary_to_entry_strict(&b),
+            Err(DecodeError::UnknownType(7))
+        );
+    }
+}
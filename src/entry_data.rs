@@ -0,0 +1,58 @@
+use crate::stream_client::{Entry, EntryType};
+use thiserror::Error;
+
+// DecodeError enum represents the errors decoding an entry or its data
+#[derive(Error, Debug, PartialEq)]
+pub enum DecodeError {
+    #[error("Invalid data length: expected {0} bytes, got {1}")]
+    InvalidLength(usize, usize),
+    #[error("Unexpected entry type {0:?}")]
+    UnexpectedEntryType(EntryType),
+    #[error("Invalid data: {0}")]
+    InvalidData(String),
+    #[error("Unknown type {0:#x}")]
+    UnknownType(u32), // Packet or entry type value not defined by the protocol
+}
+
+// FromEntryData trait for the types decoded from the data of an entry
+pub trait FromEntryData: Sized {
+    fn from_entry_data(entry_type: EntryType, data: &[u8]) -> Result<Self, DecodeError>;
+
+    // from_entry decodes the data of entry
+    fn from_entry(entry: &Entry) -> Result<Self, DecodeError> {
+        Self::from_entry_data(entry.entry_type, &entry.data)
+    }
+}
+
+impl Entry {
+    // decode_data decodes the entry data to a FromEntryData type
+    pub fn decode_data<T: FromEntryData>(&self) -> Result<T, DecodeError> {
+        T::from_entry_data(self.entry_type, &self.data)
+    }
+}
+
+impl FromEntryData for Vec<u8> {
+    fn from_entry_data(_entry_type: EntryType, data: &[u8]) -> Result<Self, DecodeError> {
+        Ok(data.to_vec())
+    }
+}
+
+// L2BlockStart moved to the zkevm module, re-exported to keep its former path
+#[cfg(feature = "zkevm")]
+pub use crate::zkevm::{L2BlockStart, L2_BLOCK_START_SIZE};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_raw_data() {
+        let entry = Entry {
+            entry_type: EntryType::Event(7),
+            data: vec![0x01, 0x02],
+            ..Default::default()
+        };
+        assert_eq!(entry.decode_data::<Vec<u8>>().unwrap(), entry.data);
+        assert_eq!(Vec::<u8>::from_entry(&entry).unwrap(), entry.data);
+    }
+}
@@ -1 +1,12 @@
+pub mod blocking;
+pub mod codec;
+pub mod entry_data;
+pub mod pool;
+pub mod protocol;
 pub mod stream_client;
+pub mod stream_file;
+pub mod stream_server;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_support;
+#[cfg(feature = "zkevm")]
+pub mod zkevm;
@@ -1,4 +1,4 @@
-pub mod stream_client;
+use datastreamer_rs::stream_client;
 
 #[tokio::main]
 async fn main() {
@@ -10,5 +10,7 @@ async fn main() {
     let mut server = stream_client::StreamClient::new("stream.zkevm-rpc.com:6900".to_string())
         .expect("Failed to create StreamClient");
 
-    _ = server.start().await;
+    if let Err(e) = server.start().await {
+        tracing::error!("Streaming failed: {}", e);
+    }
 }
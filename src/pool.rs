@@ -0,0 +1,146 @@
+use crate::stream_client::{ClientError, Entry, StreamClient};
+use futures::future::join_all;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::sync::Mutex;
+
+// StreamClientPool type to run entry lookups concurrently over several server connections
+#[derive(Debug)]
+pub struct StreamClientPool {
+    clients: Vec<Mutex<StreamClient>>,
+    next: AtomicUsize, // Index of the client for the next request, round-robin
+}
+
+impl StreamClientPool {
+    // new creates a pool of size clients with the default configuration connecting to server
+    pub fn new(server: String, size: usize) -> Result<StreamClientPool, ClientError> {
+        let clients = (0..size.max(1))
+            .map(|_| StreamClient::builder().server(server.clone()).build())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        StreamClientPool::with_clients(clients)
+    }
+
+    // with_clients creates a pool with the given clients, configured with the StreamClientBuilder.
+    // A pool needs at least one client.
+    pub fn with_clients(clients: Vec<StreamClient>) -> Result<StreamClientPool, ClientError> {
+        if clients.is_empty() {
+            return Err(ClientError::InvalidConfig(
+                "a pool needs at least one client".to_string(),
+            ));
+        }
+
+        Ok(StreamClientPool {
+            clients: clients.into_iter().map(Mutex::new).collect(),
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    // size returns the number of clients of the pool
+    pub fn size(&self) -> usize {
+        self.clients.len()
+    }
+
+    // get_entry gets an entry with the next client of the pool, connecting it if needed
+    pub async fn get_entry(&self, from_entry: u64) -> Result<Entry, ClientError> {
+        let i = self.next.fetch_add(1, Ordering::Relaxed) % self.clients.len();
+        let mut client = self.clients[i].lock().await;
+
+        client.connect_server().await?;
+        client.exec_command_get_entry(from_entry).await
+    }
+
+    // get_entries gets the entries spreading the requests across the pool clients, the results
+    // are returned in the order of the requested entry numbers
+    pub async fn get_entries<I>(&self, numbers: I) -> Vec<Result<Entry, ClientError>>
+    where
+        I: IntoIterator<Item = u64>,
+    {
+        join_all(numbers.into_iter().map(|n| self.get_entry(n))).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream_client::{EntryType, PacketType, ResultEntry};
+    use std::time::{Duration, Instant};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    const LATENCY: Duration = Duration::from_millis(50);
+
+    // start_slow_server answers every entry command on every connection after LATENCY
+    async fn start_slow_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            while let Ok((mut conn, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut command = [0u8; 24];
+                    while conn.read_exact(&mut command).await.is_ok() {
+                        let number = u64::from_be_bytes(command[16..24].try_into().unwrap());
+                        tokio::time::sleep(LATENCY).await;
+
+                        let result = ResultEntry {
+                            packet_type: PacketType::PtResult as u8,
+                            ..Default::default()
+                        };
+                        let entry = Entry {
+                            packet_type: PacketType::PtDataRsp as u8,
+                            entry_type: EntryType::Event1,
+                            number,
+                            data: number.to_be_bytes().to_vec(),
+                            ..Default::default()
+                        };
+                        let response =
+                            [result.encode_to_binary(), entry.encode_to_binary()].concat();
+                        if conn.write_all(&response).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+
+        server
+    }
+
+    #[tokio::test]
+    async fn test_pool_parallel_lookups() {
+        let server = start_slow_server().await;
+        let numbers: Vec<u64> = (0..8).collect();
+
+        let serial = StreamClientPool::new(server.clone(), 1).unwrap();
+        let start = Instant::now();
+        let entries = serial.get_entries(numbers.clone()).await;
+        let serial_elapsed = start.elapsed();
+        assert!(entries.iter().all(|e| e.is_ok()));
+
+        let pool = StreamClientPool::new(server, 4).unwrap();
+        assert_eq!(pool.size(), 4);
+        let start = Instant::now();
+        let entries = pool.get_entries(numbers.clone()).await;
+        let pool_elapsed = start.elapsed();
+
+        // Results are returned in the requested order
+        let received: Vec<u64> = entries.into_iter().map(|e| e.unwrap().number).collect();
+        assert_eq!(received, numbers);
+
+        assert!(serial_elapsed >= LATENCY * 8);
+        assert!(
+            pool_elapsed * 2 < serial_elapsed,
+            "pool {:?}, serial {:?}",
+            pool_elapsed,
+            serial_elapsed
+        );
+    }
+
+    #[test]
+    fn test_pool_without_clients() {
+        assert!(matches!(
+            StreamClientPool::with_clients(Vec::new()),
+            Err(ClientError::InvalidConfig(_))
+        ));
+    }
+}
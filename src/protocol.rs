@@ -0,0 +1,399 @@
+// Wire protocol types shared by the client, the server and the stream files: the commands and
+// their results, the packet, entry and stream types.
+use std::convert::TryFrom;
+
+// EntryTypeNotFound is the entry type value for CmdEntry/CmdBookmark when entry/bookmark not found
+pub const ENTRY_TYPE_NOT_FOUND: u32 = u32::MAX;
+const ENTRY_TYPE_BOOKMARK: u32 = 0xb0;
+
+// EntryType enum represents the entry event types. The types are compared and hashed by their
+// value, so Event(1) is the same type as Event1.
+#[derive(Debug, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum EntryType {
+    #[default]
+    NotFound, // EntryTypeNotFound for entry not found (ENTRY_TYPE_NOT_FOUND)
+    Bookmark,   // EntryTypeBookmark for bookmark entry (0xb0)
+    Event1,     // EntryTypeEvent1 for event type 1
+    Event2,     // EntryTypeEvent2 for event type 2
+    Event(u32), // Any other event type number
+}
+
+impl PartialEq for EntryType {
+    fn eq(&self, other: &Self) -> bool {
+        u32::from(*self) == u32::from(*other)
+    }
+}
+
+impl Eq for EntryType {}
+
+impl std::hash::Hash for EntryType {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        u32::from(*self).hash(state);
+    }
+}
+
+impl From<u32> for EntryType {
+    fn from(v: u32) -> Self {
+        match v {
+            ENTRY_TYPE_NOT_FOUND => EntryType::NotFound,
+            ENTRY_TYPE_BOOKMARK => EntryType::Bookmark,
+            1 => EntryType::Event1,
+            2 => EntryType::Event2,
+            _ => EntryType::Event(v),
+        }
+    }
+}
+
+impl From<EntryType> for u32 {
+    fn from(v: EntryType) -> Self {
+        match v {
+            EntryType::NotFound => ENTRY_TYPE_NOT_FOUND,
+            EntryType::Bookmark => ENTRY_TYPE_BOOKMARK,
+            EntryType::Event1 => 1,
+            EntryType::Event2 => 2,
+            EntryType::Event(v) => v,
+        }
+    }
+}
+
+// Command enum represents the TCP client commands
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    CmdStart = 1,     // CmdStart for the start from entry TCP client command
+    CmdStop,          // CmdStop for the stop TCP client command
+    CmdHeader,        // CmdHeader for the header TCP client command
+    CmdStartBookmark, // CmdStartBookmark for the start from bookmark TCP client command
+    CmdEntry,         // CmdEntry for the get entry TCP client command
+    CmdBookmark,      // CmdBookmark for the get bookmark TCP client command
+}
+
+impl Command {
+    // to_be_bytes returns the command encoded as sent to the server
+    pub fn to_be_bytes(self) -> [u8; 8] {
+        u64::from(self).to_be_bytes()
+    }
+}
+
+impl From<Command> for u64 {
+    fn from(v: Command) -> Self {
+        v as u64
+    }
+}
+
+impl TryFrom<u64> for Command {
+    type Error = u64;
+
+    fn try_from(v: u64) -> Result<Self, Self::Error> {
+        match v {
+            1 => Ok(Command::CmdStart),
+            2 => Ok(Command::CmdStop),
+            3 => Ok(Command::CmdHeader),
+            4 => Ok(Command::CmdStartBookmark),
+            5 => Ok(Command::CmdEntry),
+            6 => Ok(Command::CmdBookmark),
+            _ => Err(v),
+        }
+    }
+}
+
+// CommandError enum represents the errors of the command results
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CommandError {
+    CmdErrOK,              // CmdErrOK for no error (0)
+    CmdErrAlreadyStarted,  // CmdErrAlreadyStarted for client already started error (1)
+    CmdErrAlreadyStopped,  // CmdErrAlreadyStopped for client already stopped error (2)
+    CmdErrBadFromEntry,    // CmdErrBadFromEntry for invalid starting entry number (3)
+    CmdErrBadFromBookmark, // CmdErrBadFromBookmark for invalid starting bookmark (4)
+    CmdErrInvalidCommand,  // CmdErrInvalidCommand for invalid/unknown command error (9)
+    Unknown(u32),          // Any other error code sent by the server
+}
+
+impl From<u32> for CommandError {
+    fn from(v: u32) -> Self {
+        match v {
+            0 => CommandError::CmdErrOK,
+            1 => CommandError::CmdErrAlreadyStarted,
+            2 => CommandError::CmdErrAlreadyStopped,
+            3 => CommandError::CmdErrBadFromEntry,
+            4 => CommandError::CmdErrBadFromBookmark,
+            9 => CommandError::CmdErrInvalidCommand,
+            _ => CommandError::Unknown(v),
+        }
+    }
+}
+
+impl From<CommandError> for u32 {
+    fn from(v: CommandError) -> Self {
+        match v {
+            CommandError::CmdErrOK => 0,
+            CommandError::CmdErrAlreadyStarted => 1,
+            CommandError::CmdErrAlreadyStopped => 2,
+            CommandError::CmdErrBadFromEntry => 3,
+            CommandError::CmdErrBadFromBookmark => 4,
+            CommandError::CmdErrInvalidCommand => 9,
+            CommandError::Unknown(v) => v,
+        }
+    }
+}
+
+impl std::fmt::Display for CommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            CommandError::CmdErrOK => "no error",
+            CommandError::CmdErrAlreadyStarted => "client already started",
+            CommandError::CmdErrAlreadyStopped => "client already stopped",
+            CommandError::CmdErrBadFromEntry => "invalid starting entry number",
+            CommandError::CmdErrBadFromBookmark => "invalid starting bookmark",
+            CommandError::CmdErrInvalidCommand => "invalid command",
+            CommandError::Unknown(v) => return write!(f, "unknown error code {}", v),
+        };
+        write!(f, "{}", description)
+    }
+}
+
+// ChainId type for the system identifier of a stream header
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChainId(pub u64);
+
+impl From<u64> for ChainId {
+    fn from(v: u64) -> Self {
+        ChainId(v)
+    }
+}
+
+impl From<ChainId> for u64 {
+    fn from(v: ChainId) -> Self {
+        v.0
+    }
+}
+
+impl std::fmt::Display for ChainId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+// StreamType enum represents the stream types. The types are compared by their value, so
+// Other(1) is the same type as Sequencer.
+#[derive(Debug, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StreamType {
+    #[default]
+    Sequencer, // Sequencer for sequencer stream type (1)
+    Other(u64), // Any other stream type number
+}
+
+impl PartialEq for StreamType {
+    fn eq(&self, other: &Self) -> bool {
+        u64::from(*self) == u64::from(*other)
+    }
+}
+
+impl From<u64> for StreamType {
+    fn from(v: u64) -> Self {
+        match v {
+            1 => StreamType::Sequencer,
+            _ => StreamType::Other(v),
+        }
+    }
+}
+
+impl StreamType {
+    // to_be_bytes returns the stream type encoded as sent to the server
+    pub fn to_be_bytes(self) -> [u8; 8] {
+        u64::from(self).to_be_bytes()
+    }
+}
+
+impl From<StreamType> for u64 {
+    fn from(v: StreamType) -> Self {
+        match v {
+            StreamType::Sequencer => 1,
+            StreamType::Other(v) => v,
+        }
+    }
+}
+
+// PacketType enum represents the packet types
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
+pub enum PacketType {
+    PtPadding = 0u8,  // PtPadding is packet type for pad
+    PtHeader = 0x01,  // PtHeader is packet type just for the header page
+    PtData = 0x02,    // PtData is packet type for data entry
+    PtDataRsp = 0xfe, // PtDataRsp is packet type for command response with data
+    PtResult = 0xff, // PtResult is packet type not stored/present in file (just for client command result)
+}
+
+impl From<u8> for PacketType {
+    fn from(v: u8) -> Self {
+        match v {
+            0x00 => PacketType::PtPadding,
+            0x01 => PacketType::PtHeader,
+            0x02 => PacketType::PtData,
+            0xfe => PacketType::PtDataRsp,
+            0xff => PacketType::PtResult,
+            _ => PacketType::PtPadding,
+        }
+    }
+}
+
+// CommandFrame type to assemble a command with its parameters in memory, written to the server
+// with a single write
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandFrame {
+    b: Vec<u8>,
+}
+
+impl CommandFrame {
+    // new starts the frame of the command for stream_type
+    pub fn new(cmd: Command, stream_type: StreamType) -> CommandFrame {
+        let mut b = Vec::with_capacity(24);
+        b.extend_from_slice(&cmd.to_be_bytes());
+        b.extend_from_slice(&stream_type.to_be_bytes());
+        CommandFrame { b }
+    }
+
+    // from_entry adds the entry number parameter of the start and entry commands
+    pub fn from_entry(mut self, entry: u64) -> Self {
+        self.b.extend_from_slice(&entry.to_be_bytes());
+        self
+    }
+
+    // bookmark adds the length prefixed bookmark parameter of the start bookmark and bookmark
+    // commands
+    pub fn bookmark(mut self, bookmark: &[u8]) -> Self {
+        self.b
+            .extend_from_slice(&(bookmark.len() as u32).to_be_bytes());
+        self.b.extend_from_slice(bookmark);
+        self
+    }
+
+    // as_bytes returns the bytes of the frame
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.b
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The discriminants are the protocol values, they must never change
+
+    #[test]
+    fn test_command_values() {
+        let commands = [
+            (Command::CmdStart, 1),
+            (Command::CmdStop, 2),
+            (Command::CmdHeader, 3),
+            (Command::CmdStartBookmark, 4),
+            (Command::CmdEntry, 5),
+            (Command::CmdBookmark, 6),
+        ];
+        for (cmd, v) in commands {
+            assert_eq!(u64::from(cmd), v);
+            assert_eq!(cmd.to_be_bytes(), v.to_be_bytes());
+            assert_eq!(Command::try_from(v), Ok(cmd));
+        }
+        assert_eq!(Command::try_from(0), Err(0));
+        assert_eq!(Command::try_from(7), Err(7));
+    }
+
+    #[test]
+    fn test_command_error_values() {
+        let errors = [
+            (CommandError::CmdErrOK, 0),
+            (CommandError::CmdErrAlreadyStarted, 1),
+            (CommandError::CmdErrAlreadyStopped, 2),
+            (CommandError::CmdErrBadFromEntry, 3),
+            (CommandError::CmdErrBadFromBookmark, 4),
+            (CommandError::CmdErrInvalidCommand, 9),
+        ];
+        for (error, v) in errors {
+            assert_eq!(u32::from(error), v);
+            assert_eq!(CommandError::from(v), error);
+        }
+        for v in [5, 8, 10, u32::MAX] {
+            assert_eq!(CommandError::from(v), CommandError::Unknown(v));
+            assert_eq!(u32::from(CommandError::from(v)), v);
+        }
+        assert_eq!(
+            CommandError::Unknown(42).to_string(),
+            "unknown error code 42"
+        );
+    }
+
+    #[test]
+    fn test_packet_type_values() {
+        let packets = [
+            (PacketType::PtPadding, 0x00),
+            (PacketType::PtHeader, 0x01),
+            (PacketType::PtData, 0x02),
+            (PacketType::PtDataRsp, 0xfe),
+            (PacketType::PtResult, 0xff),
+        ];
+        for (packet, v) in packets {
+            assert_eq!(packet as u8, v);
+            assert_eq!(PacketType::from(v), packet);
+        }
+    }
+
+    #[test]
+    fn test_entry_and_stream_type_values() {
+        assert_eq!(u32::from(EntryType::NotFound), u32::MAX);
+        assert_eq!(u32::from(EntryType::Bookmark), 0xb0);
+        assert_eq!(u32::from(EntryType::Event1), 1);
+        assert_eq!(u32::from(EntryType::Event2), 2);
+        assert_eq!(EntryType::from(0xb0), EntryType::Bookmark);
+
+        assert_eq!(u64::from(StreamType::Sequencer), 1);
+        assert_eq!(StreamType::Sequencer.to_be_bytes(), 1u64.to_be_bytes());
+        assert_eq!(StreamType::from(2).to_be_bytes(), 2u64.to_be_bytes());
+    }
+
+    #[test]
+    fn test_catch_all_types_equal_named() {
+        use std::collections::HashSet;
+
+        assert_eq!(EntryType::Event(1), EntryType::Event1);
+        assert_eq!(EntryType::Event(2), EntryType::Event2);
+        assert_eq!(EntryType::Event(0xb0), EntryType::Bookmark);
+        assert_eq!(EntryType::Event(u32::MAX), EntryType::NotFound);
+        assert_ne!(EntryType::Event(3), EntryType::Event1);
+        let types = HashSet::from([EntryType::Event1, EntryType::Event(3)]);
+        assert!(types.contains(&EntryType::Event(1)));
+        assert!(!types.contains(&EntryType::Event2));
+
+        assert_eq!(StreamType::Other(1), StreamType::Sequencer);
+        assert_ne!(StreamType::Other(2), StreamType::Sequencer);
+    }
+
+    #[test]
+    fn test_command_frame() {
+        let frame = CommandFrame::new(Command::CmdStartBookmark, StreamType::Sequencer)
+            .bookmark(&[0xb0, 0x01, 0x02]);
+        assert_eq!(
+            frame.as_bytes(),
+            [
+                0, 0, 0, 0, 0, 0, 0, 4, // CmdStartBookmark
+                0, 0, 0, 0, 0, 0, 0, 1, // Sequencer stream type
+                0, 0, 0, 3, // Bookmark length
+                0xb0, 0x01, 0x02, // Bookmark
+            ]
+        );
+
+        let frame = CommandFrame::new(Command::CmdEntry, StreamType::Other(2)).from_entry(258);
+        assert_eq!(
+            frame.as_bytes(),
+            [
+                0, 0, 0, 0, 0, 0, 0, 5, // CmdEntry
+                0, 0, 0, 0, 0, 0, 0, 2, // Stream type 2
+                0, 0, 0, 0, 0, 0, 1, 2, // Entry 258
+            ]
+        );
+    }
+}
@@ -1,133 +1,449 @@
+use crate::protocol::CommandFrame;
+pub use crate::protocol::{
+    ChainId, Command, CommandError, EntryType, PacketType, StreamType, ENTRY_TYPE_NOT_FOUND,
+};
+use crate::stream_file::{validate_page_size, FileStreamWriter, PAGE_DATA_SIZE};
 use byteorder::{BigEndian, ByteOrder};
+use futures::Stream;
+use std::collections::{HashMap, VecDeque};
 use std::convert::From;
-use std::io::{self, ErrorKind};
-use std::io::{Read, Write};
-use std::net::TcpStream;
-use std::thread;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
 use std::time::Duration;
 use thiserror::Error;
-use tracing::{debug, error, info};
+use tokio::io::{
+    AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, AsyncWrite, AsyncWriteExt,
+    BufReader, ReadBuf,
+};
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::{sleep, timeout, Instant};
+#[cfg(feature = "tls")]
+use tokio_rustls::{
+    rustls::{self, pki_types::ServerName},
+    TlsConnector,
+};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, debug_span, error, info, instrument, warn, Instrument};
 
-const ENTRY_RSP_BUFFER: usize = 32;
-const HEADER_SIZE: usize = 38;
-const FIXED_SIZE_FILE_ENTRY: usize = 17;
-const FIXED_SIZE_RESULT_ENTRY: usize = 9;
+pub(crate) const HEADER_SIZE: usize = 38;
+pub(crate) const FIXED_SIZE_FILE_ENTRY: usize = 17;
+pub(crate) const FIXED_SIZE_RESULT_ENTRY: usize = 9;
+const DEFAULT_RECONNECT_DELAY: Duration = Duration::from_secs(5);
+const DEFAULT_MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+const DEFAULT_RECONNECT_JITTER: f64 = 0.2;
+const DEFAULT_COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_HEADER_CACHE_TTL: Duration = Duration::from_secs(2);
+const DEFAULT_TEE_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+const STREAM_BUFFER: usize = 256; // Buffers for the stream channel
+const DEFAULT_READ_BUFFER_SIZE: usize = 4096; // Initial capacity of the entries read buffer
+const DEFAULT_READ_AHEAD_SIZE: usize = 64 << 10; // Bytes read ahead from the socket, 64 KiB
+const DEFAULT_MAX_ENTRY_SIZE: usize = 64 << 20; // Maximum size of a data entry, 64 MiB
+const CHECKSUM_SIZE: usize = 4; // Size of the CRC32 trailing the event entries data
+const MAX_ERROR_STR_SIZE: usize = 64 << 10; // Maximum size of a result error string, 64 KiB
+#[cfg(unix)]
+pub const UNIX_SOCKET_PREFIX: &str = "unix:"; // Prefix of the Unix domain socket server addresses
+pub const MAX_BOOKMARK_LENGTH: usize = 16; // Maximum number of bytes for a bookmark
+pub const SUPPORTED_VERSIONS: &[u8] = &[1, 2, 3]; // Stream versions understood by default
 
-// Entry type for a data file entry
-#[derive(Debug, Default)]
+// Metric names recorded with the metrics feature
+#[cfg(feature = "metrics")]
+pub const METRIC_ENTRIES: &str = "datastreamer_entries_total"; // Counter of data entries read
+#[cfg(feature = "metrics")]
+pub const METRIC_BYTES: &str = "datastreamer_bytes_total"; // Counter of data entry bytes read
+#[cfg(feature = "metrics")]
+pub const METRIC_RECONNECTS: &str = "datastreamer_reconnects_total"; // Counter of reconnections
+#[cfg(feature = "metrics")]
+pub const METRIC_LAST_ENTRY: &str = "datastreamer_last_entry_number"; // Gauge of the last entry number
+#[cfg(feature = "metrics")]
+pub const METRIC_CLIENT_STATUS: &str = "datastreamer_client_status"; // Gauge 0:disconnected, 1:connected, 2:streaming
+#[cfg(feature = "metrics")]
+pub const METRIC_SOCKET_READS: &str = "datastreamer_socket_reads_total"; // Counter of reads from the connection
+
+// Entry type for a data file entry, its Debug output shows a summary of the data, see full_debug
+#[derive(Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Entry {
     pub packet_type: u8,       // 2:Data entry, 0:Padding
     pub length: u32,           // Total length of the entry (17 bytes + length(data))
     pub entry_type: EntryType, // 0xb0:Bookmark, 1:Event1, 2:Event2,...
     pub number: u64,           // Entry number (sequential starting with 0)
+    #[cfg_attr(feature = "serde", serde(with = "serde_data"))]
     pub data: Vec<u8>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub raw: Option<Vec<u8>>, // Framed bytes the entry was decoded from, only with capture_raw
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub received_at: Option<std::time::Instant>, // Time the entry was read, only with timestamp_entries
+}
+
+// EntryHeader type for the fixed size fields of a data entry, returned by next_entry_into
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct EntryHeader {
+    pub packet_type: u8,
+    pub length: u32, // Total length of the entry (17 bytes + length(data))
+    pub entry_type: EntryType,
+    pub number: u64,
 }
 
 // HeaderEntry type for a header entry
-#[derive(Debug, Default)]
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct HeaderEntry {
     pub packet_type: u8,         // 1:Header
     pub head_length: u32,        // Total length of header entry (38)
     pub version: u8,             // Stream file version
-    pub system_id: u64,          // System identifier (e.g. ChainID)
+    pub system_id: ChainId,      // System identifier (e.g. ChainID)
     pub stream_type: StreamType, // 1:Sequencer
     pub total_length: u64,       // Total bytes used in the file
     pub total_entries: u64,      // Total number of data entries (packet type PtData)
 }
 
 // ResultEntry type for a result entry
-#[derive(Debug, Default)]
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ResultEntry {
     pub packet_type: u8, // 0xff:Result
     pub length: u32,
     pub error_num: u32, // 0:No error
+    #[cfg_attr(feature = "serde", serde(with = "serde_data"))]
     pub error_str: Vec<u8>,
 }
 
-// EntryType enum represents the entry event types
-#[derive(Debug, Clone, Copy, Default, PartialEq)]
-pub enum EntryType {
-    #[default]
-    NotFound = 0, // EntryTypeNotFound for entry not found
-    Bookmark = 0xb0, // EntryTypeBookmark for bookmark entry
-    Event1 = 1,      // EntryTypeEvent1 for event type 1
-    Event2 = 2,      // EntryTypeEvent2 for event type 2
+// serde_data serializes byte fields as base64 strings for human readable formats (e.g. JSON)
+// and as raw bytes for binary formats
+#[cfg(feature = "serde")]
+mod serde_data {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&STANDARD.encode(data))
+        } else {
+            serializer.serialize_bytes(data)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        if deserializer.is_human_readable() {
+            let s = String::deserialize(deserializer)?;
+            STANDARD.decode(s).map_err(serde::de::Error::custom)
+        } else {
+            serde_bytes::deserialize(deserializer)
+        }
+    }
+}
+
+// Bytes shown at each end of the data in the Debug output of an entry
+const DEBUG_DATA_BYTES: usize = 8;
+
+impl std::fmt::Debug for Entry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.fmt_fields(f, false)
+    }
 }
 
-impl From<u32> for EntryType {
-    fn from(v: u32) -> Self {
-        match v {
-            0 => EntryType::NotFound,
-            0xb0 => EntryType::Bookmark,
-            1 => EntryType::Event1,
-            2 => EntryType::Event2,
-            _ => EntryType::NotFound,
+// FullDebug formats an entry with the complete data and raw bytes
+struct FullDebug<'a>(&'a Entry);
+
+impl std::fmt::Debug for FullDebug<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt_fields(f, true)
+    }
+}
+
+// DataSummary formats the length and the hex of the first and last bytes of an entry data
+struct DataSummary<'a>(&'a [u8]);
+
+impl std::fmt::Debug for DataSummary<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let data = self.0;
+        write!(f, "<{} bytes: ", data.len())?;
+        match data.len() > 2 * DEBUG_DATA_BYTES {
+            true => {
+                write_hex(f, &data[..DEBUG_DATA_BYTES])?;
+                f.write_str("..")?;
+                write_hex(f, &data[data.len() - DEBUG_DATA_BYTES..])?;
+            }
+            false => write_hex(f, data)?,
         }
+        f.write_str(">")
     }
 }
 
-#[derive(Debug, Clone, Copy)]
-pub enum Command {
-    CmdStart = 1,     // CmdStart for the start from entry TCP client command
-    CmdStop,          // CmdStop for the stop TCP client command
-    CmdHeader,        // CmdHeader for the header TCP client command
-    CmdStartBookmark, // CmdStartBookmark for the start from bookmark TCP client command
-    CmdEntry,         // CmdEntry for the get entry TCP client command
-    CmdBookmark,      // CmdBookmark for the get bookmark TCP client command
+// write_hex writes the bytes as lowercase hex
+fn write_hex(f: &mut std::fmt::Formatter<'_>, b: &[u8]) -> std::fmt::Result {
+    b.iter().try_for_each(|b| write!(f, "{:02x}", b))
 }
 
-#[derive(Debug, Clone, Copy)]
-pub enum CommandError {
-    CmdErrOK = 0,             // CmdErrOK for no error
-    CmdErrAlreadyStarted,     // CmdErrAlreadyStarted for client already started error
-    CmdErrAlreadyStopped,     // CmdErrAlreadyStopped for client already stopped error
-    CmdErrBadFromEntry,       // CmdErrBadFromEntry for invalid starting entry number
-    CmdErrBadFromBookmark,    // CmdErrBadFromBookmark for invalid starting bookmark
-    CmdErrInvalidCommand = 9, // CmdErrInvalidCommand for invalid/unknown command error
+impl Entry {
+    // full_debug returns the Debug output of the entry with the complete data, which the Debug
+    // impl summarizes
+    pub fn full_debug(&self) -> String {
+        format!("{:?}", FullDebug(self))
+    }
+
+    // fmt_fields formats the entry fields, summarizing the data and raw bytes unless full
+    fn fmt_fields(&self, f: &mut std::fmt::Formatter<'_>, full: bool) -> std::fmt::Result {
+        let mut d = f.debug_struct("Entry");
+        d.field("packet_type", &self.packet_type)
+            .field("length", &self.length)
+            .field("entry_type", &self.entry_type)
+            .field("number", &self.number);
+        match full {
+            true => d.field("data", &self.data).field("raw", &self.raw),
+            false => d
+                .field("data", &DataSummary(&self.data))
+                .field("raw", &self.raw.as_deref().map(DataSummary)),
+        };
+        d.field("received_at", &self.received_at).finish()
+    }
+
+    // raw_bytes returns the framed bytes received for the entry, empty unless the client was
+    // built with capture_raw
+    pub fn raw_bytes(&self) -> &[u8] {
+        self.raw.as_deref().unwrap_or_default()
+    }
+
+    // encode_to_binary encodes a data entry to the binary format expected by decode_binary_to_entry
+    pub fn encode_to_binary(&self) -> Vec<u8> {
+        let length = (FIXED_SIZE_FILE_ENTRY + self.data.len()) as u32;
+
+        let mut b = Vec::with_capacity(length as usize);
+        b.push(self.packet_type);
+        b.extend_from_slice(&length.to_be_bytes());
+        b.extend_from_slice(&u32::from(self.entry_type).to_be_bytes());
+        b.extend_from_slice(&self.number.to_be_bytes());
+        b.extend_from_slice(&self.data);
+        b
+    }
 }
 
-// StreamType enum represents the stream types
-#[derive(Debug, Clone, Copy, Default, PartialEq)]
-pub enum StreamType {
-    #[default]
-    Sequencer = 1, // Sequencer for sequencer stream type
+impl HeaderEntry {
+    // encode_to_binary encodes a header entry to the binary format expected by decode_binary_to_header_entry
+    pub fn encode_to_binary(&self) -> Vec<u8> {
+        let mut b = Vec::with_capacity(HEADER_SIZE);
+        b.push(self.packet_type);
+        b.extend_from_slice(&self.head_length.to_be_bytes());
+        b.push(self.version);
+        b.extend_from_slice(&u64::from(self.system_id).to_be_bytes());
+        b.extend_from_slice(&u64::from(self.stream_type).to_be_bytes());
+        b.extend_from_slice(&self.total_length.to_be_bytes());
+        b.extend_from_slice(&self.total_entries.to_be_bytes());
+        b
+    }
 }
 
-impl From<u64> for StreamType {
-    fn from(v: u64) -> Self {
-        match v {
-            1 => StreamType::Sequencer,
-            _ => StreamType::Sequencer,
+impl ResultEntry {
+    // encode_to_binary encodes a result entry to the binary format expected by decode_binary_to_result_entry
+    pub fn encode_to_binary(&self) -> Vec<u8> {
+        let length = (FIXED_SIZE_RESULT_ENTRY + self.error_str.len()) as u32;
+
+        let mut b = Vec::with_capacity(length as usize);
+        b.push(self.packet_type);
+        b.extend_from_slice(&length.to_be_bytes());
+        b.extend_from_slice(&self.error_num.to_be_bytes());
+        b.extend_from_slice(&self.error_str);
+        b
+    }
+
+    // describe returns the command error followed by the error string sent by the server, e.g.
+    // "CmdErrBadFromEntry: entry 99999 out of range", or by the error description if it's empty
+    pub fn describe(&self) -> String {
+        let error = CommandError::from(self.error_num);
+        match self.error_str.is_empty() {
+            true => format!("{:?}: {}", error, error),
+            false => format!("{:?}: {}", error, String::from_utf8_lossy(&self.error_str)),
         }
     }
 }
 
-// PacketType enum represents the packet types
-#[derive(Debug, Clone, Copy, PartialEq)]
-#[repr(u8)]
-pub enum PacketType {
-    PtPadding = 0u8,  // PtPadding is packet type for pad
-    PtHeader = 0x01,  // PtHeader is packet type just for the header page
-    PtData = 0x02,    // PtData is packet type for data entry
-    PtDataRsp = 0xfe, // PtDataRsp is packet type for command response with data
-    PtResult = 0xff, // PtResult is packet type not stored/present in file (just for client command result)
+// Bookmark type for a bookmark entry data (bookmark type byte followed by the value)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Bookmark {
+    pub bookmark_type: u8,
+    pub value: Vec<u8>,
 }
 
-impl From<u8> for PacketType {
-    fn from(v: u8) -> Self {
-        match v {
-            0x00 => PacketType::PtPadding,
-            0x01 => PacketType::PtHeader,
-            0x02 => PacketType::PtData,
-            0xfe => PacketType::PtDataRsp,
-            0xff => PacketType::PtResult,
-            _ => PacketType::PtPadding,
+impl Bookmark {
+    pub fn new(bookmark_type: u8, value: Vec<u8>) -> Self {
+        Bookmark {
+            bookmark_type,
+            value,
+        }
+    }
+
+    // encode encodes the bookmark to the bytes sent to the server
+    pub fn encode(&self) -> Vec<u8> {
+        let mut b = Vec::with_capacity(1 + self.value.len());
+        b.push(self.bookmark_type);
+        b.extend_from_slice(&self.value);
+        b
+    }
+
+    // decode decodes a bookmark from its binary bytes
+    pub fn decode(b: &[u8]) -> Result<Self, ClientError> {
+        match b.split_first() {
+            Some((bookmark_type, value)) => Ok(Bookmark::new(*bookmark_type, value.to_vec())),
+            None => Err(ClientError::InvalidBookmark("empty bookmark".to_string())),
         }
     }
 }
 
+// A raw Vec<u8> is taken as an already encoded bookmark, failing if it's empty
+impl TryFrom<Vec<u8>> for Bookmark {
+    type Error = ClientError;
+
+    fn try_from(b: Vec<u8>) -> Result<Self, Self::Error> {
+        Bookmark::decode(&b)
+    }
+}
+
+impl From<Bookmark> for Vec<u8> {
+    fn from(b: Bookmark) -> Self {
+        b.encode()
+    }
+}
+
+// ClientStatus enum represents the streaming status of a client
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ClientStatus {
+    CsSyncing = 1, // CsSyncing while catching up to the total entries of the latest header
+    CsSynced,      // CsSynced once the last entry of the latest header is received
+    #[default]
+    CsStopped, // CsStopped when not streaming
+    CsKilled = 0xff, // CsKilled for a client killed by the server
+}
+
+// ClientState type for the connection flags of a client, reported to the event listener when
+// any of them changes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct ClientState {
+    pub connected: bool, // Connected to server
+    pub started: bool,   // Started reading the streamed entries
+    pub streaming: bool, // Streaming started on the server
+}
+
+// Compression enum represents the compression of the event entries data
+#[cfg(feature = "compression")]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Compression {
+    #[default]
+    None, // None for uncompressed data
+    Gzip, // Gzip for gzip compressed data
+    Zlib, // Zlib for zlib compressed data
+}
+
+// StartMode enum represents how the streaming was started, to be restored the same way on reconnect
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StartMode {
+    #[default]
+    Entry, // Start from the resume entry number (CmdStart)
+    Bookmark(Vec<u8>), // Start from the encoded bookmark (CmdStartBookmark) until an entry is delivered
+}
+
+// HookErrorPolicy enum for the action the client takes when the process entry hook fails
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum HookErrorPolicy {
+    Ignore, // The error is logged, continue streaming with the next entry
+    #[default]
+    Stop, // Stop the streaming, start returns the hook error
+    Retry(u32), // Call the hook again with the entry up to n times, then stop as with Stop
+}
+
+// Checkpoint type for the streaming progress of a client, an opaque token returned by checkpoint
+// to be persisted and resume the streaming where it was left with StreamClientBuilder::resume_from
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Checkpoint {
+    last_delivered: Option<u64>, // Number of the highest entry delivered
+    resume_from: u64,            // Entry number to start the streaming from
+    start_mode: StartMode, // How to start the streaming, a bookmark until an entry is delivered
+}
+
+impl Checkpoint {
+    // resume_from returns the entry number the streaming is resumed from
+    pub fn resume_from(&self) -> u64 {
+        self.resume_from
+    }
+}
+
+// HookAction enum for the action the client takes after the process entry hook returns
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum HookAction {
+    #[default]
+    Continue, // The entry was processed, continue streaming
+    Stop, // Stop the streaming with CmdStop and return from start
+    Skip, // The entry was ignored by the hook, continue streaming
+}
+
+// Snapshot type for the entries of the stream read by snapshot_with_limit
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Snapshot {
+    pub entries: Vec<Entry>, // Entries from the first one, in order
+    pub partial: bool,       // Flag entries cut at max_entries, the stream has more
+}
+
+// ValidationReport type for the entries read by validate_stream
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ValidationReport {
+    pub entries: u64,       // Data entries read, including the ones failing to decode
+    pub bytes: u64,         // Total length of the data entries read
+    pub decode_errors: u64, // Data entries failing to decode
+    pub entry_types: HashMap<EntryType, u64>, // Data entries read by entry type
+}
+
+// ServerCapabilities type for the read-only commands accepted by a server
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ServerCapabilities {
+    pub header: bool,   // CmdHeader accepted
+    pub entry: bool,    // CmdEntry accepted
+    pub bookmark: bool, // CmdBookmark accepted
+}
+
 // Type of the callback function to process the received entry
-type ProcessEntryFunc = fn(Entry) -> Result<(), ClientError>;
+pub type ProcessEntryFunc = Box<dyn FnMut(Entry) -> Result<HookAction, ClientError> + Send>;
+
+// Type of the future returned by the async callback function processing the received entry
+pub type ProcessEntryFuture = Pin<Box<dyn Future<Output = Result<HookAction, ClientError>> + Send>>;
+
+// Type of the async callback function to process the received entry, awaited before the next
+// entry is read
+pub type AsyncProcessEntryFunc = Box<dyn FnMut(Entry) -> ProcessEntryFuture + Send>;
+
+// Type of the async callback function to process the received entries concurrently, called by
+// the worker tasks
+pub type ConcurrentProcessEntryFunc = Arc<dyn Fn(Entry) -> ProcessEntryFuture + Send + Sync>;
+
+// EventListener trait for callbacks on the client lifecycle events, for users not collecting the
+// tracing logs. Every method does nothing by default.
+pub trait EventListener: Send {
+    // on_connected is called once connected to server, with the client id
+    fn on_connected(&self, _client_id: &str, _server: &str) {}
+    // on_disconnected is called when the connection to the server is closed
+    fn on_disconnected(&self, _client_id: &str) {}
+    // on_entry is called for every streamed entry before it's processed
+    fn on_entry(&self, _entry: &Entry) {}
+    // on_error is called on connection, read and entry processing errors
+    fn on_error(&self, _error: &ClientError) {}
+    // on_state_change is called when the connected, started or streaming flags change
+    fn on_state_change(&self, _client_id: &str, _state: ClientState) {}
+    // on_synced is called when the client catches up to the total entries of the latest header,
+    // with the number of the entry it synced at. The streaming goes on tailing the new entries.
+    fn on_synced(&self, _client_id: &str, _number: u64) {}
+    // on_gap is called with verify_sequence when a streamed entry isn't numbered next to the
+    // previous one, with the expected and the received entry numbers
+    fn on_gap(&self, _client_id: &str, _expected: u64, _actual: u64) {}
+}
+
+// Type of the function to select the streamed entries to process, it's called with the entry
+// fields but without the data, which is only decoded for the selected entries
+pub type EntryFilterFunc = Box<dyn Fn(&Entry) -> bool + Send>;
 
 // ClientError enum represents the client errors
 #[derive(Debug, Error)]
@@ -136,503 +452,8420 @@ pub enum ClientError {
     ClientNotStarted(&'static str),
     #[error("Error executing command: {0}")]
     InvalidCommand(&'static str),
-    #[error("Error network")]
-    NetworkError(std::io::Error),
+    #[error("Command failed: {1}")]
+    CommandFailed(CommandError, String), // Error returned by the server and its description
+    #[error("network error: {0}")]
+    NetworkError(#[from] std::io::Error),
     #[error("Errors entry not found")]
     EntryNotFound,
     #[error("Error bookmark not found")]
     BookmarkNotFound,
+    #[error("Invalid server address: {0}")]
+    InvalidServerAddress(String),
+    #[error("Error decoding: {0}")]
+    DecodeError(String),
+    #[error("Error decoding: {0}")]
+    Decode(#[from] crate::entry_data::DecodeError),
+    #[error("Invalid bookmark: {0}")]
+    InvalidBookmark(String),
+    #[error("Timeout waiting for the command response")]
+    Timeout,
+    #[error("Command cancelled")]
+    Cancelled,
+    #[error("Error connecting to server {0} after {1} attempts")]
+    ConnectionFailed(String, u32),
+    #[error("Unsupported stream version: {0}")]
+    UnsupportedVersion(u8),
+    #[error("Invalid configuration: {0}")]
+    InvalidConfig(String),
+    #[error("Error starting the streaming, {0}: {1}")]
+    StartFailed(String, #[source] Box<ClientError>), // Step of the start that failed and its error
+    #[cfg(feature = "tls")]
+    #[error("TLS configuration error: {0}")]
+    TlsError(String),
 }
 
-#[derive(Debug)]
-// StreamClient type to manage a data stream client
-pub struct StreamClient {
-    server: String, // Server address to connect IP:port
-    stream_type: StreamType,
-    conn: Option<TcpStream>,
-    id: String,         // Client id
-    started: bool,      // Flag client started
-    connected: bool,    // Flag client connected to server
-    streaming: bool,    // Flag client streaming started
-    from_stream: u64,   // Start entry number from latest start command
-    total_entries: u64, // Total entries from latest header command
+// Backoff type for the delays between reconnection attempts, doubling from base_delay up to max_delay
+#[derive(Debug, Clone, PartialEq)]
+struct Backoff {
+    base_delay: Duration,
+    max_delay: Duration,
+    max_retries: Option<u32>, // Retries before giving up, None to retry forever
+    jitter: f64,              // Fraction of the delay randomly subtracted (0.0 to 1.0)
+}
 
-    pub process_entry_hook: ProcessEntryFunc, // Callback function to process the entry
+// RateLimiter type for a token bucket pacing the delivered entries, refilled at rate tokens per
+// second up to a single token so the entries are evenly spaced
+#[derive(Debug, Clone, PartialEq)]
+struct RateLimiter {
+    rate: f64,     // Entries per second
+    tokens: f64,   // Tokens available, one per entry
+    last: Instant, // Time of the latest refill
 }
 
-impl StreamClient {
-    pub fn new(server: String) -> Result<StreamClient, Box<dyn std::error::Error>> {
-        let client = StreamClient {
-            server: server.clone(),
-            stream_type: StreamType::Sequencer,
-            conn: None,
-            id: String::new(),
-            started: false,
-            connected: false,
-            streaming: false,
-            from_stream: 0,
-            total_entries: 0,
+impl RateLimiter {
+    fn new(entries_per_sec: u32) -> RateLimiter {
+        RateLimiter {
+            rate: entries_per_sec as f64,
+            tokens: 1.0,
+            last: Instant::now(),
+        }
+    }
 
-            process_entry_hook: print_received_entry,
-        };
+    // acquire takes a token, waiting for the bucket to refill if it's empty
+    async fn acquire(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(1.0);
+        self.last = now;
 
-        Ok(client)
+        if self.tokens < 1.0 {
+            let wait = Duration::from_secs_f64((1.0 - self.tokens) / self.rate);
+            sleep(wait).await;
+            self.tokens = 1.0;
+            self.last = Instant::now();
+        }
+        self.tokens -= 1.0;
     }
+}
 
-    // Start connects to the data stream server and starts getting data from the server
-    pub async fn start(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        // Connect to server
-        self.connect_server()?;
+// TeeWriter trait for the writers the streamed entries are teed to
+trait TeeWriter: AsyncWrite + AsyncSeek + Unpin + Send {}
 
-        let header = self.exec_command_get_header()?;
-        self.total_entries = header.total_entries;
+impl<W: AsyncWrite + AsyncSeek + Unpin + Send> TeeWriter for W {}
 
-        _ = self.exec_command_start(0)?;
-        self.started = true;
-        loop {
-            self.read_entries().await;
+// TeeBuffer type for the writes and seeks of the tee FileStreamWriter, pending to be applied to
+// the async tee writer
+#[derive(Default)]
+struct TeeBuffer {
+    ops: Vec<(Option<u64>, Vec<u8>)>, // Bytes to write, after seeking to the position if any
+    pos: u64,                         // Position after the pending operations
+}
+
+impl std::io::Write for TeeBuffer {
+    fn write(&mut self, b: &[u8]) -> std::io::Result<usize> {
+        match self.ops.last_mut() {
+            Some((_, bytes)) => bytes.extend_from_slice(b),
+            None => self.ops.push((None, b.to_vec())),
         }
+        self.pos += b.len() as u64;
+        Ok(b.len())
     }
 
-    // connect_server waits until the server connection is established and returns if a command result is pending
-    pub fn connect_server(&mut self) -> Result<bool, Box<dyn std::error::Error>> {
-        // Connect to server
-        while !self.connected {
-            match TcpStream::connect(&self.server) {
-                Ok(conn) => {
-                    // Connected
-                    self.conn = Some(conn);
-                    self.connected = true;
-                    self.id = self.conn.as_ref().unwrap().local_addr()?.to_string();
-                    info!("{} Connected to server: {}", self.id, self.server);
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
 
-                    // Restore streaming
-                    if self.streaming {
-                        match self.exec_command(Command::CmdStart, 0, None) {
-                            Ok(_) => {}
-                            Err(e) => {
-                                self.close_connection();
-                                thread::sleep(Duration::from_secs(5));
-                                info!("Error restoring streaming: {:?}", e);
-                                self.streaming = false;
-                                continue;
-                            }
-                        }
-                        return Ok(true);
-                    } else {
-                        return Ok(false);
-                    }
-                }
-                Err(e) => {
-                    error!("Error connecting to server {}: {}", self.server, e);
-                    thread::sleep(Duration::from_secs(5));
-                    continue;
-                }
-            }
-        }
-        Ok(false)
+impl std::io::Seek for TeeBuffer {
+    fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+        self.pos = match pos {
+            std::io::SeekFrom::Start(n) => n,
+            std::io::SeekFrom::Current(n) => self.pos.checked_add_signed(n).ok_or_else(|| {
+                std::io::Error::new(std::io::ErrorKind::InvalidInput, "invalid seek position")
+            })?,
+            std::io::SeekFrom::End(_) => return Err(std::io::ErrorKind::Unsupported.into()),
+        };
+        self.ops.push((Some(self.pos), Vec::new()));
+        Ok(self.pos)
     }
+}
 
-    // read_result_entry reads bytes from server connection and returns a result entry type
-    fn read_result_entry(&mut self) -> Result<ResultEntry, std::io::Error> {
-        let mut conn = self.conn.as_ref().unwrap();
+// StreamTee type for the datastream file the streamed entries are written to. The file is laid
+// out by a FileStreamWriter, whose writes are then applied to the async writer. The header is
+// only updated on flush.
+struct StreamTee {
+    file: FileStreamWriter<TeeBuffer>,
+    writer: Box<dyn TeeWriter>,
+    flush_interval: Duration, // Time between flushes of the written entries
+    flushed_at: Instant,      // Time of the latest flush
+}
 
-        // Read the rest of fixed size fields
-        let mut buffer = vec![0; FIXED_SIZE_RESULT_ENTRY];
-        conn.read_exact(&mut buffer)?;
+impl StreamTee {
+    // new writes the header page of an empty datastream and positions the writer at the first
+    // data page
+    async fn new(
+        writer: Box<dyn TeeWriter>,
+        header: HeaderEntry,
+        page_size: u64,
+        flush_interval: Duration,
+    ) -> std::io::Result<StreamTee> {
+        let file = FileStreamWriter::from_header(TeeBuffer::default(), header)?
+            .with_page_size(page_size)?;
+        let mut tee = StreamTee {
+            file,
+            writer,
+            flush_interval,
+            flushed_at: Instant::now(),
+        };
+        tee.apply_writes().await?;
+        Ok(tee)
+    }
 
-        // TODO: This is not necessary in our impl because we've not read the packet yet so it's there
-        // let packet = vec![PacketType::PtResult as u8];
-        // buffer = [packet, buffer].concat();
+    // write_entry appends the framed bytes of a data entry, padding the current data page if the
+    // entry doesn't fit in it, and flushes once the flush interval elapsed
+    async fn write_entry(&mut self, b: &[u8]) -> std::io::Result<()> {
+        self.file.append_encoded(b)?;
+        self.apply_writes().await?;
 
-        // Read variable field (errStr)
-        let length = BigEndian::read_u32(&buffer[1..5]);
-        if length < FIXED_SIZE_RESULT_ENTRY as u32 {
-            return Err(std::io::Error::new(
-                ErrorKind::Other,
-                "Error reading result entry",
-            ));
+        if self.flushed_at.elapsed() >= self.flush_interval {
+            self.flush().await?;
         }
+        Ok(())
+    }
 
-        let mut buffer_aux = vec![0; (length - FIXED_SIZE_RESULT_ENTRY as u32) as usize];
-        conn.read_exact(&mut buffer_aux)?;
-
-        buffer = [buffer, buffer_aux].concat();
-
-        // Decode binary entry result
-        // Assuming DecodeBinaryToResultEntry is defined somewhere
-        let e = decode_binary_to_result_entry(&buffer);
-
-        Ok(e)
+    // flush writes the updated header and flushes the written entries
+    async fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()?;
+        self.apply_writes().await?;
+        self.writer.flush().await?;
+        self.flushed_at = Instant::now();
+        Ok(())
     }
 
-    // read_header_entry reads bytes from server connection and returns a header entry type
-    fn read_header_entry(&mut self) -> Result<HeaderEntry, std::io::Error> {
-        let mut conn = self.conn.as_ref().unwrap();
+    // apply_writes applies the pending writes and seeks of the file to the writer
+    async fn apply_writes(&mut self) -> std::io::Result<()> {
+        let ops = std::mem::take(&mut self.file.get_mut().ops);
+        for (pos, bytes) in ops {
+            if let Some(pos) = pos {
+                self.writer.seek(std::io::SeekFrom::Start(pos)).await?;
+            }
+            self.writer.write_all(&bytes).await?;
+        }
+        Ok(())
+    }
+}
 
-        // Read the rest of fixed size fields
-        let mut buffer = vec![0; HEADER_SIZE];
-        conn.read_exact(&mut buffer)?;
+// WorkerPool type for the tasks calling the concurrent process entry hook. The hook results are
+// kept in a reorder buffer until the entries dispatched before them are done, so they're applied
+// in the streamed order.
+struct WorkerPool {
+    jobs: mpsc::Sender<(u64, Entry)>, // Entries to process by dispatch sequence, shared by the workers
+    results: mpsc::UnboundedReceiver<(u64, Result<HookAction, ClientError>)>, // Hook results by dispatch sequence
+    in_flight: VecDeque<(u64, u64)>, // Dispatch sequence and number of the entries not applied, in order
+    done: HashMap<u64, Result<HookAction, ClientError>>, // Results received before the previous ones
+    next_seq: u64,                                       // Dispatch sequence of the next entry
+    workers: Vec<tokio::task::JoinHandle<()>>,
+}
 
-        // Decode binary header entry
-        let h = decode_binary_to_header_entry(&buffer)?;
+impl WorkerPool {
+    // new spawns count workers calling the hook, retrying a failed entry up to retries times
+    fn new(id: &str, count: usize, hook: ConcurrentProcessEntryFunc, retries: u32) -> WorkerPool {
+        let (jobs, rx) = mpsc::channel::<(u64, Entry)>(count);
+        let rx = Arc::new(tokio::sync::Mutex::new(rx));
+        let (tx, results) = mpsc::unbounded_channel();
+        let workers = (0..count)
+            .map(|_| {
+                let (id, rx, tx, hook) = (id.to_string(), rx.clone(), tx.clone(), hook.clone());
+                tokio::spawn(async move {
+                    loop {
+                        // The receiver is unlocked before calling the hook so the other workers
+                        // take the next entries
+                        let job = rx.lock().await.recv().await;
+                        let Some((seq, mut e)) = job else {
+                            return;
+                        };
+                        let number = e.number;
+                        let span = debug_span!("entry", client_id = %id, entry_number = number);
+                        let mut attempt = 0;
+                        let res = loop {
+                            let retry = attempt < retries;
+                            let entry = match retry {
+                                true => e.clone(),
+                                false => std::mem::take(&mut e),
+                            };
+                            match hook(entry).instrument(span.clone()).await {
+                                Err(e) if retry => {
+                                    attempt += 1;
+                                    error!(
+                                        "{} Error processing entry {} (attempt {}), retrying: {}",
+                                        id, number, attempt, e
+                                    );
+                                }
+                                res => break res,
+                            }
+                        };
+                        if tx.send((seq, res)).is_err() {
+                            return;
+                        }
+                    }
+                })
+            })
+            .collect();
 
-        Ok(h)
+        WorkerPool {
+            jobs,
+            results,
+            in_flight: VecDeque::new(),
+            done: HashMap::new(),
+            next_seq: 0,
+            workers,
+        }
     }
+}
 
-    // read_bookmark_entry
-    fn read_bookmark_entry(&mut self) -> Result<Entry, std::io::Error> {
-        // Get the command result
-        let mut packet = [0u8; 1];
-        self.conn
-            .as_ref()
-            .unwrap()
-            .read_exact(&mut packet)
-            .expect("Error reading packet");
-
-        self.read_data_entry()
+impl Drop for WorkerPool {
+    fn drop(&mut self) {
+        for worker in &self.workers {
+            worker.abort();
+        }
     }
+}
 
-    // read_data_entry reads bytes from server connection and returns a data entry type
-    fn read_data_entry(&mut self) -> Result<Entry, std::io::Error> {
-        let mut conn = self.conn.as_ref().unwrap();
+// Transport type for the server address, a TCP host:port or, on Unix platforms, a Unix domain
+// socket path prefixed by unix: (e.g. unix:/var/run/datastream.sock) for co-located processes
+#[derive(Debug, Clone, PartialEq)]
+pub enum Transport {
+    Tcp(String),
+    #[cfg(unix)]
+    Unix(std::path::PathBuf),
+}
 
-        // Read the rest of fixed size fields
-        let mut buffer = vec![0; FIXED_SIZE_FILE_ENTRY - 1];
-        conn.read_exact(&mut buffer)?;
+impl Transport {
+    // parse parses a server address, checking a TCP address is an IP:port or a host:port pair
+    pub fn parse(server: &str) -> Result<Transport, ClientError> {
+        #[cfg(unix)]
+        if let Some(path) = server.strip_prefix(UNIX_SOCKET_PREFIX) {
+            return match path.is_empty() {
+                true => Err(ClientError::InvalidServerAddress(server.to_string())),
+                false => Ok(Transport::Unix(path.into())),
+            };
+        }
 
-        let packet = vec![PacketType::PtDataRsp as u8];
-        buffer = [packet, buffer].concat();
+        if server.parse::<std::net::SocketAddr>().is_ok() {
+            return Ok(Transport::Tcp(server.to_string()));
+        }
 
-        // Read variable field (errStr)
-        let length = BigEndian::read_u32(&buffer[1..5]);
-        if length < FIXED_SIZE_RESULT_ENTRY as u32 {
-            return Err(std::io::Error::new(
-                ErrorKind::Other,
-                "Error reading result entry",
-            ));
+        match server.rsplit_once(':') {
+            Some((host, port))
+                if !host.is_empty()
+                    && !host.contains(|c: char| c.is_whitespace() || c == ':')
+                    && port.parse::<u16>().is_ok() =>
+            {
+                Ok(Transport::Tcp(server.to_string()))
+            }
+            _ => Err(ClientError::InvalidServerAddress(server.to_string())),
         }
+    }
+}
 
-        let mut buffer_aux = vec![0; (length - FIXED_SIZE_FILE_ENTRY as u32) as usize];
-        conn.read_exact(&mut buffer_aux)?;
+// SocketOptions type for the TCP options applied to the server connection
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct SocketOptions {
+    nodelay: bool, // Disable Nagle's algorithm, sending the commands right away
+    keepalive: Option<Duration>, // Idle time and interval of the keepalive probes, None to disable
+    read_timeout: Option<Duration>, // Maximum time to wait for data from the server, None to disable
+}
 
-        buffer = [buffer, buffer_aux].concat();
+impl SocketOptions {
+    // apply sets the options on the connected stream
+    fn apply(&self, stream: &TcpStream) -> std::io::Result<()> {
+        stream.set_nodelay(self.nodelay)?;
+        if let Some(interval) = self.keepalive {
+            let keepalive = socket2::TcpKeepalive::new()
+                .with_time(interval)
+                .with_interval(interval);
+            socket2::SockRef::from(stream).set_tcp_keepalive(&keepalive)?;
+        }
 
-        // Decode binary data entry
-        let e = decode_binary_to_entry(&buffer)?;
+        Ok(())
+    }
+}
 
-        Ok(e)
+impl Backoff {
+    // delay returns the delay before the given retry (starting at 0) without jitter
+    fn delay(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry);
+        self.base_delay.saturating_mul(factor).min(self.max_delay)
     }
 
-    async fn read_entries(&mut self) {
-        let mut conn = self.conn.as_ref().unwrap();
+    // jittered_delay returns the delay before the given retry with the random jitter applied
+    fn jittered_delay(&self, retry: u32) -> Duration {
+        let delay = self.delay(retry);
+        if self.jitter <= 0.0 {
+            return delay;
+        }
+        delay.mul_f64(1.0 - self.jitter * rand::random::<f64>())
+    }
+}
 
-        // Get the command result
-        let mut packet = [0u8; 1];
-        conn.read_exact(&mut packet).expect("Error reading packet");
-        match PacketType::from(packet[0]) {
-            PacketType::PtPadding => {
-                info!("Received packet type: {:?}", PacketType::PtPadding);
-            }
-            PacketType::PtHeader => {
-                info!("Received packet type: {:?}", PacketType::PtHeader);
-                let _h = self
-                    .read_header_entry()
-                    .expect("Error reading header entry");
-            }
-            PacketType::PtData => {
-                info!("Received packet type: {:?}", PacketType::PtData);
-                let e = self.read_data_entry().expect("Error reading data entry");
-                _ = (self.process_entry_hook)(e);
-            }
-            PacketType::PtDataRsp => {
-                info!("Received packet type: {:?}", PacketType::PtDataRsp);
-            }
-            PacketType::PtResult => {
-                info!("Received packet type: {:?}", PacketType::PtResult);
+// Connection to the server, the protocol framing is the same over plain TCP, TLS and Unix sockets
+#[derive(Debug)]
+enum Connection {
+    Plain(TcpStream),
+    #[cfg(feature = "tls")]
+    Tls(Box<tokio_rustls::client::TlsStream<TcpStream>>),
+    #[cfg(unix)]
+    Unix(tokio::net::UnixStream),
+}
+
+impl Connection {
+    // local_id returns the local address of a TCP connection, or the socket descriptor of a Unix
+    // socket connection as its local address is unnamed
+    fn local_id(&self) -> std::io::Result<String> {
+        match self {
+            Connection::Plain(s) => Ok(s.local_addr()?.to_string()),
+            #[cfg(feature = "tls")]
+            Connection::Tls(s) => Ok(s.get_ref().0.local_addr()?.to_string()),
+            #[cfg(unix)]
+            Connection::Unix(s) => {
+                use std::os::fd::AsRawFd;
+                Ok(format!("{}{}", UNIX_SOCKET_PREFIX, s.as_raw_fd()))
             }
         }
     }
 
-    // close_connection closes connection to the server
-    pub fn close_connection(&mut self) {
-        if self.connected {
-            info!("{} Close connection", self.id);
-            // self.conn.close(); // Uncomment this when you have a connection to close
+    // peer_addr returns the server address of a TCP connection, None for a Unix socket connection
+    fn peer_addr(&self) -> std::io::Result<Option<std::net::SocketAddr>> {
+        match self {
+            Connection::Plain(s) => s.peer_addr().map(Some),
+            #[cfg(feature = "tls")]
+            Connection::Tls(s) => s.get_ref().0.peer_addr().map(Some),
+            #[cfg(unix)]
+            Connection::Unix(_) => Ok(None),
         }
-        self.connected = false;
     }
 
-    // exec_command_start executes client TCP command to start streaming from entry
-    pub fn exec_command_start(&mut self, from_entry: u64) -> Result<(), ClientError> {
-        match self.exec_command(Command::CmdStart, from_entry, None) {
-            Ok(_) => Ok(()),
-            Err(e) => Err(e),
+    // shutdown shuts down both directions of the socket
+    fn shutdown(&self) -> std::io::Result<()> {
+        let socket = match self {
+            Connection::Plain(s) => socket2::SockRef::from(s),
+            #[cfg(feature = "tls")]
+            Connection::Tls(s) => socket2::SockRef::from(s.get_ref().0),
+            #[cfg(unix)]
+            Connection::Unix(s) => socket2::SockRef::from(s),
+        };
+        socket.shutdown(std::net::Shutdown::Both)
+    }
+
+    // try_write_plain writes to a plain TCP or Unix socket connection without waiting, TLS records
+    // can't be written without the async stream so it's not supported over TLS
+    fn try_write_plain(&self, b: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Connection::Plain(s) => s.try_write(b),
+            #[cfg(feature = "tls")]
+            Connection::Tls(_) => Err(std::io::ErrorKind::Unsupported.into()),
+            #[cfg(unix)]
+            Connection::Unix(s) => s.try_write(b),
         }
     }
+}
 
-    // exec_command_start_bookmark executes client TCP command to start streaming from bookmark
-    pub fn exec_command_start_bookmark(
-        &mut self,
-        from_bookmark: Vec<u8>,
-    ) -> Result<(), ClientError> {
-        match self.exec_command(Command::CmdStartBookmark, 0, Some(from_bookmark)) {
-            Ok(_) => Ok(()),
-            Err(e) => Err(e),
+impl AsyncRead for Connection {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let res = match self.get_mut() {
+            Connection::Plain(s) => Pin::new(s).poll_read(cx, buf),
+            #[cfg(feature = "tls")]
+            Connection::Tls(s) => Pin::new(s.as_mut()).poll_read(cx, buf),
+            #[cfg(unix)]
+            Connection::Unix(s) => Pin::new(s).poll_read(cx, buf),
+        };
+
+        #[cfg(feature = "metrics")]
+        if let Poll::Ready(Ok(())) = res {
+            metrics::counter!(METRIC_SOCKET_READS).increment(1);
         }
+        res
     }
+}
 
-    // exec_command_stop executes client TCP command to stop streaming
-    pub fn exec_command_stop(&mut self) -> Result<(), ClientError> {
-        match self.exec_command(Command::CmdStop, 0, None) {
-            Ok(_) => Ok(()),
-            Err(e) => Err(e),
+impl AsyncWrite for Connection {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        match self.get_mut() {
+            Connection::Plain(s) => Pin::new(s).poll_write(cx, buf),
+            #[cfg(feature = "tls")]
+            Connection::Tls(s) => Pin::new(s.as_mut()).poll_write(cx, buf),
+            #[cfg(unix)]
+            Connection::Unix(s) => Pin::new(s).poll_write(cx, buf),
         }
     }
 
-    // exec_command_get_header executes client TCP command to get the header
-    pub fn exec_command_get_header(&mut self) -> Result<HeaderEntry, ClientError> {
-        match self.exec_command(Command::CmdHeader, 0, None) {
-            Ok((header, _)) => Ok(header),
-            Err(e) => Err(e),
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            Connection::Plain(s) => Pin::new(s).poll_flush(cx),
+            #[cfg(feature = "tls")]
+            Connection::Tls(s) => Pin::new(s.as_mut()).poll_flush(cx),
+            #[cfg(unix)]
+            Connection::Unix(s) => Pin::new(s).poll_flush(cx),
         }
     }
 
-    // exec_command_get_entry executes client TCP command to get an entry
-    pub fn exec_command_get_entry(&mut self, from_entry: u64) -> Result<Entry, ClientError> {
-        match self.exec_command(Command::CmdEntry, from_entry, None) {
-            Ok((_, entry)) => Ok(entry),
-            Err(e) => Err(e),
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            Connection::Plain(s) => Pin::new(s).poll_shutdown(cx),
+            #[cfg(feature = "tls")]
+            Connection::Tls(s) => Pin::new(s.as_mut()).poll_shutdown(cx),
+            #[cfg(unix)]
+            Connection::Unix(s) => Pin::new(s).poll_shutdown(cx),
         }
     }
+}
 
-    // exec_command_get_bookmark executes client TCP command to get a bookmark
-    pub fn exec_command_get_bookmark(
-        &mut self,
-        from_bookmark: Vec<u8>,
-    ) -> Result<Entry, ClientError> {
-        match self.exec_command(Command::CmdBookmark, 0, Some(from_bookmark)) {
-            Ok((_, entry)) => Ok(entry),
-            Err(e) => Err(e),
-        }
+// TlsConfig type with the TLS settings used to secure the connection
+#[cfg(feature = "tls")]
+#[derive(Clone)]
+struct TlsConfig {
+    connector: TlsConnector,
+    server_name: ServerName<'static>, // Name sent as SNI and verified against the certificate
+}
+
+#[cfg(feature = "tls")]
+impl std::fmt::Debug for TlsConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TlsConfig")
+            .field("server_name", &self.server_name)
+            .finish_non_exhaustive()
     }
+}
 
-    // exec_command executes a valid client TCP command with deferred command result possibility
-    fn exec_command(
-        &mut self,
-        cmd: Command,
-        from_entry: u64,
-        from_bookmark: Option<Vec<u8>>,
-    ) -> Result<(HeaderEntry, Entry), ClientError> {
-        info!("{} Executing command {:?}...", self.id, cmd,);
-        let mut header: HeaderEntry = Default::default();
-        let mut entry: Entry = Default::default();
+// StreamClient type to manage a data stream client
+pub struct StreamClient {
+    server: String,       // Server address to connect IP:port
+    servers: Vec<String>, // Server addresses rotated on connection failures, server included
+    stream_type: StreamType,
+    conn: Option<BufReader<Connection>>,
+    id: String,                    // Client id
+    peer_addr: Option<SocketAddr>, // Server address of the current connection
+    started: bool,                 // Flag client started
+    connected: bool,               // Flag client connected to server
+    needs_reconnect: bool, // Flag connection closed by an aborted command, opened on the next one
+    streaming: bool,       // Flag client streaming started
+    from_stream: u64,      // Start entry number from latest start command
+    resume_from: u64, // Entry number to start or restore the streaming, next to the last delivered
+    start_mode: StartMode, // How the streaming was started, replayed on reconnect
+    status: ClientStatus, // Streaming status
+    reported_state: ClientState, // Latest state reported to the event listener
+    #[cfg(feature = "compression")]
+    compression: Compression, // Compression of the event entries data
+    capture_raw: bool, // Flag to keep the framed bytes of the data entries
+    timestamp_entries: bool, // Flag to set the time the data entries are read
+    verify_checksums: bool, // Flag to verify and strip the CRC32 trailing the event entries data
+    strict: bool, // Flag to fail on the packet and entry types unknown to the protocol with UnknownType
+    supported_versions: Vec<u8>, // Stream versions accepted in the header
+    read_buffer: Vec<u8>, // Buffer reused to read the data entries, grown as needed
+    read_ahead_size: usize, // Capacity of the buffer the connection is read through
+    max_entry_size: usize, // Maximum size of a data entry, larger entries are rejected unread
+    page_size: u64, // Data page size, a padding region can't be longer than a page
+    bytes_consumed: u64, // Bytes of the packets read from the server, over all connections
+    dedup_entries: bool, // Flag to drop the entries not past the last delivered one
+    last_delivered: Option<u64>, // Number of the highest entry delivered
+    verify_sequence: bool, // Flag to check the streamed entries are numbered sequentially
+    fill_gaps: bool, // Flag to fetch the entries missing in a sequence gap
+    entry_filter: Option<EntryFilterFunc>, // Selects the streamed entries to process, None for all
+    total_entries: u64, // Total entries from latest header command
+    header: Option<HeaderEntry>, // Header from latest header command
+    header_fetched_at: Option<Instant>, // Time of the latest header command
+    concurrent_header: bool, // Flag to get the header on a side connection while starting
+    skip_initial_header: bool, // Flag to start the streaming without getting the header
+    header_wanted: AtomicBool, // Set by total_entries to fetch the skipped header while streaming
+    pending_header: Option<oneshot::Receiver<Result<HeaderEntry, ClientError>>>, // Header fetch in flight
+    header_cache_ttl: Duration, // Time the cached header is served by get_header
+    backoff: Backoff,           // Delays between reconnection attempts
+    command_timeout: Duration,  // Maximum time to wait for a command response
+    connect_timeout: Option<Duration>, // Maximum time to wait for a connection, None for the OS default
+    command_retries: u32, // Reconnections to re-issue a one-shot command failed with a network error
+    idle_timeout: Option<Duration>, // Time without data before probing the connection, None to disable
+    socket_options: SocketOptions,  // TCP options of the server connection
+    rate_limiter: Option<RateLimiter>, // Paces the delivered entries, None for no limit
+    tee: Option<StreamTee>,         // Datastream file the streamed entries are also written to
+    tee_flush_interval: Duration,   // Time between flushes of the teed entries
+    entry_sender: Option<mpsc::Sender<Entry>>, // Channel to forward the entries to a subscriber
+    event_listener: Option<Box<dyn EventListener>>, // Callbacks on the lifecycle events
+    #[cfg(feature = "tls")]
+    tls: Option<TlsConfig>, // TLS settings, None for plain TCP
 
-        // Check status of the client
-        if !self.connected {
-            info!("Execute command not allowed. Client is not started");
-            return Err(ClientError::ClientNotStarted(
-                "Execute command not allowed.",
-            ));
-        }
+    pub process_entry_hook: ProcessEntryFunc, // Callback function to process the entry
+    async_process_entry_hook: Option<AsyncProcessEntryFunc>, // Awaited instead of process_entry_hook if set
+    concurrent_process_entry_hook: Option<ConcurrentProcessEntryFunc>, // Called by the workers instead of the other hooks if set
+    worker_count: usize, // Worker tasks calling the concurrent process entry hook
+    workers: Option<WorkerPool>, // Workers spawned on the first dispatched entry
+    hook_error_policy: HookErrorPolicy, // What to do when the hook fails
+    hook_error: Option<ClientError>, // Hook error the streaming was stopped on, returned by start
+}
+
+impl std::fmt::Debug for StreamClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StreamClient")
+            .field("server", &self.server)
+            .field("servers", &self.servers)
+            .field("stream_type", &self.stream_type)
+            .field("conn", &self.conn)
+            .field("id", &self.id)
+            .field("peer_addr", &self.peer_addr)
+            .field("started", &self.started)
+            .field("connected", &self.connected)
+            .field("needs_reconnect", &self.needs_reconnect)
+            .field("streaming", &self.streaming)
+            .field("from_stream", &self.from_stream)
+            .field("resume_from", &self.resume_from)
+            .field("start_mode", &self.start_mode)
+            .field("status", &self.status)
+            .field("capture_raw", &self.capture_raw)
+            .field("timestamp_entries", &self.timestamp_entries)
+            .field("verify_checksums", &self.verify_checksums)
+            .field("strict", &self.strict)
+            .field("supported_versions", &self.supported_versions)
+            .field("read_ahead_size", &self.read_ahead_size)
+            .field("max_entry_size", &self.max_entry_size)
+            .field("page_size", &self.page_size)
+            .field("bytes_consumed", &self.bytes_consumed)
+            .field("dedup_entries", &self.dedup_entries)
+            .field("last_delivered", &self.last_delivered)
+            .field("verify_sequence", &self.verify_sequence)
+            .field("fill_gaps", &self.fill_gaps)
+            .field("total_entries", &self.total_entries)
+            .field("header", &self.header)
+            .field("header_cache_ttl", &self.header_cache_ttl)
+            .field("concurrent_header", &self.concurrent_header)
+            .field("skip_initial_header", &self.skip_initial_header)
+            .field("backoff", &self.backoff)
+            .field("command_timeout", &self.command_timeout)
+            .field("connect_timeout", &self.connect_timeout)
+            .field("command_retries", &self.command_retries)
+            .field("idle_timeout", &self.idle_timeout)
+            .field("socket_options", &self.socket_options)
+            .field("rate_limiter", &self.rate_limiter)
+            .field("teeing", &self.tee.is_some())
+            .field("tee_flush_interval", &self.tee_flush_interval)
+            .field("subscribed", &self.entry_sender.is_some())
+            .field("filtered", &self.entry_filter.is_some())
+            .field("listened", &self.event_listener.is_some())
+            .field("async_hook", &self.async_process_entry_hook.is_some())
+            .field(
+                "concurrent_hook",
+                &self.concurrent_process_entry_hook.is_some(),
+            )
+            .field("worker_count", &self.worker_count)
+            .field("hook_error_policy", &self.hook_error_policy)
+            .finish_non_exhaustive()
+    }
+}
 
-        let mut conn = self.conn.as_ref().unwrap();
+impl StreamClient {
+    pub fn new(server: String) -> Result<StreamClient, Box<dyn std::error::Error>> {
+        let client = StreamClient::builder().server(server).build()?;
 
-        // Send command
-        conn.write_all(&(cmd as u64).to_be_bytes())
-            .expect("Error sending command");
+        Ok(client)
+    }
 
-        // Send stream type
-        conn.write_all(&(self.stream_type as u64).to_be_bytes())
-            .expect("Error sending stream type");
+    // builder returns a StreamClientBuilder to configure a new client
+    pub fn builder() -> StreamClientBuilder {
+        StreamClientBuilder::default()
+    }
 
-        // Send the command parameters
-        match cmd {
-            Command::CmdStart => {
-                info!("{} ...from entry {}", self.id, from_entry);
-                // Send starting/from entry number
-                conn.write_all(&from_entry.to_be_bytes())
-                    .expect("Error sending Start command");
+    // set_process_entry_hook sets the callback function to process the received entries
+    pub fn set_process_entry_hook<F>(&mut self, f: F)
+    where
+        F: FnMut(Entry) -> Result<HookAction, ClientError> + Send + 'static,
+    {
+        self.process_entry_hook = Box::new(f);
+    }
+
+    // set_async_process_hook sets an async function to process the entries, awaited in the read
+    // loop instead of calling process_entry_hook so the entries are still processed in order
+    pub fn set_async_process_hook<F>(&mut self, f: F)
+    where
+        F: FnMut(Entry) -> ProcessEntryFuture + Send + 'static,
+    {
+        self.async_process_entry_hook = Some(Box::new(f));
+    }
+
+    // set_entry_filter sets the function selecting the streamed entries passed to the
+    // process_entry_hook, subscribers and entry streams. The filter gets the entry without the
+    // data, the data of the skipped entries is never decoded.
+    pub fn set_entry_filter<F>(&mut self, f: F)
+    where
+        F: Fn(&Entry) -> bool + Send + 'static,
+    {
+        self.entry_filter = Some(Box::new(f));
+    }
+
+    // set_entry_type_filter sets an entry filter selecting only the entries of entry_type
+    pub fn set_entry_type_filter(&mut self, entry_type: EntryType) {
+        self.set_entry_filter(move |e| e.entry_type == entry_type);
+    }
+
+    // subscribe returns a channel receiver where the entries read by start are forwarded
+    // instead of being passed to process_entry_hook. The channel is bounded to STREAM_BUFFER
+    // entries, when it's full no more entries are read from the server until there is room.
+    // Dropping the receiver stops the stream and closes the connection.
+    pub fn subscribe(&mut self) -> mpsc::Receiver<Entry> {
+        let (tx, rx) = mpsc::channel(STREAM_BUFFER);
+        self.entry_sender = Some(tx);
+        rx
+    }
+
+    // Start connects to the data stream server and starts getting data from the server
+    pub async fn start(&mut self) -> Result<(), ClientError> {
+        self.start_with_shutdown(CancellationToken::new()).await
+    }
+
+    // start_with_shutdown works like start until the token is cancelled, then it stops the
+    // streaming and closes the connection before returning
+    pub async fn start_with_shutdown(
+        &mut self,
+        token: CancellationToken,
+    ) -> Result<(), ClientError> {
+        tokio::select! {
+            res = self.start_streaming_with_context() => res?,
+            _ = token.cancelled() => {
+                self.close_connection();
+                return Ok(());
             }
-            Command::CmdStartBookmark => {
-                info!("{} ...from bookmark {:?}", self.id, from_bookmark);
-                // Send starting/from bookmark length
-                if let Some(bookmark) = &from_bookmark {
-                    conn.write_all(&(bookmark.len() as u32).to_be_bytes())
-                        .expect("Error sending StartBookmark command");
-                    // Send starting/from bookmark
-                    conn.write_all(bookmark)
-                        .expect("Error sending from bookmark");
+        }
+
+        self.read_until_stopped(token).await
+    }
+
+    // start_from_last works like start, streaming the last n entries of the stream and the new
+    // ones. The streaming starts from the first entry when there are less than n entries.
+    pub async fn start_from_last(&mut self, n: u64) -> Result<(), ClientError> {
+        self.connect_server()
+            .await
+            .map_err(|e| start_failed(format!("connecting to {}", self.server), e))?;
+        let header = self
+            .start_header()
+            .await
+            .map_err(|e| start_failed("getting the header".to_string(), e))?;
+        self.follow_from(header.total_entries.saturating_sub(n));
+        self.start_from_resume()
+            .await
+            .map_err(|e| start_failed(format!("starting from entry {}", self.resume_from), e))?;
+
+        self.read_until_stopped(CancellationToken::new()).await
+    }
+
+    // start_streaming_with_context works like start_streaming, failing with StartFailed naming
+    // the step that failed
+    async fn start_streaming_with_context(&mut self) -> Result<(), ClientError> {
+        self.connect_server()
+            .await
+            .map_err(|e| start_failed(format!("connecting to {}", self.server), e))?;
+        self.request_header()
+            .await
+            .map_err(|e| start_failed("getting the header".to_string(), e))?;
+        self.start_from_resume()
+            .await
+            .map_err(|e| start_failed(format!("starting from entry {}", self.resume_from), e))
+    }
+
+    // read_until_stopped processes the streamed entries until the streaming is stopped or the
+    // token is cancelled, reconnecting on read errors
+    async fn read_until_stopped(&mut self, token: CancellationToken) -> Result<(), ClientError> {
+        while self.started {
+            tokio::select! {
+                res = self.read_entries() => {
+                    if let Err(e) = res {
+                        error!("{} Error reading entries: {}", self.id, e);
+                        self.reconnect().await.map_err(|e| {
+                            start_failed(
+                                format!("reconnecting after a read error at entry {}", self.resume_from),
+                                e,
+                            )
+                        })?;
+                    }
                 }
-            }
-            Command::CmdEntry => {
-                info!("{} ...get entry {}", self.id, from_entry);
-                // Send entry to retrieve
-                conn.write_all(&from_entry.to_be_bytes())
-                    .expect("Error sending entry");
-            }
-            Command::CmdBookmark => {
-                info!("{} ...get bookmark {:?}", self.id, from_bookmark);
-                // Send bookmark length
-                if let Some(bookmark) = &from_bookmark {
-                    conn.write_all(&(bookmark.len() as u32).to_be_bytes())
-                        .expect("Error sending bookmark length");
-                    // Send bookmark to retrieve
-                    conn.write_all(bookmark).expect("Error sending bookmark");
+                _ = token.cancelled() => {
+                    info!("{} Shutdown requested", self.id);
+                    if let Err(e) = self.exec_command_stop().await {
+                        error!("{} Error stopping the streaming: {}", self.id, e);
+                    }
+                    self.close_connection();
                 }
             }
-            _ => {}
         }
 
-        // Get the command result
-        let re = self
-            .read_result_entry()
-            .expect("Error reading result entry");
-        if re.error_num != CommandError::CmdErrOK as u32 {
-            // TODO string the command
-            return Err(ClientError::InvalidCommand("TODO string the command"));
+        // The entries still processed by the workers are applied before returning
+        self.complete_entries(true).await;
+
+        // A streaming stopped by a failed hook returns its error
+        match self.hook_error.take() {
+            Some(e) => Err(e),
+            None => Ok(()),
         }
-        debug!("Result entry: {:?}", re);
+    }
 
-        // Get the data response and update streaming flag
-        match cmd {
-            Command::CmdStart => {
-                self.streaming = true;
-                self.from_stream = from_entry;
+    // into_entry_stream consumes the client and returns a stream of the received data entries.
+    // Network errors are handled reconnecting to the server, other errors are yielded as the
+    // last item of the stream.
+    pub fn into_entry_stream(self) -> impl Stream<Item = Result<Entry, ClientError>> {
+        futures::stream::unfold(Some(self), |client| async move {
+            let mut client = client?;
+            match client.next_entry().await {
+                Ok(e) => Some((Ok(e), Some(client))),
+                Err(e) => Some((Err(e), None)),
             }
-            Command::CmdStartBookmark => {
-                self.streaming = true;
+        })
+    }
+
+    // start_streaming connects to the server, gets the header and starts streaming from the first entry
+    async fn start_streaming(&mut self) -> Result<(), ClientError> {
+        // Connect to server
+        self.connect_server().await?;
+
+        self.request_header().await?;
+
+        self.start_from_resume().await
+    }
+
+    // request_header gets the header when starting the streaming. With concurrent_header it's
+    // fetched on a side connection while the streaming is started, and applied once received
+    // by apply_pending_header. It's got first if the side connection can't be opened.
+    async fn request_header(&mut self) -> Result<(), ClientError> {
+        if self.skip_initial_header {
+            debug!("{} Starting without the header", self.id);
+            return Ok(());
+        }
+        if self.concurrent_header {
+            match self.spawn_header_fetch().await {
+                Ok(()) => return Ok(()),
+                Err(e) => error!(
+                    "{} Error opening the header connection, getting the header first: {}",
+                    self.id, e
+                ),
             }
-            Command::CmdStop => {
-                self.streaming = false;
+        }
+
+        self.start_header().await.map(|_| ())
+    }
+
+    // spawn_header_fetch gets the header on a side connection, applied once received by
+    // apply_pending_header
+    async fn spawn_header_fetch(&mut self) -> Result<(), ClientError> {
+        let conn = self.open_side_connection().await?;
+        let (tx, rx) = oneshot::channel();
+        let (stream_type, command_timeout) = (self.stream_type, self.command_timeout);
+        tokio::spawn(async move {
+            let _ = tx.send(fetch_header(conn, stream_type, command_timeout).await);
+        });
+        self.pending_header = Some(rx);
+        Ok(())
+    }
+
+    // open_side_connection opens another connection to the current server, besides the client
+    // connection
+    async fn open_side_connection(&mut self) -> Result<Connection, ClientError> {
+        let conn = match Transport::parse(&self.server)? {
+            Transport::Tcp(_) => {
+                let addrs = self.resolve_server().await?;
+                self.open_connection(&addrs).await
             }
-            Command::CmdHeader => {
-                header = self
-                    .read_header_entry()
-                    .expect("Error reading header entry");
+            #[cfg(unix)]
+            Transport::Unix(path) => tokio::net::UnixStream::connect(path)
+                .await
+                .map(Connection::Unix),
+        };
+        Ok(conn?)
+    }
+
+    // apply_pending_header sets the header fetched concurrently once received. The streaming is
+    // closed if its version isn't supported.
+    fn apply_pending_header(&mut self) {
+        let Some(rx) = &mut self.pending_header else {
+            return;
+        };
+        let res = match rx.try_recv() {
+            Ok(res) => res,
+            Err(oneshot::error::TryRecvError::Empty) => return,
+            Err(oneshot::error::TryRecvError::Closed) => {
+                self.pending_header = None;
+                return;
             }
-            Command::CmdEntry => {
-                let e = self.read_data_entry().expect("Error decoding entry");
-                if e.entry_type == EntryType::NotFound {
-                    return Err(ClientError::EntryNotFound);
+        };
+        self.pending_header = None;
+
+        match res.and_then(|header| self.set_header(header)) {
+            Ok(header) => debug!("{} Header received while streaming: {:?}", self.id, header),
+            Err(e) => {
+                error!("{} Error getting the header: {}", self.id, e);
+                self.notify_error(&e);
+                if let ClientError::UnsupportedVersion(_) = e {
+                    self.close_connection();
                 }
-                entry = e;
             }
-            Command::CmdBookmark => {
-                let e = self.read_bookmark_entry().expect("Error decoding bookmark");
-                if e.entry_type == EntryType::NotFound {
-                    return Err(ClientError::BookmarkNotFound);
+        }
+    }
+
+    // start_header gets the header when starting the streaming, retrying the network errors and
+    // timeouts with the reconnection backoff. The header error is returned once the retries are
+    // exhausted, and an error result from the server is returned right away.
+    async fn start_header(&mut self) -> Result<HeaderEntry, ClientError> {
+        let mut attempt = 0;
+        loop {
+            match self.exec_command_get_header().await {
+                Ok(header) => return Ok(header),
+                Err(e @ (ClientError::NetworkError(_) | ClientError::Timeout)) => {
+                    error!(
+                        "{} Getting the header failed (attempt {}): {}",
+                        self.id,
+                        attempt + 1,
+                        e
+                    );
+                    if self.wait_reconnect(&mut attempt).await.is_err() {
+                        return Err(e);
+                    }
+                    self.disconnect();
+                    self.connect_server().await?;
                 }
-                entry = e;
+                Err(e) => return Err(e),
             }
         }
+    }
 
-        Ok((header, entry))
+    // start_from_resume starts the streaming of a connected client from the resume entry, or the
+    // start bookmark if no entry was delivered since it was set
+    async fn start_from_resume(&mut self) -> Result<(), ClientError> {
+        match self.start_mode.clone() {
+            StartMode::Entry => self.exec_command_start(self.resume_from).await?,
+            StartMode::Bookmark(bookmark) => {
+                self.exec_command_start_bookmark(Bookmark::try_from(bookmark)?)
+                    .await?
+            }
+        }
+        self.started = true;
+        self.record_status();
+        Ok(())
     }
-}
 
-// decode_binary_to_header_entry decodes from binary bytes slice to a header entry type
-fn decode_binary_to_header_entry(b: &[u8]) -> io::Result<HeaderEntry> {
-    if b.len() != HEADER_SIZE {
-        return Err(io::Error::new(
-            ErrorKind::InvalidData,
-            "Invalid binary header entry",
-        ));
+    // reconnect closes the connection and connects again restoring the streaming
+    async fn reconnect(&mut self) -> Result<(), ClientError> {
+        self.disconnect();
+        self.rotate_server();
+        self.connect_server().await?;
+        Ok(())
     }
 
-    let packet_type = b[0];
-    let head_length = BigEndian::read_u32(&b[1..5]);
-    let version = b[5];
-    let system_id = BigEndian::read_u64(&b[6..14]);
-    let stream_type = StreamType::from(BigEndian::read_u64(&b[14..22])); // Convert u64 to StreamType
-    let total_length = BigEndian::read_u64(&b[22..30]);
-    let total_entries = BigEndian::read_u64(&b[30..38]);
+    // next_entry reads packets until the next data entry, reconnecting on network errors
+    async fn next_entry(&mut self) -> Result<Entry, ClientError> {
+        if !self.started {
+            self.start_streaming().await?;
+        }
 
-    Ok(HeaderEntry {
-        packet_type,
-        head_length,
-        version,
-        system_id,
-        stream_type,
-        total_length,
-        total_entries,
-    })
-}
+        loop {
+            match self.read_packet().await {
+                Ok(Some(e)) if self.is_duplicate(e.number) => {
+                    debug!("{} Dropping duplicated entry {}", self.id, e.number);
+                }
+                Ok(Some(e)) => {
+                    self.delivered(e.number);
+                    return Ok(e);
+                }
+                Ok(None) => {}
+                Err(ClientError::NetworkError(e)) => {
+                    error!("{} Error reading entries: {}", self.id, e);
+                    self.reconnect().await?;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
 
-// decode_binary_to_file_entry decodes from binary bytes slice to file entry type
-fn decode_binary_to_entry(b: &[u8]) -> io::Result<Entry> {
-    if b.len() < FIXED_SIZE_FILE_ENTRY {
-        return Err(io::Error::new(
-            ErrorKind::InvalidData,
-            "Invalid binary data entry",
-        ));
+    // next_entry_into reads the next streamed data entry appending its data to buf, so a single
+    // buffer can be reused for every entry, and returns the entry fixed fields. The data is
+    // appended as received, without decompression nor checksum verification. The other packets
+    // are read like when streaming, reconnecting on the network errors.
+    pub async fn next_entry_into(&mut self, buf: &mut Vec<u8>) -> Result<EntryHeader, ClientError> {
+        if !self.started {
+            self.start_streaming().await?;
+        }
+
+        loop {
+            // The packets other than data entries are read like when streaming
+            let res = match self.read_packet_type().await {
+                Ok(Some(packet)) => self.dispatch_packet(packet).await,
+                Ok(None) => Ok(false),
+                Err(e) => Err(e),
+            };
+
+            match res {
+                Ok(false) => {}
+                Ok(true) => {
+                    let e = decode_entry_fields(&self.read_buffer);
+                    if self.strict {
+                        check_known_entry_type(e.entry_type)?;
+                    }
+                    buf.extend_from_slice(&self.read_buffer[FIXED_SIZE_FILE_ENTRY..]);
+                    self.delivered(e.number);
+                    return Ok(EntryHeader {
+                        packet_type: e.packet_type,
+                        length: e.length,
+                        entry_type: e.entry_type,
+                        number: e.number,
+                    });
+                }
+                Err(ClientError::NetworkError(e)) => {
+                    error!("{} Error reading entries: {}", self.id, e);
+                    self.reconnect().await?;
+                }
+                Err(e) => return Err(e),
+            }
+        }
     }
 
-    let packet_type = b[0];
-    let length = BigEndian::read_u32(&b[1..5]);
-    let entry_type = EntryType::from(BigEndian::read_u32(&b[5..9])); // Convert u32 to EntryType
-    let number = BigEndian::read_u64(&b[9..17]);
-    let data = b[17..].to_vec();
-
-    if data.len() as u32 != length - FIXED_SIZE_FILE_ENTRY as u32 {
-        return Err(io::Error::new(
-            ErrorKind::InvalidData,
-            "Error decoding binary data entry",
-        ));
+    // validate_stream reads the next count streamed data entries checking they decode, without
+    // calling the hook, and returns the stats of the entries read. Entries failing to decode are
+    // counted and skipped, as they're read whole the stream stays framed.
+    pub async fn validate_stream(&mut self, count: u64) -> Result<ValidationReport, ClientError> {
+        if !self.started {
+            self.start_streaming().await?;
+        }
+
+        let mut report = ValidationReport::default();
+        while report.entries < count {
+            let conn = self
+                .conn
+                .as_mut()
+                .ok_or(ClientError::ClientNotStarted("Read entries not allowed."))?;
+            let packet = match conn.fill_buf().await?.first() {
+                Some(&packet) => packet,
+                None => {
+                    return Err(ClientError::NetworkError(std::io::Error::from(
+                        std::io::ErrorKind::UnexpectedEof,
+                    )))
+                }
+            };
+            // Other packets are read as when streaming
+            if packet != PacketType::PtData as u8 {
+                self.read_packet().await?;
+                continue;
+            }
+            conn.consume(1);
+            self.read_data_entry_bytes(packet).await?;
+
+            let fields = decode_entry_fields(&self.read_buffer);
+            report.entries += 1;
+            report.bytes += fields.length as u64;
+            *report.entry_types.entry(fields.entry_type).or_default() += 1;
+            if let Err(e) = self.decode_data_entry() {
+                error!(
+                    "{} Entry {} failed to decode: {}",
+                    self.id, fields.number, e
+                );
+                report.decode_errors += 1;
+            }
+            self.delivered(fields.number);
+        }
+
+        Ok(report)
     }
 
-    Ok(Entry {
-        packet_type,
-        length,
-        entry_type,
-        number,
-        data,
-    })
-}
+    // connect_server waits until the server connection is established and returns if a command result is pending
+    pub async fn connect_server(&mut self) -> Result<bool, ClientError> {
+        let mut attempt = 0;
+        if self.connected {
+            return Ok(false);
+        }
 
-// DecodeBinaryToResultEntry decodes from binary bytes slice to a result entry type
-fn decode_binary_to_result_entry(b: &[u8]) -> ResultEntry {
-    let mut e = ResultEntry::default();
+        // Connect to server
+        while !self.connected {
+            let transport = Transport::parse(&self.server)?;
+            let addrs = match &transport {
+                Transport::Tcp(_) => self.resolve_server().await,
+                #[cfg(unix)]
+                Transport::Unix(_) => Ok(Vec::new()),
+            };
+            let addrs = match addrs {
+                Ok(addrs) => addrs,
+                // An address that can't be resolved won't connect on any retry
+                Err(e) if self.servers.len() <= 1 => return Err(e),
+                Err(e) => {
+                    self.notify_error(&e);
+                    self.rotate_server();
+                    self.wait_reconnect(&mut attempt).await?;
+                    continue;
+                }
+            };
 
-    let packet_type = b[0];
-    let length = BigEndian::read_u32(&b[1..5]);
-    let error_num = BigEndian::read_u32(&b[5..9]);
-    let error_str = b[9..].to_vec();
+            let conn = match &transport {
+                Transport::Tcp(_) => self.open_connection(&addrs).await,
+                #[cfg(unix)]
+                Transport::Unix(path) => tokio::net::UnixStream::connect(path)
+                    .await
+                    .map(Connection::Unix),
+            };
+            match conn {
+                Ok(conn) => {
+                    // Connected, the client id is only empty before the first connection
+                    #[cfg(feature = "metrics")]
+                    if !self.id.is_empty() {
+                        metrics::counter!(METRIC_RECONNECTS).increment(1);
+                    }
+                    self.id = conn.local_id()?;
+                    self.peer_addr = conn.peer_addr()?;
+                    self.conn = Some(BufReader::with_capacity(self.read_ahead_size, conn));
+                    self.connected = true;
+                    self.needs_reconnect = false;
+                    self.record_status();
+                    info!("{} Connected to server: {}", self.id, self.server);
+                    if let Some(listener) = &self.event_listener {
+                        listener.on_connected(&self.id, &self.server);
+                    }
 
-    e.packet_type = packet_type;
-    e.length = length;
-    e.error_num = error_num;
-    e.error_str = error_str;
+                    // Restore streaming
+                    if self.streaming {
+                        let res = match self.start_mode.clone() {
+                            StartMode::Entry => {
+                                info!(
+                                    "{} Restoring streaming from entry {}",
+                                    self.id, self.resume_from
+                                );
+                                self.send_command(
+                                    Command::CmdStart,
+                                    self.stream_type,
+                                    self.resume_from,
+                                    None,
+                                )
+                                .await
+                            }
+                            StartMode::Bookmark(bookmark) => {
+                                info!(
+                                    "{} Restoring streaming from bookmark {:?}",
+                                    self.id, bookmark
+                                );
+                                self.send_command(
+                                    Command::CmdStartBookmark,
+                                    self.stream_type,
+                                    0,
+                                    Some(bookmark),
+                                )
+                                .await
+                            }
+                        };
+                        match res {
+                            Ok(_) => {}
+                            Err(e) => {
+                                self.close_connection();
+                                info!("Error restoring streaming: {:?}", e);
+                                self.rotate_server();
+                                self.wait_reconnect(&mut attempt).await?;
+                                self.streaming = false;
+                                self.record_status();
+                                continue;
+                            }
+                        }
+                        return Ok(true);
+                    } else {
+                        return Ok(false);
+                    }
+                }
+                Err(e) => {
+                    error!("Error connecting to server {}: {}", self.server, e);
+                    self.notify_error(&ClientError::NetworkError(e));
+                    self.rotate_server();
+                    self.wait_reconnect(&mut attempt).await?;
+                    continue;
+                }
+            }
+        }
+        Ok(false)
+    }
 
-    e
-}
+    // rotate_server switches to the next server address of the list to connect to
+    fn rotate_server(&mut self) {
+        if self.servers.len() <= 1 {
+            return;
+        }
 
-fn print_received_entry(entry: Entry) -> Result<(), ClientError> {
-    info!("Received entry: {:?}", entry);
-    Ok(())
-}
+        let next = match self.servers.iter().position(|s| *s == self.server) {
+            Some(i) => (i + 1) % self.servers.len(),
+            None => 0,
+        };
+        self.server = self.servers[next].clone();
+        info!("Switching to server {}", self.server);
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use tracing_test::traced_test;
+    // resolve_server resolves the server address, IPv6 literals like [::1]:6900 included, to all
+    // the socket addresses it may be reached at
+    async fn resolve_server(&mut self) -> Result<Vec<SocketAddr>, ClientError> {
+        let addrs: Vec<SocketAddr> = match tokio::net::lookup_host(&self.server).await {
+            Ok(addrs) => addrs.collect(),
+            Err(e) => {
+                error!("Error resolving server address {}: {}", self.server, e);
+                return Err(ClientError::InvalidServerAddress(format!(
+                    "{}: {}",
+                    self.server, e
+                )));
+            }
+        };
+        if addrs.is_empty() {
+            return Err(ClientError::InvalidServerAddress(format!(
+                "{}: no addresses resolved",
+                self.server
+            )));
+        }
 
-    #[tokio::test]
-    #[traced_test]
-    async fn test_stream_client_new() {
-        let server = "stream.zkevm-rpc.com:6900".to_string(); // "stream.zkevm-rpc.com:6900".to_string();
-        let stream_type = StreamType::Sequencer;
-        let mut client = StreamClient::new(server.clone()).unwrap();
-        assert_eq!(client.server, server);
-        assert_eq!(client.stream_type, stream_type);
+        Ok(addrs)
+    }
 
-        client.connect_server().unwrap();
+    // open_connection opens the TCP connection to the first reachable address of the server,
+    // trying each in turn, and does the TLS handshake if enabled
+    async fn open_connection(&mut self, addrs: &[SocketAddr]) -> std::io::Result<Connection> {
+        let mut conn = Err(std::io::Error::from(std::io::ErrorKind::AddrNotAvailable));
+        for addr in addrs {
+            let connect = TcpStream::connect(addr);
+            conn = match self.connect_timeout {
+                Some(connect_timeout) => {
+                    timeout(connect_timeout, connect).await.unwrap_or_else(|_| {
+                        Err(std::io::Error::new(
+                            std::io::ErrorKind::TimedOut,
+                            format!("connection timed out after {:?}", connect_timeout),
+                        ))
+                    })
+                }
+                None => connect.await,
+            };
+            match &conn {
+                Ok(_) => break,
+                Err(e) => debug!("Error connecting to {}: {}", addr, e),
+            }
+        }
+        let conn = conn?;
+        self.socket_options.apply(&conn)?;
 
-        let e = client
-            .exec_command_get_bookmark(0u64.to_be_bytes().to_vec())
-            .unwrap();
-        assert_eq!(e.entry_type, EntryType::Bookmark);
+        #[cfg(feature = "tls")]
+        if let Some(tls) = &self.tls {
+            let stream = tls.connector.connect(tls.server_name.clone(), conn).await?;
+            return Ok(Connection::Tls(Box::new(stream)));
+        }
 
-        client.start().await.unwrap();
+        Ok(Connection::Plain(conn))
+    }
+
+    // wait_reconnect waits the backoff delay before the next connection attempt, or returns
+    // ConnectionFailed once the maximum retries are exhausted
+    async fn wait_reconnect(&mut self, attempt: &mut u32) -> Result<(), ClientError> {
+        *attempt += 1;
+        if self.backoff.max_retries.is_some_and(|max| *attempt > max) {
+            return Err(ClientError::ConnectionFailed(self.server.clone(), *attempt));
+        }
+
+        let delay = self.backoff.jittered_delay(*attempt - 1);
+        debug!("Reconnecting to {} in {:?}", self.server, delay);
+        sleep(delay).await;
+        Ok(())
+    }
+
+    // read_result_entry reads bytes from server connection and returns a result entry type
+    async fn read_result_entry(&mut self) -> Result<ResultEntry, ClientError> {
+        let mut packet = [0u8; 1];
+        self.conn.as_mut().unwrap().read_exact(&mut packet).await?;
+
+        self.read_result_entry_from(packet[0]).await
+    }
+
+    // read_result_entry_from reads the rest of a result entry whose packet type was already read
+    async fn read_result_entry_from(&mut self, packet: u8) -> Result<ResultEntry, ClientError> {
+        let conn = self.conn.as_mut().unwrap();
+
+        // Read the rest of fixed size fields
+        let mut buffer = vec![0; FIXED_SIZE_RESULT_ENTRY];
+        buffer[0] = packet;
+        conn.read_exact(&mut buffer[1..]).await?;
+
+        // Read variable field (errStr)
+        let length = BigEndian::read_u32(&buffer[1..5]);
+        let error_length = match error_str_length(length) {
+            Ok(error_length) => error_length,
+            Err(e) => {
+                // The connection can't be resynced without reading the error string
+                error!(
+                    "{} Invalid result entry, closing connection: {}",
+                    self.id, e
+                );
+                self.disconnect();
+                return Err(e);
+            }
+        };
+
+        let mut buffer_aux = vec![0; error_length];
+        conn.read_exact(&mut buffer_aux).await?;
+
+        buffer = [buffer, buffer_aux].concat();
+        self.bytes_consumed += buffer.len() as u64;
+
+        // Decode binary entry result
+        decode_binary_to_result_entry(&buffer)
+    }
+
+    // read_header_entry reads bytes from server connection and returns a header entry type
+    async fn read_header_entry(&mut self) -> Result<HeaderEntry, ClientError> {
+        let mut packet = [0u8; 1];
+        self.conn.as_mut().unwrap().read_exact(&mut packet).await?;
+
+        self.read_header_entry_from(packet[0]).await
+    }
+
+    // read_header_entry_from reads the rest of a header entry whose packet type was already read
+    async fn read_header_entry_from(&mut self, packet: u8) -> Result<HeaderEntry, ClientError> {
+        let conn = self.conn.as_mut().unwrap();
+
+        // Read the rest of fixed size fields
+        let mut buffer = vec![0; HEADER_SIZE];
+        buffer[0] = packet;
+        conn.read_exact(&mut buffer[1..]).await?;
+        self.bytes_consumed += HEADER_SIZE as u64;
+
+        // Decode binary header entry
+        let h = decode_binary_to_header_entry(&buffer)?;
+
+        Ok(h)
+    }
+
+    // read_data_rsp_entry reads the packet type and the data entry of a command response
+    async fn read_data_rsp_entry(&mut self) -> Result<Entry, ClientError> {
+        // Get the command result
+        let mut packet = [0u8; 1];
+        self.conn.as_mut().unwrap().read_exact(&mut packet).await?;
+
+        self.read_data_entry(packet[0]).await
+    }
+
+    // read_data_entry reads bytes from server connection and returns a data entry type
+    async fn read_data_entry(&mut self, packet: u8) -> Result<Entry, ClientError> {
+        self.read_data_entry_bytes(packet).await?;
+        self.decode_data_entry()
+    }
+
+    // decode_data_entry decodes the data entry in the read buffer, verifying its checksum and
+    // decompressing its data if the client is configured to
+    fn decode_data_entry(&self) -> Result<Entry, ClientError> {
+        // Decode binary data entry
+        let mut e = decode_binary_to_entry(&self.read_buffer)?;
+        if self.strict {
+            check_known_entry_type(e.entry_type)?;
+        }
+        if self.capture_raw {
+            e.raw = Some(self.read_buffer.clone());
+        }
+        if self.timestamp_entries {
+            e.received_at = Some(std::time::Instant::now());
+        }
+
+        if self.verify_checksums {
+            e = verify_entry_checksum(e)?;
+        }
+
+        #[cfg(feature = "compression")]
+        let e = self.decompress_entry(e)?;
+
+        Ok(e)
+    }
+
+    // decompress_entry decompresses the data of an event entry, failing instead of returning
+    // corrupt data
+    #[cfg(feature = "compression")]
+    fn decompress_entry(&self, mut e: Entry) -> Result<Entry, ClientError> {
+        if self.compression == Compression::None
+            || matches!(e.entry_type, EntryType::NotFound | EntryType::Bookmark)
+        {
+            return Ok(e);
+        }
+
+        e.data = decompress(self.compression, &e.data).map_err(|err| {
+            ClientError::DecodeError(format!(
+                "error decompressing entry {} data: {}",
+                e.number, err
+            ))
+        })?;
+        e.length = (FIXED_SIZE_FILE_ENTRY + e.data.len()) as u32;
+        Ok(e)
+    }
+
+    // read_data_entry_bytes reads a data entry from server connection into the read buffer, after
+    // its already read packet type
+    async fn read_data_entry_bytes(&mut self, packet: u8) -> Result<(), ClientError> {
+        let conn = self.conn.as_mut().unwrap();
+        let buffer = &mut self.read_buffer;
+
+        // Read the rest of fixed size fields after the packet type
+        buffer.resize(FIXED_SIZE_FILE_ENTRY, 0);
+        buffer[0] = packet;
+        conn.read_exact(&mut buffer[1..]).await?;
+
+        // Read variable field (data)
+        let length = BigEndian::read_u32(&buffer[1..5]);
+        let data_length = variable_length("data", length, FIXED_SIZE_FILE_ENTRY)?;
+
+        // The connection can't be resynced without reading the entry, so it's closed
+        if length as usize > self.max_entry_size {
+            error!(
+                "{} Entry of {} bytes exceeds the maximum entry size, closing connection",
+                self.id, length
+            );
+            self.disconnect();
+            return Err(ClientError::DecodeError(format!(
+                "entry length {} exceeds the maximum entry size {}",
+                length, self.max_entry_size
+            )));
+        }
+
+        buffer.resize(FIXED_SIZE_FILE_ENTRY + data_length, 0);
+        conn.read_exact(&mut buffer[FIXED_SIZE_FILE_ENTRY..])
+            .await?;
+        self.bytes_consumed += buffer.len() as u64;
+
+        #[cfg(feature = "metrics")]
+        {
+            metrics::counter!(METRIC_ENTRIES).increment(1);
+            metrics::counter!(METRIC_BYTES).increment(length as u64);
+            metrics::gauge!(METRIC_LAST_ENTRY).set(BigEndian::read_u64(&buffer[9..17]) as f64);
+        }
+
+        Ok(())
+    }
+
+    // read_entries reads the next packet from the server connection and processes it
+    async fn read_entries(&mut self) -> Result<(), ClientError> {
+        if self.header_wanted.swap(false, Ordering::Relaxed) && self.pending_header.is_none() {
+            if let Err(e) = self.spawn_header_fetch().await {
+                error!("{} Error opening the header connection: {}", self.id, e);
+            }
+        }
+        if !self.await_data().await {
+            return Ok(());
+        }
+
+        let packet = match self.read_packet().await {
+            Ok(packet) => packet,
+            Err(e) => {
+                self.notify_error(&e);
+                // The entries processed by the workers are applied before reconnecting
+                self.complete_entries(true).await;
+                return Err(e);
+            }
+        };
+        if let Some(e) = packet {
+            let number = e.number;
+            if self.is_duplicate(number) {
+                debug!("{} Dropping duplicated entry {}", self.id, number);
+                return Ok(());
+            }
+            if let Some(expected) = self.sequence_gap(number) {
+                error!(
+                    "{} Entry {} out of sequence, expected {}",
+                    self.id, number, expected
+                );
+                if let Some(listener) = &self.event_listener {
+                    listener.on_gap(&self.id, expected, number);
+                }
+                if self.fill_gaps && number > expected {
+                    for missing in self.fetch_gap(expected, number).await? {
+                        if !self.started {
+                            return Ok(());
+                        }
+                        self.deliver_entry(missing).await;
+                    }
+                }
+            }
+            self.deliver_entry(e).await;
+        }
+
+        Ok(())
+    }
+
+    // deliver_entry hands a streamed entry to the subscriber or the process entry hook, moving
+    // the resume point past it
+    async fn deliver_entry(&mut self, mut e: Entry) {
+        let number = e.number;
+        if let Some(limiter) = &mut self.rate_limiter {
+            limiter.acquire().await;
+        }
+        if let Some(listener) = &self.event_listener {
+            listener.on_entry(&e);
+        }
+        let span = debug_span!("entry", client_id = %self.id, entry_number = number);
+        match &self.entry_sender {
+            Some(tx) => {
+                if tx.send(e).instrument(span).await.is_err() {
+                    info!("{} Subscriber dropped, stopping stream", self.id);
+                    self.entry_sender = None;
+                    self.close_connection();
+                    return;
+                }
+            }
+            None if self.concurrent_process_entry_hook.is_some() => {
+                self.dispatch_entry(e).await;
+                return;
+            }
+            None => {
+                let mut attempt = 0;
+                let res = loop {
+                    // The entry is only cloned if the hook can be called again with it
+                    let retry =
+                        matches!(self.hook_error_policy, HookErrorPolicy::Retry(n) if attempt < n);
+                    let entry = match retry {
+                        true => e.clone(),
+                        false => std::mem::take(&mut e),
+                    };
+                    match self.call_hook(entry, span.clone()).await {
+                        Err(e) if retry => {
+                            attempt += 1;
+                            error!(
+                                "{} Error processing entry {} (attempt {}), retrying: {}",
+                                self.id, number, attempt, e
+                            );
+                        }
+                        res => break res,
+                    }
+                };
+                self.apply_hook_result(number, res).await;
+                return;
+            }
+        }
+        self.delivered(number);
+    }
+
+    // apply_hook_result moves the resume point past a processed entry, or stops the streaming as
+    // requested by the hook or its error policy. Returns false if the streaming was stopped.
+    async fn apply_hook_result(
+        &mut self,
+        number: u64,
+        res: Result<HookAction, ClientError>,
+    ) -> bool {
+        match res {
+            Ok(HookAction::Continue) => {}
+            Ok(HookAction::Skip) => {
+                debug!("{} Entry {} skipped by the hook", self.id, number);
+            }
+            Ok(HookAction::Stop) => {
+                self.delivered(number);
+                info!("{} Stop requested by the hook", self.id);
+                self.stop_streaming().await;
+                return false;
+            }
+            Err(e) => {
+                error!("{} Error processing entry {}: {}", self.id, number, e);
+                self.notify_error(&e);
+                // The resume point is left at the failed entry
+                if self.hook_error_policy != HookErrorPolicy::Ignore {
+                    info!("{} Stopping after the hook failed", self.id);
+                    self.stop_streaming().await;
+                    self.hook_error = Some(e);
+                    return false;
+                }
+            }
+        }
+        self.delivered(number);
+        true
+    }
+
+    // dispatch_entry hands an entry to the workers, spawned on the first entry, and applies the
+    // results of the entries done
+    async fn dispatch_entry(&mut self, e: Entry) {
+        let pool = match &mut self.workers {
+            Some(pool) => pool,
+            None => {
+                let hook = self.concurrent_process_entry_hook.clone().unwrap();
+                let retries = match self.hook_error_policy {
+                    HookErrorPolicy::Retry(n) => n,
+                    _ => 0,
+                };
+                self.workers
+                    .insert(WorkerPool::new(&self.id, self.worker_count, hook, retries))
+            }
+        };
+        let seq = pool.next_seq;
+        pool.next_seq += 1;
+        pool.in_flight.push_back((seq, e.number));
+        // The workers only stop when the pool is dropped
+        let _ = pool.jobs.send((seq, e)).await;
+
+        self.complete_entries(false).await;
+    }
+
+    // await_data applies the results of the workers while waiting for data to read, so the
+    // resume point doesn't lag behind while the stream is idle. Returns false if the streaming
+    // was stopped.
+    async fn await_data(&mut self) -> bool {
+        while let (Some(pool), Some(conn)) = (&mut self.workers, &mut self.conn) {
+            if pool.in_flight.is_empty() {
+                break;
+            }
+            tokio::select! {
+                biased;
+                // A read error is returned by the next read
+                _ = conn.fill_buf() => break,
+                next = pool.results.recv() => match next {
+                    Some((seq, res)) => pool.done.insert(seq, res),
+                    None => break,
+                },
+            };
+            if !self.complete_entries(false).await {
+                return false;
+            }
+        }
+        true
+    }
+
+    // complete_entries applies the results of the entries processed by the workers in the
+    // dispatch order, waiting for all of them if wait is set. The results past an entry stopping
+    // the streaming are dropped, those entries are streamed again on resume. Returns false if the
+    // streaming was stopped.
+    async fn complete_entries(&mut self, wait: bool) -> bool {
+        let mut streaming = true;
+        while let Some(pool) = &mut self.workers {
+            let Some(&(seq, number)) = pool.in_flight.front() else {
+                break;
+            };
+            let Some(res) = pool.done.remove(&seq) else {
+                let next = match wait || !streaming {
+                    true => pool.results.recv().await,
+                    false => pool.results.try_recv().ok(),
+                };
+                match next {
+                    Some((seq, res)) => {
+                        pool.done.insert(seq, res);
+                        continue;
+                    }
+                    None => break,
+                }
+            };
+            pool.in_flight.pop_front();
+            if streaming {
+                streaming = self.apply_hook_result(number, res).await;
+            } else {
+                debug!("{} Dropping the result of entry {}", self.id, number);
+            }
+        }
+        streaming
+    }
+
+    // call_hook calls the async process entry hook if set, the process entry hook otherwise
+    async fn call_hook(
+        &mut self,
+        e: Entry,
+        span: tracing::Span,
+    ) -> Result<HookAction, ClientError> {
+        match &mut self.async_process_entry_hook {
+            Some(hook) => hook(e).instrument(span).await,
+            None => span.in_scope(|| (self.process_entry_hook)(e)),
+        }
+    }
+
+    // sequence_gap returns the expected entry number when verify_sequence is set and number isn't
+    // the next entry. The first entry of a bookmark started streaming isn't known, so it's not
+    // checked.
+    fn sequence_gap(&self, number: u64) -> Option<u64> {
+        if !self.verify_sequence || matches!(self.start_mode, StartMode::Bookmark(_)) {
+            return None;
+        }
+        (number != self.resume_from).then_some(self.resume_from)
+    }
+
+    // fetch_gap gets the entries from expected up to the received entry number, stopping the
+    // streaming to fetch them on the same connection. The streaming is restarted past the
+    // received entry, keeping the resume point at expected until the entries are delivered.
+    async fn fetch_gap(&mut self, expected: u64, number: u64) -> Result<Vec<Entry>, ClientError> {
+        info!(
+            "{} Fetching the missing entries {} to {}",
+            self.id,
+            expected,
+            number - 1
+        );
+        self.exec_command_stop().await?;
+        let entries = match self.get_entries_range(expected, number - 1).await {
+            Ok(entries) => entries,
+            Err(e) => {
+                // The streaming is restored from the missing entries on reconnect
+                self.streaming = true;
+                return Err(e);
+            }
+        };
+        self.exec_command(Command::CmdStart, self.stream_type, number + 1, None)
+            .await?;
+        Ok(entries)
+    }
+
+    // notify_error calls the on_error event listener, if any
+    fn notify_error(&self, error: &ClientError) {
+        if let Some(listener) = &self.event_listener {
+            listener.on_error(error);
+        }
+    }
+
+    // stop_streaming stops the streaming keeping the connection open for other commands
+    async fn stop_streaming(&mut self) {
+        if let Err(e) = self.exec_command_stop().await {
+            error!("{} Error stopping the streaming: {}", self.id, e);
+        }
+        self.started = false;
+        self.record_status();
+    }
+
+    // probe_connection checks an idle connection is alive with a header command, returning a
+    // network error to reconnect if the probe fails
+    async fn probe_connection(&mut self) -> Result<(), ClientError> {
+        info!(
+            "{} No data received in {:?}, probing connection",
+            self.id, self.idle_timeout
+        );
+
+        // A failed command closes the connection, keep the streaming state to restore it
+        let (started, streaming) = (self.started, self.streaming);
+        let res = self.exec_command_get_header().await;
+        self.started = started;
+        self.streaming = streaming;
+        self.record_status();
+
+        res.map(|_| ()).map_err(|e| {
+            ClientError::NetworkError(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                format!("idle connection probe failed: {}", e),
+            ))
+        })
+    }
+
+    // delivered moves the resume point past the entry number handed to the caller, from then on
+    // a bookmark started streaming is restored from the entry number
+    fn delivered(&mut self, number: u64) {
+        self.apply_pending_header();
+        self.last_delivered = Some(self.last_delivered.map_or(number, |last| last.max(number)));
+        if let StartMode::Bookmark(_) = self.start_mode {
+            self.start_mode = StartMode::Entry;
+            self.resume_from = number + 1;
+        }
+        self.resume_from = self.resume_from.max(number + 1);
+
+        if self.status == ClientStatus::CsSyncing
+            && self.total_entries > 0
+            && number + 1 >= self.total_entries
+        {
+            info!(
+                "{} Synced at entry {}, tailing new entries",
+                self.id, number
+            );
+            self.status = ClientStatus::CsSynced;
+            if let Some(listener) = &self.event_listener {
+                listener.on_synced(&self.id, number);
+            }
+        }
+    }
+
+    // is_duplicate returns whether an entry is dropped by the dedup_entries mode
+    fn is_duplicate(&self, number: u64) -> bool {
+        self.dedup_entries && self.last_delivered.is_some_and(|last| number <= last)
+    }
+
+    // status returns the streaming status of the client
+    pub fn status(&self) -> ClientStatus {
+        self.status
+    }
+
+    // is_connected returns whether the client is connected to the server
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    // is_started returns whether the client started reading the streamed entries
+    pub fn is_started(&self) -> bool {
+        self.started
+    }
+
+    // is_streaming returns whether the server is streaming the entries to the client
+    pub fn is_streaming(&self) -> bool {
+        self.streaming
+    }
+
+    // state returns the connected, started and streaming flags of the client
+    pub fn state(&self) -> ClientState {
+        ClientState {
+            connected: self.connected,
+            started: self.started,
+            streaming: self.streaming,
+        }
+    }
+
+    // client_id returns the client id, the local address of the latest connection or empty
+    // before connecting
+    pub fn client_id(&self) -> &str {
+        &self.id
+    }
+
+    // peer_addr returns the server address of the current connection, None when not connected
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr
+    }
+
+    // read_packet reads the next packet from the server connection and returns it if it's a data entry
+    async fn read_packet(&mut self) -> Result<Option<Entry>, ClientError> {
+        let Some(packet) = self.read_packet_type().await? else {
+            return Ok(None);
+        };
+        if !self.dispatch_packet(packet).await? {
+            return Ok(None);
+        }
+
+        if self.tee.is_some() {
+            self.tee_entry().await;
+        }
+
+        // Skipped entries move the resume point without decoding the data
+        if let Some(filter) = &self.entry_filter {
+            let fields = decode_entry_fields(&self.read_buffer);
+            if !filter(&fields) {
+                debug!("{} Skipping filtered entry {}", self.id, fields.number);
+                self.delivered(fields.number);
+                return Ok(None);
+            }
+        }
+
+        let e = self.decode_data_entry()?;
+        Ok(Some(e))
+    }
+
+    // read_packet_type reads the type of the next packet from the server connection, None if the
+    // connection was probed instead after the idle timeout
+    async fn read_packet_type(&mut self) -> Result<Option<u8>, ClientError> {
+        let conn = self
+            .conn
+            .as_mut()
+            .ok_or(ClientError::ClientNotStarted("Read entries not allowed."))?;
+
+        // Get the command result
+        let mut packet = [0u8; 1];
+        let read = conn.read_exact(&mut packet);
+        match self.idle_timeout {
+            Some(idle_timeout) => match timeout(idle_timeout, read).await {
+                Ok(res) => res?,
+                Err(_) => {
+                    self.probe_connection().await?;
+                    return Ok(None);
+                }
+            },
+            None => match self.socket_options.read_timeout {
+                Some(read_timeout) => timeout(read_timeout, read)
+                    .await
+                    .map_err(|_| std::io::Error::from(std::io::ErrorKind::TimedOut))??,
+                None => read.await?,
+            },
+        };
+        Ok(Some(packet[0]))
+    }
+
+    // dispatch_packet reads the rest of a streamed packet by its type. A data entry is left in the
+    // read buffer, returning true, and the other packets are skipped.
+    async fn dispatch_packet(&mut self, packet: u8) -> Result<bool, ClientError> {
+        match PacketType::from(packet) {
+            // Unknown packet types are also converted to PtPadding
+            PacketType::PtPadding if packet == PacketType::PtPadding as u8 => {
+                info!("Received packet type: {:?}", PacketType::PtPadding);
+                self.skip_padding().await?;
+            }
+            PacketType::PtPadding => {
+                self.coerce_unknown_packet(packet)?;
+                self.skip_padding().await?;
+            }
+            PacketType::PtHeader => {
+                info!("Received packet type: {:?}", PacketType::PtHeader);
+                let h = self.read_header_entry_from(packet).await?;
+                debug!("{} Ignoring header entry while streaming: {:?}", self.id, h);
+            }
+            PacketType::PtData => {
+                info!("Received packet type: {:?}", PacketType::PtData);
+                self.read_data_entry_bytes(packet).await?;
+                return Ok(true);
+            }
+            // Command responses aren't expected while streaming, they're read to keep the framing
+            // but not delivered
+            PacketType::PtDataRsp => {
+                info!("Received packet type: {:?}", PacketType::PtDataRsp);
+                self.read_data_entry_bytes(packet).await?;
+                let e = decode_entry_fields(&self.read_buffer);
+                debug!("{} Ignoring data response entry {}", self.id, e.number);
+            }
+            PacketType::PtResult => {
+                info!("Received packet type: {:?}", PacketType::PtResult);
+                let re = self.read_result_entry_from(packet).await?;
+                match re.error_num == u32::from(CommandError::CmdErrOK) {
+                    true => debug!("{} Ignoring result entry while streaming", self.id),
+                    false => error!(
+                        "{} Received error result while streaming: {}",
+                        self.id,
+                        String::from_utf8_lossy(&re.error_str)
+                    ),
+                }
+            }
+        }
+
+        Ok(false)
+    }
+
+    // tee_to writes the entries streamed from then on to writer as well, as a datastream file
+    // readable by FileStreamReader with the client page size and the server header. The entries
+    // are written as received, flushed every tee_flush_interval and by finish_tee. A client
+    // not streaming yet gets the header first if it's not cached.
+    pub async fn tee_to<W>(&mut self, writer: W) -> Result<(), ClientError>
+    where
+        W: AsyncWrite + AsyncSeek + Unpin + Send + 'static,
+    {
+        let header = match self.header.clone() {
+            Some(header) => header,
+            None => self.get_header().await?,
+        };
+        let tee = StreamTee::new(
+            Box::new(writer),
+            header,
+            self.page_size,
+            self.tee_flush_interval,
+        )
+        .await?;
+        self.tee = Some(tee);
+        Ok(())
+    }
+
+    // finish_tee flushes the entries teed and stops teeing the streamed entries
+    pub async fn finish_tee(&mut self) -> Result<(), ClientError> {
+        if let Some(mut tee) = self.tee.take() {
+            tee.flush().await?;
+        }
+        Ok(())
+    }
+
+    // tee_entry writes the data entry in the read buffer to the tee, duplicated entries aside.
+    // Teeing is stopped on a write error, without interrupting the streaming.
+    async fn tee_entry(&mut self) {
+        let number = decode_entry_fields(&self.read_buffer).number;
+        if self.is_duplicate(number) {
+            return;
+        }
+        let Some(tee) = &mut self.tee else {
+            return;
+        };
+        if let Err(e) = tee.write_entry(&self.read_buffer).await {
+            error!(
+                "{} Error teeing entry {}, teeing stopped: {}",
+                self.id, number, e
+            );
+            self.tee = None;
+            self.notify_error(&ClientError::NetworkError(e));
+        }
+    }
+
+    // coerce_unknown_packet checks a packet type read where a data or padding packet is expected.
+    // The packet types not defined by the protocol are coerced to padding, to be skipped, or fail
+    // with UnknownType in strict mode. The defined ones not expected there fail.
+    fn coerce_unknown_packet(&self, packet: u8) -> Result<(), ClientError> {
+        // Unknown packet types are converted to PtPadding, whose value is zero
+        if PacketType::from(packet) as u8 == packet {
+            return Err(ClientError::DecodeError(format!(
+                "unexpected packet type {:#04x}",
+                packet
+            )));
+        }
+        if self.strict {
+            return Err(crate::entry_data::DecodeError::UnknownType(packet as u32).into());
+        }
+        warn!(
+            "{} Unknown packet type {:#04x}, skipped as padding",
+            self.id, packet
+        );
+        Ok(())
+    }
+
+    // skip_padding discards the rest of a padding region, the zero bytes filling a data page up to
+    // its boundary, so the next packet is read framed. A padding region can't be longer than a
+    // data page (page_size), anything longer means the stream is misaligned.
+    async fn skip_padding(&mut self) -> Result<(), ClientError> {
+        let conn = self.conn.as_mut().unwrap();
+        let mut skipped: u64 = 1;
+
+        loop {
+            let buffer = conn.fill_buf().await?;
+            if buffer.is_empty() {
+                return Err(ClientError::NetworkError(std::io::Error::from(
+                    std::io::ErrorKind::UnexpectedEof,
+                )));
+            }
+
+            let padding = buffer
+                .iter()
+                .take_while(|b| **b == PacketType::PtPadding as u8)
+                .count();
+            let done = padding < buffer.len();
+            conn.consume(padding);
+            skipped += padding as u64;
+
+            if skipped > self.page_size {
+                return Err(ClientError::DecodeError(format!(
+                    "padding longer than a data page of {} bytes",
+                    self.page_size
+                )));
+            }
+            if done {
+                self.bytes_consumed += skipped;
+                debug!("{} Skipped {} padding bytes", self.id, skipped);
+                return Ok(());
+            }
+        }
+    }
+
+    // drain_data_packets discards the streamed packets until the next command result
+    async fn drain_data_packets(&mut self) -> Result<(), ClientError> {
+        loop {
+            let conn = self.conn.as_mut().unwrap();
+            let buffer = conn.fill_buf().await?;
+            match buffer.first() {
+                None => {
+                    return Err(ClientError::NetworkError(std::io::Error::from(
+                        std::io::ErrorKind::UnexpectedEof,
+                    )))
+                }
+                Some(&packet) if packet == PacketType::PtResult as u8 => return Ok(()),
+                Some(&packet)
+                    if packet == PacketType::PtData as u8
+                        || packet == PacketType::PtDataRsp as u8 =>
+                {
+                    conn.consume(1);
+                    self.read_data_entry_bytes(packet).await?;
+                    let e = decode_entry_fields(&self.read_buffer);
+                    debug!("{} Discarding entry {}", self.id, e.number);
+                }
+                Some(&packet) if packet == PacketType::PtPadding as u8 => {
+                    conn.consume(1);
+                    self.skip_padding().await?;
+                }
+                Some(&packet) => {
+                    self.coerce_unknown_packet(packet)?;
+                    self.conn.as_mut().unwrap().consume(1);
+                    self.skip_padding().await?;
+                }
+            }
+        }
+    }
+
+    // close_connection closes connection to the server and resets the client state
+    pub fn close_connection(&mut self) {
+        self.disconnect();
+        self.needs_reconnect = false;
+        self.started = false;
+        self.streaming = false;
+        self.status = ClientStatus::CsStopped;
+        self.record_status();
+    }
+
+    // abort_connection closes a connection left in the middle of a response by an aborted command,
+    // stopping the streaming it was reading. The connection is opened again on the next command.
+    fn abort_connection(&mut self) {
+        self.disconnect();
+        self.needs_reconnect = true;
+        if self.streaming {
+            self.streaming = false;
+            self.status = ClientStatus::CsStopped;
+            self.record_status();
+        }
+    }
+
+    // disconnect closes connection to the server keeping the streaming state to be restored on reconnect
+    fn disconnect(&mut self) {
+        if self.connected {
+            info!("{} Close connection", self.id);
+            if let Some(listener) = &self.event_listener {
+                listener.on_disconnected(&self.id);
+            }
+        }
+        // Dropping the stream closes the socket
+        self.conn = None;
+        self.peer_addr = None;
+        self.connected = false;
+        self.record_status();
+    }
+
+    // record_status sets the client status gauge when the metrics feature is enabled, and reports
+    // the state to the event listener if it changed since the latest report
+    fn record_status(&mut self) {
+        let state = self.state();
+        if state != self.reported_state {
+            self.reported_state = state;
+            if let Some(listener) = &self.event_listener {
+                listener.on_state_change(&self.id, state);
+            }
+        }
+
+        #[cfg(feature = "metrics")]
+        {
+            let status = match (self.connected, self.streaming) {
+                (false, _) => 0,
+                (true, false) => 1,
+                (true, true) => 2,
+            };
+            metrics::gauge!(METRIC_CLIENT_STATUS).set(status as f64);
+        }
+    }
+
+    // exec_command_start executes client TCP command to start streaming from entry
+    pub async fn exec_command_start(&mut self, from_entry: u64) -> Result<(), ClientError> {
+        match self
+            .exec_command(Command::CmdStart, self.stream_type, from_entry, None)
+            .await
+        {
+            Ok(_) => {
+                self.resume_from = from_entry;
+                self.start_mode = StartMode::Entry;
+                Ok(())
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    // exec_command_start_bookmark executes client TCP command to start streaming from bookmark
+    pub async fn exec_command_start_bookmark(
+        &mut self,
+        from_bookmark: impl Into<Bookmark>,
+    ) -> Result<(), ClientError> {
+        let from_bookmark = encode_bookmark(from_bookmark.into().encode())?;
+        match self
+            .exec_command(
+                Command::CmdStartBookmark,
+                self.stream_type,
+                0,
+                Some(from_bookmark.clone()),
+            )
+            .await
+        {
+            Ok(_) => {
+                self.start_mode = StartMode::Bookmark(from_bookmark);
+                Ok(())
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    // exec_command_stop executes client TCP command to stop streaming. Over TLS call it before
+    // dropping a streaming client, since drop can't send the stop command on a TLS connection.
+    pub async fn exec_command_stop(&mut self) -> Result<(), ClientError> {
+        match self
+            .exec_command(Command::CmdStop, self.stream_type, 0, None)
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    // exec_command_get_header executes client TCP command to get the header
+    pub async fn exec_command_get_header(&mut self) -> Result<HeaderEntry, ClientError> {
+        match self
+            .exec_command_retry(Command::CmdHeader, self.stream_type, 0, None)
+            .await
+        {
+            Ok((header, _)) => self.set_header(header),
+            Err(e) => Err(e),
+        }
+    }
+
+    // exec_command_get_header_for executes client TCP command to get the header of stream_type,
+    // the header is not cached as it may not be the client stream type
+    pub async fn exec_command_get_header_for(
+        &mut self,
+        stream_type: StreamType,
+    ) -> Result<HeaderEntry, ClientError> {
+        match self
+            .exec_command_retry(Command::CmdHeader, stream_type, 0, None)
+            .await
+        {
+            Ok((header, _)) => Ok(header),
+            Err(e) => Err(e),
+        }
+    }
+
+    // ping measures the round trip to the server with a header command, from sending it until its
+    // result entry is received. The header isn't cached, and pinging isn't allowed while streaming
+    // as the response would be mixed with the streamed entries. On timeout the socket is dropped, as
+    // a late response would be read by the next command, but the client state is kept and the next
+    // command opens a new connection.
+    pub async fn ping(&mut self) -> Result<Duration, ClientError> {
+        if !self.connected && self.needs_reconnect {
+            self.connect_server().await?;
+        }
+        if !self.connected {
+            return Err(ClientError::ClientNotStarted("Ping not allowed."));
+        }
+        if self.streaming {
+            return Err(ClientError::InvalidCommand(
+                "ping not allowed while streaming",
+            ));
+        }
+
+        let frame = CommandFrame::new(Command::CmdHeader, self.stream_type);
+        let sent_at = Instant::now();
+        self.conn
+            .as_mut()
+            .unwrap()
+            .write_all(frame.as_bytes())
+            .await?;
+
+        let res = timeout(self.command_timeout, async {
+            let re = self.read_result_entry().await?;
+            let rtt = sent_at.elapsed();
+            if re.error_num != u32::from(CommandError::CmdErrOK) {
+                return Err(ClientError::CommandFailed(
+                    CommandError::from(re.error_num),
+                    re.describe(),
+                ));
+            }
+            // The header is read so the connection is ready for the next command
+            self.read_header_entry().await?;
+            Ok(rtt)
+        })
+        .await;
+        match res {
+            Ok(res) => res,
+            Err(_) => {
+                error!("{} Timeout waiting for the ping response", self.id);
+                self.abort_connection();
+                Err(ClientError::Timeout)
+            }
+        }
+    }
+
+    // set_header caches the header received, failing if its version isn't supported
+    fn set_header(&mut self, header: HeaderEntry) -> Result<HeaderEntry, ClientError> {
+        if !self.supported_versions.contains(&header.version) {
+            error!(
+                "{} Unsupported stream version {}, supported {:?}",
+                self.id, header.version, self.supported_versions
+            );
+            return Err(ClientError::UnsupportedVersion(header.version));
+        }
+        self.total_entries = header.total_entries;
+        self.header = Some(header.clone());
+        self.header_fetched_at = Some(Instant::now());
+        Ok(header)
+    }
+
+    // get_header returns the cached header if it was fetched within the header_cache_ttl,
+    // executing a header command otherwise
+    pub async fn get_header(&mut self) -> Result<HeaderEntry, ClientError> {
+        match (&self.header, self.header_fetched_at) {
+            (Some(header), Some(fetched_at)) if fetched_at.elapsed() < self.header_cache_ttl => {
+                Ok(header.clone())
+            }
+            _ => self.exec_command_get_header().await,
+        }
+    }
+
+    // refresh_header fetches the header with a header command, updating the cache
+    pub async fn refresh_header(&mut self) -> Result<HeaderEntry, ClientError> {
+        self.exec_command_get_header().await
+    }
+
+    // bytes_consumed returns the bytes of the packets read from the server: command results,
+    // headers, data entries and padding
+    pub fn bytes_consumed(&self) -> u64 {
+        self.bytes_consumed
+    }
+
+    // progress returns the fraction of the stream total length consumed, from 0.0 to 1.0, or
+    // None before a header with the total length is received. It's only meaningful for a
+    // stream read from its first entry.
+    pub fn progress(&self) -> Option<f64> {
+        match &self.header {
+            Some(header) if header.total_length > 0 => {
+                Some((self.bytes_consumed as f64 / header.total_length as f64).min(1.0))
+            }
+            _ => None,
+        }
+    }
+
+    // header returns the header from the latest header command, if any
+    pub fn header(&self) -> Option<&HeaderEntry> {
+        self.header.as_ref()
+    }
+
+    // chain_id returns the system identifier from the latest header command, if any
+    pub fn chain_id(&self) -> Option<ChainId> {
+        self.header.as_ref().map(|header| header.system_id)
+    }
+
+    // total_entries returns the total entries from the latest header command. With
+    // skip_initial_header it's 0 until a header is received, calling it gets the header while
+    // streaming.
+    pub fn total_entries(&self) -> u64 {
+        if self.skip_initial_header && self.header.is_none() {
+            self.header_wanted.store(true, Ordering::Relaxed);
+        }
+        self.total_entries
+    }
+
+    // follow_from sets the entry number to start the streaming from, later moved past every
+    // delivered entry so a reconnection resumes where the stream was left
+    pub fn follow_from(&mut self, entry: u64) {
+        self.resume_from = entry;
+    }
+
+    // resume_from returns the entry number the streaming will be started or restored from
+    pub fn resume_from(&self) -> u64 {
+        self.resume_from
+    }
+
+    // start_mode returns how the streaming will be restored on reconnect
+    pub fn start_mode(&self) -> &StartMode {
+        &self.start_mode
+    }
+
+    // checkpoint returns the streaming progress, to be persisted and resumed after a restart by
+    // a client built with StreamClientBuilder::resume_from
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            last_delivered: self.last_delivered,
+            resume_from: self.resume_from,
+            start_mode: self.start_mode.clone(),
+        }
+    }
+
+    // exec_command_get_entry executes client TCP command to get an entry
+    pub async fn exec_command_get_entry(&mut self, from_entry: u64) -> Result<Entry, ClientError> {
+        self.exec_command_get_entry_for(self.stream_type, from_entry)
+            .await
+    }
+
+    // exec_command_get_entry_with_cancel works like exec_command_get_entry, failing with Cancelled
+    // when token is cancelled before the response is received. The connection of a cancelled
+    // command is closed, as its response may still come, and opened again on the next command.
+    pub async fn exec_command_get_entry_with_cancel(
+        &mut self,
+        from_entry: u64,
+        token: Option<&CancellationToken>,
+    ) -> Result<Entry, ClientError> {
+        let Some(token) = token else {
+            return self.exec_command_get_entry(from_entry).await;
+        };
+
+        tokio::select! {
+            res = self.exec_command_get_entry(from_entry) => res,
+            _ = token.cancelled() => {
+                info!("{} Get entry {} cancelled", self.id, from_entry);
+                self.close_connection();
+                self.needs_reconnect = true;
+                Err(ClientError::Cancelled)
+            }
+        }
+    }
+
+    // exec_command_get_entry_for executes client TCP command to get an entry of stream_type
+    pub async fn exec_command_get_entry_for(
+        &mut self,
+        stream_type: StreamType,
+        from_entry: u64,
+    ) -> Result<Entry, ClientError> {
+        match self
+            .exec_command_retry(Command::CmdEntry, stream_type, from_entry, None)
+            .await
+        {
+            Ok((_, entry)) => Ok(entry),
+            Err(e) => Err(e),
+        }
+    }
+
+    // get_last_entry gets the latest entry of the stream from the header total entries, returns
+    // EntryNotFound for an empty stream
+    pub async fn get_last_entry(&mut self) -> Result<Entry, ClientError> {
+        let header = self.get_header().await?;
+        match header.total_entries.checked_sub(1) {
+            Some(last) => self.exec_command_get_entry(last).await,
+            None => Err(ClientError::EntryNotFound),
+        }
+    }
+
+    // exec_command_get_bookmark executes client TCP command to get a bookmark
+    pub async fn exec_command_get_bookmark(
+        &mut self,
+        from_bookmark: impl Into<Bookmark>,
+    ) -> Result<Entry, ClientError> {
+        self.exec_command_get_bookmark_for(self.stream_type, from_bookmark)
+            .await
+    }
+
+    // exec_command_get_bookmark_for executes client TCP command to get a bookmark of stream_type
+    pub async fn exec_command_get_bookmark_for(
+        &mut self,
+        stream_type: StreamType,
+        from_bookmark: impl Into<Bookmark>,
+    ) -> Result<Entry, ClientError> {
+        let from_bookmark = encode_bookmark(from_bookmark.into().encode())?;
+        match self
+            .exec_command_retry(Command::CmdBookmark, stream_type, 0, Some(from_bookmark))
+            .await
+        {
+            Ok((_, entry)) => Ok(entry),
+            Err(e) => Err(e),
+        }
+    }
+
+    // resolve_bookmark returns the number of the entry of a bookmark, to start the streaming from
+    // it with exec_command_start. Fails with BookmarkNotFound if the bookmark doesn't exist.
+    pub async fn resolve_bookmark(
+        &mut self,
+        bookmark: impl Into<Bookmark>,
+    ) -> Result<u64, ClientError> {
+        self.exec_command_get_bookmark(bookmark)
+            .await
+            .map(|e| e.number)
+    }
+
+    // get_bookmark_or_after gets the entry of a bookmark like exec_command_get_bookmark when it
+    // exists. Otherwise, instead of failing with BookmarkNotFound, it starts streaming from the
+    // bookmark and returns the first entry at or after it, e.g. the bookmark of the next block
+    // for consumers tailing the stream by time. Timeout is returned when no entry is streamed
+    // within the command timeout, closing the connection as it's left streaming.
+    pub async fn get_bookmark_or_after(
+        &mut self,
+        bookmark: impl Into<Bookmark>,
+    ) -> Result<Entry, ClientError> {
+        if self.streaming {
+            return Err(ClientError::InvalidCommand(
+                "Bookmark fetch not allowed while streaming.",
+            ));
+        }
+
+        let bookmark = bookmark.into();
+        match self.exec_command_get_bookmark(bookmark.clone()).await {
+            Err(ClientError::BookmarkNotFound) => {}
+            res => return res,
+        }
+        debug!(
+            "{} Bookmark {:?} not found, streaming from it",
+            self.id, bookmark
+        );
+
+        // The follow streaming state is kept, so the start command is sent directly
+        let from_bookmark = encode_bookmark(bookmark.encode())?;
+        self.exec_command(
+            Command::CmdStartBookmark,
+            self.stream_type,
+            0,
+            Some(from_bookmark),
+        )
+        .await?;
+
+        // The first entry is returned, whatever the entry filter, and it's not teed as it's not
+        // part of the followed stream
+        let (filter, tee) = (self.entry_filter.take(), self.tee.take());
+        let res = timeout(self.command_timeout, async {
+            loop {
+                match self.read_packet().await {
+                    Ok(Some(e)) => break Ok(e),
+                    Ok(None) => {}
+                    Err(e) => break Err(e),
+                }
+            }
+        })
+        .await
+        .unwrap_or(Err(ClientError::Timeout));
+        self.entry_filter = filter;
+        self.tee = tee;
+        let entry = match res {
+            Ok(entry) => entry,
+            Err(e) => {
+                error!(
+                    "{} Error getting the entry after bookmark {:?}, closing connection: {}",
+                    self.id, bookmark, e
+                );
+                self.abort_connection();
+                return Err(e);
+            }
+        };
+
+        self.exec_command_stop().await?;
+
+        Ok(entry)
+    }
+
+    // entries_between_bookmarks gets the entries from the from bookmark entry up to the to bookmark
+    // entry, not included, streaming them with a single start command. Bookmarks encoding block
+    // numbers or timestamps select the entries of a block or time range. BookmarkNotFound is
+    // returned when the last entry of the stream is reached without finding the to bookmark. The
+    // connection is closed if the range can't be read, as it's left streaming.
+    pub async fn entries_between_bookmarks(
+        &mut self,
+        from: Bookmark,
+        to: Bookmark,
+    ) -> Result<Vec<Entry>, ClientError> {
+        if self.streaming {
+            return Err(ClientError::InvalidCommand(
+                "Range fetch not allowed while streaming.",
+            ));
+        }
+
+        let from = encode_bookmark(from.encode())?;
+        let to = encode_bookmark(to.encode())?;
+        let header = self.get_header().await?;
+        if header.total_entries == 0 {
+            return Err(ClientError::BookmarkNotFound);
+        }
+        let last = header.total_entries - 1;
+
+        // The follow streaming state is kept, so the start command is sent directly
+        self.exec_command(Command::CmdStartBookmark, self.stream_type, 0, Some(from))
+            .await?;
+
+        // Every entry of the range is returned, whatever the entry filter, and it's not teed as
+        // it's not part of the followed stream
+        let (filter, tee) = (self.entry_filter.take(), self.tee.take());
+        let mut entries = Vec::new();
+        let res = loop {
+            match timeout(self.command_timeout, self.read_packet()).await {
+                Ok(Ok(Some(e))) if e.entry_type == EntryType::Bookmark && e.data == to => {
+                    break Ok(true)
+                }
+                Ok(Ok(Some(e))) => {
+                    let number = e.number;
+                    entries.push(e);
+                    if number >= last {
+                        break Ok(false);
+                    }
+                }
+                Ok(Ok(None)) => {}
+                Ok(Err(e)) => break Err(e),
+                Err(_) => break Err(ClientError::Timeout),
+            }
+        };
+        self.entry_filter = filter;
+        self.tee = tee;
+        let found = match res {
+            Ok(found) => found,
+            Err(e) => {
+                error!(
+                    "{} Error getting the entries between bookmarks, closing connection: {}",
+                    self.id, e
+                );
+                self.abort_connection();
+                return Err(e);
+            }
+        };
+
+        self.exec_command_stop().await?;
+        match found {
+            true => Ok(entries),
+            false => Err(ClientError::BookmarkNotFound),
+        }
+    }
+
+    // get_entries_range gets the entries from to to (both included) streaming them with a single
+    // start command instead of a command per entry. The range is cut at the last entry of a
+    // freshly fetched header, returning EntryNotFound only when from is past it. The connection
+    // is closed if the range can't be read, as it's left streaming.
+    pub async fn get_entries_range(
+        &mut self,
+        from: u64,
+        to: u64,
+    ) -> Result<Vec<Entry>, ClientError> {
+        if self.streaming {
+            return Err(ClientError::InvalidCommand(
+                "Range fetch not allowed while streaming.",
+            ));
+        }
+        if from > to {
+            return Ok(Vec::new());
+        }
+
+        let header = self.refresh_header().await?;
+        if from >= header.total_entries {
+            return Err(ClientError::EntryNotFound);
+        }
+        let last = to.min(header.total_entries - 1);
+
+        // The follow streaming state is kept, so the start command is sent directly
+        self.exec_command(Command::CmdStart, self.stream_type, from, None)
+            .await?;
+
+        // Every entry of the range is returned, whatever the entry filter, and it's not teed as
+        // it's not part of the followed stream
+        let (filter, tee) = (self.entry_filter.take(), self.tee.take());
+        // The range comes from the caller, so only a bounded part of it is allocated up front
+        let mut entries = Vec::with_capacity((last - from).saturating_add(1).min(1024) as usize);
+        let res = loop {
+            match timeout(self.command_timeout, self.read_packet()).await {
+                Ok(Ok(Some(e))) => {
+                    let number = e.number;
+                    if number <= last {
+                        entries.push(e);
+                    }
+                    if number >= last {
+                        break Ok(());
+                    }
+                }
+                Ok(Ok(None)) => {}
+                Ok(Err(e)) => break Err(e),
+                Err(_) => break Err(ClientError::Timeout),
+            }
+        };
+        self.entry_filter = filter;
+        self.tee = tee;
+        if let Err(e) = res {
+            error!(
+                "{} Error getting the entries {} to {}, closing connection: {}",
+                self.id, from, last, e
+            );
+            self.abort_connection();
+            return Err(e);
+        }
+
+        self.exec_command_stop().await?;
+
+        Ok(entries)
+    }
+
+    // server_capabilities probes which read-only commands the server accepts, a command is
+    // unsupported when answered with CmdErrInvalidCommand. Other command errors, like an entry
+    // or bookmark not found, mean the command is supported.
+    pub async fn server_capabilities(&mut self) -> Result<ServerCapabilities, ClientError> {
+        if self.streaming {
+            return Err(ClientError::InvalidCommand(
+                "Probing the server not allowed while streaming.",
+            ));
+        }
+        self.connect_server().await?;
+
+        Ok(ServerCapabilities {
+            header: self.probe_command(Command::CmdHeader, None).await?,
+            entry: self.probe_command(Command::CmdEntry, None).await?,
+            bookmark: self
+                .probe_command(Command::CmdBookmark, Some(vec![0]))
+                .await?,
+        })
+    }
+
+    // probe_command checks the server accepts a command. The server closes the connection after
+    // rejecting a command, so it's reconnected for the next one.
+    async fn probe_command(
+        &mut self,
+        cmd: Command,
+        bookmark: Option<Vec<u8>>,
+    ) -> Result<bool, ClientError> {
+        let res = self.exec_command(cmd, self.stream_type, 0, bookmark).await;
+        let supported = command_supported(res)?;
+        if !supported {
+            self.close_connection();
+            self.connect_server().await?;
+        }
+        Ok(supported)
+    }
+
+    // snapshot gets every entry currently in the stream, from the first one up to the last entry
+    // of a freshly fetched header
+    pub async fn snapshot(&mut self) -> Result<Vec<Entry>, ClientError> {
+        Ok(self.snapshot_with_limit(usize::MAX).await?.entries)
+    }
+
+    // snapshot_with_limit gets the entries currently in the stream like snapshot, up to
+    // max_entries to bound the memory used. Cut snapshots are flagged as partial.
+    pub async fn snapshot_with_limit(
+        &mut self,
+        max_entries: usize,
+    ) -> Result<Snapshot, ClientError> {
+        let header = self.refresh_header().await?;
+        let total = header.total_entries;
+        let count = total.min(max_entries as u64);
+        let partial = count < total;
+
+        let entries = match count {
+            0 => Vec::new(),
+            _ => self.get_entries_range(0, count - 1).await?,
+        };
+
+        Ok(Snapshot { entries, partial })
+    }
+
+    // exec_command_retry executes a one-shot command, reconnecting and re-issuing it when it fails
+    // with a network error up to command_retries times. It's not retried while streaming, as the
+    // reconnection restores the streaming and a second start would be sent.
+    async fn exec_command_retry(
+        &mut self,
+        cmd: Command,
+        stream_type: StreamType,
+        from_entry: u64,
+        from_bookmark: Option<Vec<u8>>,
+    ) -> Result<(HeaderEntry, Entry), ClientError> {
+        let mut retries = 0;
+        loop {
+            match self
+                .exec_command(cmd, stream_type, from_entry, from_bookmark.clone())
+                .await
+            {
+                Err(ClientError::NetworkError(e))
+                    if retries < self.command_retries && !self.streaming =>
+                {
+                    retries += 1;
+                    error!(
+                        "{} Command {:?} failed: {}, reconnecting (retry {}/{})",
+                        self.id, cmd, e, retries, self.command_retries
+                    );
+                    self.disconnect();
+                    self.connect_server().await?;
+                }
+                res => return res,
+            }
+        }
+    }
+
+    // exec_command executes a client TCP command, reconnecting first if the connection was closed
+    // by an aborted command. A client never connected fails with ClientNotStarted.
+    async fn exec_command(
+        &mut self,
+        cmd: Command,
+        stream_type: StreamType,
+        from_entry: u64,
+        from_bookmark: Option<Vec<u8>>,
+    ) -> Result<(HeaderEntry, Entry), ClientError> {
+        if !self.connected && self.needs_reconnect {
+            self.connect_server().await?;
+        }
+        self.send_command(cmd, stream_type, from_entry, from_bookmark)
+            .await
+    }
+
+    // send_command executes a valid client TCP command with deferred command result possibility,
+    // within a command span to correlate the logs of the command
+    #[instrument(
+        name = "command",
+        skip_all,
+        fields(client_id = %self.id, command = ?cmd, from_entry = from_entry)
+    )]
+    async fn send_command(
+        &mut self,
+        cmd: Command,
+        stream_type: StreamType,
+        from_entry: u64,
+        from_bookmark: Option<Vec<u8>>,
+    ) -> Result<(HeaderEntry, Entry), ClientError> {
+        info!("{} Executing command {:?}...", self.id, cmd,);
+
+        // Check status of the client
+        if !self.connected {
+            info!("Execute command not allowed. Client is not started");
+            return Err(ClientError::ClientNotStarted(
+                "Execute command not allowed.",
+            ));
+        }
+
+        let conn = self.conn.as_mut().unwrap();
+
+        // Build the command with its parameters
+        let frame = CommandFrame::new(cmd, stream_type);
+        let frame = match cmd {
+            Command::CmdStart => {
+                info!("{} ...from entry {}", self.id, from_entry);
+                frame.from_entry(from_entry)
+            }
+            Command::CmdEntry => {
+                info!("{} ...get entry {}", self.id, from_entry);
+                frame.from_entry(from_entry)
+            }
+            Command::CmdStartBookmark | Command::CmdBookmark => {
+                info!("{} ...bookmark {:?}", self.id, from_bookmark);
+                match &from_bookmark {
+                    Some(bookmark) => frame.bookmark(bookmark),
+                    None => frame,
+                }
+            }
+            _ => frame,
+        };
+
+        // Send command
+        conn.write_all(frame.as_bytes()).await?;
+
+        // Get the command result and data response
+        match timeout(
+            self.command_timeout,
+            self.read_command_response(cmd, from_entry),
+        )
+        .await
+        {
+            Ok(res) => res,
+            Err(_) => {
+                error!("{} Timeout waiting for command {:?} response", self.id, cmd);
+                self.close_connection();
+                Err(ClientError::Timeout)
+            }
+        }
+    }
+
+    // read_command_response reads the command result and the data response of a command
+    async fn read_command_response(
+        &mut self,
+        cmd: Command,
+        from_entry: u64,
+    ) -> Result<(HeaderEntry, Entry), ClientError> {
+        let mut header: HeaderEntry = Default::default();
+        let mut entry: Entry = Default::default();
+
+        // Entries streamed before a stop command was processed are still on the way, the
+        // streaming is only stopped once its result is received
+        if matches!(cmd, Command::CmdStop) {
+            self.drain_data_packets().await?;
+        }
+
+        // Get the command result
+        let re = self.read_result_entry().await?;
+        if re.error_num != u32::from(CommandError::CmdErrOK) {
+            let description = re.describe();
+            error!("{} Command {:?} failed: {}", self.id, cmd, description);
+
+            // The server confirms it's not streaming to this client
+            let error = CommandError::from(re.error_num);
+            if matches!(cmd, Command::CmdStop) && error == CommandError::CmdErrAlreadyStopped {
+                self.streaming = false;
+                self.status = ClientStatus::CsStopped;
+                self.record_status();
+            }
+            return Err(ClientError::CommandFailed(error, description));
+        }
+        debug!("Result entry: {:?}", re);
+
+        // Get the data response and update streaming flag
+        match cmd {
+            Command::CmdStart => {
+                self.streaming = true;
+                self.from_stream = from_entry;
+                self.status = match self.total_entries > 0 && from_entry >= self.total_entries {
+                    true => ClientStatus::CsSynced,
+                    false => ClientStatus::CsSyncing,
+                };
+            }
+            Command::CmdStartBookmark => {
+                self.streaming = true;
+                self.status = ClientStatus::CsSyncing;
+            }
+            Command::CmdStop => {
+                self.streaming = false;
+                self.status = ClientStatus::CsStopped;
+            }
+            Command::CmdHeader => {
+                header = self.read_header_entry().await?;
+            }
+            Command::CmdEntry => {
+                let e = self.read_data_rsp_entry().await?;
+                if e.entry_type == EntryType::NotFound {
+                    return Err(ClientError::EntryNotFound);
+                }
+                entry = e;
+            }
+            Command::CmdBookmark => {
+                let e = self.read_data_rsp_entry().await?;
+                if e.entry_type == EntryType::NotFound {
+                    return Err(ClientError::BookmarkNotFound);
+                }
+                entry = e;
+            }
+        }
+        self.record_status();
+
+        Ok((header, entry))
+    }
+}
+
+// Dropping a streaming client sends a best-effort stop command, without waiting for its result,
+// and shuts down the socket so the server frees the connection promptly. Errors are ignored.
+// The stop command is only sent over plain TCP and Unix socket connections: a TLS record can't
+// be written without awaiting the stream, so a TLS connection is shut down without the stop
+// command or a close_notify alert. Call exec_command_stop before dropping a TLS client to stop
+// the streaming cleanly.
+impl Drop for StreamClient {
+    fn drop(&mut self) {
+        let Some(conn) = self.conn.take() else {
+            return;
+        };
+        let conn = conn.get_ref();
+
+        if self.streaming {
+            let frame = CommandFrame::new(Command::CmdStop, self.stream_type);
+            if let Err(e) = conn.try_write_plain(frame.as_bytes()) {
+                debug!("{} Stop command not sent on drop: {}", self.id, e);
+            }
+        }
+        let _ = conn.shutdown();
+    }
+}
+
+// StreamClientBuilder type to configure and build a StreamClient
+pub struct StreamClientBuilder {
+    server: Option<String>,
+    servers: Vec<String>,
+    stream_type: StreamType,
+    backoff: Backoff,
+    command_timeout: Duration,
+    connect_timeout: Option<Duration>,
+    command_retries: u32,
+    header_cache_ttl: Duration,
+    concurrent_header: bool,
+    skip_initial_header: bool,
+    idle_timeout: Option<Duration>,
+    socket_options: SocketOptions,
+    rate_limit: Option<u32>,
+    tee_flush_interval: Duration,
+    #[cfg(feature = "compression")]
+    compression: Compression,
+    capture_raw: bool,
+    timestamp_entries: bool,
+    verify_checksums: bool,
+    strict: bool,
+    supported_versions: Vec<u8>,
+    read_buffer_size: usize,
+    read_ahead_size: usize,
+    max_entry_size: usize,
+    page_size: u64,
+    dedup_entries: bool,
+    checkpoint: Checkpoint,
+    verify_sequence: bool,
+    fill_gaps: bool,
+    process_entry_hook: ProcessEntryFunc,
+    async_process_entry_hook: Option<AsyncProcessEntryFunc>,
+    concurrent_process_entry_hook: Option<ConcurrentProcessEntryFunc>,
+    worker_count: usize,
+    hook_error_policy: HookErrorPolicy,
+    event_listener: Option<Box<dyn EventListener>>,
+    #[cfg(feature = "tls")]
+    tls: bool,
+    #[cfg(feature = "tls")]
+    tls_root_cert: Option<Vec<u8>>, // DER root certificate, None to use the webpki roots
+    #[cfg(feature = "tls")]
+    tls_server_name: Option<String>, // SNI hostname, None to use the server address host
+}
+
+impl Default for StreamClientBuilder {
+    fn default() -> Self {
+        StreamClientBuilder {
+            server: None,
+            servers: Vec::new(),
+            stream_type: StreamType::Sequencer,
+            backoff: Backoff {
+                base_delay: DEFAULT_RECONNECT_DELAY,
+                max_delay: DEFAULT_MAX_RECONNECT_DELAY,
+                max_retries: None,
+                jitter: DEFAULT_RECONNECT_JITTER,
+            },
+            command_timeout: DEFAULT_COMMAND_TIMEOUT,
+            connect_timeout: None,
+            command_retries: 0,
+            header_cache_ttl: DEFAULT_HEADER_CACHE_TTL,
+            tee_flush_interval: DEFAULT_TEE_FLUSH_INTERVAL,
+            concurrent_header: false,
+            skip_initial_header: false,
+            idle_timeout: None,
+            socket_options: SocketOptions::default(),
+            rate_limit: None,
+            #[cfg(feature = "compression")]
+            compression: Compression::None,
+            capture_raw: false,
+            timestamp_entries: false,
+            verify_checksums: false,
+            strict: false,
+            supported_versions: SUPPORTED_VERSIONS.to_vec(),
+            read_buffer_size: DEFAULT_READ_BUFFER_SIZE,
+            read_ahead_size: DEFAULT_READ_AHEAD_SIZE,
+            max_entry_size: DEFAULT_MAX_ENTRY_SIZE,
+            page_size: PAGE_DATA_SIZE,
+            dedup_entries: false,
+            checkpoint: Checkpoint::default(),
+            verify_sequence: false,
+            fill_gaps: false,
+            process_entry_hook: Box::new(print_received_entry),
+            async_process_entry_hook: None,
+            concurrent_process_entry_hook: None,
+            worker_count: 1,
+            hook_error_policy: HookErrorPolicy::default(),
+            event_listener: None,
+            #[cfg(feature = "tls")]
+            tls: false,
+            #[cfg(feature = "tls")]
+            tls_root_cert: None,
+            #[cfg(feature = "tls")]
+            tls_server_name: None,
+        }
+    }
+}
+
+impl StreamClientBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // server sets the server address to connect (host:port)
+    pub fn server(mut self, server: String) -> Self {
+        self.server = Some(server);
+        self
+    }
+
+    // servers sets the server addresses to connect (host:port), the client connects to the first
+    // and rotates to the next one when a connection fails or drops. It takes precedence over server.
+    pub fn servers(mut self, servers: Vec<String>) -> Self {
+        self.servers = servers;
+        self
+    }
+
+    // stream_type sets the stream type sent with every command
+    pub fn stream_type(mut self, stream_type: StreamType) -> Self {
+        self.stream_type = stream_type;
+        self
+    }
+
+    // reconnect_delay sets the base delay between reconnection attempts, doubled on every retry
+    pub fn reconnect_delay(mut self, delay: Duration) -> Self {
+        self.backoff.base_delay = delay;
+        self
+    }
+
+    // max_reconnect_delay sets the maximum delay between reconnection attempts
+    pub fn max_reconnect_delay(mut self, delay: Duration) -> Self {
+        self.backoff.max_delay = delay;
+        self
+    }
+
+    // max_reconnect_retries sets the reconnection retries before failing with ConnectionFailed
+    pub fn max_reconnect_retries(mut self, retries: u32) -> Self {
+        self.backoff.max_retries = Some(retries);
+        self
+    }
+
+    // reconnect_jitter sets the fraction (0.0 to 1.0) of each delay randomly subtracted
+    pub fn reconnect_jitter(mut self, jitter: f64) -> Self {
+        self.backoff.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    // command_timeout sets the maximum time to wait for a command response
+    pub fn command_timeout(mut self, timeout: Duration) -> Self {
+        self.command_timeout = timeout;
+        self
+    }
+
+    // connect_timeout sets the maximum time to wait for a connection to the server instead of the
+    // OS default, a connection timing out moves to the next attempt of the backoff and server
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    // command_retries sets how many times the header, entry and bookmark commands failed with a
+    // network error are re-issued after reconnecting, 0 (the default) to fail right away
+    pub fn command_retries(mut self, retries: u32) -> Self {
+        self.command_retries = retries;
+        self
+    }
+
+    // header_cache_ttl sets the time a fetched header is served by get_header without a new
+    // header command, zero to always fetch it
+    pub fn header_cache_ttl(mut self, ttl: Duration) -> Self {
+        self.header_cache_ttl = ttl;
+        self
+    }
+
+    // concurrent_header gets the header on a side connection when starting the streaming, so the
+    // entries flow without waiting for it. The total entries and the synced status are only
+    // known once it's received.
+    pub fn concurrent_header(mut self, concurrent_header: bool) -> Self {
+        self.concurrent_header = concurrent_header;
+        self
+    }
+
+    // skip_initial_header starts the streaming without getting the header, for servers slow to
+    // answer the header command. The total entries stay unknown, and the status syncing, until
+    // total_entries is called: the header is then got on a side connection while streaming.
+    pub fn skip_initial_header(mut self, skip_initial_header: bool) -> Self {
+        self.skip_initial_header = skip_initial_header;
+        self
+    }
+
+    // idle_timeout enables probing the connection with a header command when no data is received
+    // for the given time, reconnecting if the probe fails. It can't be combined with read_timeout
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    // tcp_nodelay disables Nagle's algorithm on the server connection, lowering the latency of
+    // the commands on high-latency links
+    pub fn tcp_nodelay(mut self, nodelay: bool) -> Self {
+        self.socket_options.nodelay = nodelay;
+        self
+    }
+
+    // so_keepalive enables the TCP keepalive probes on the server connection, sent after the
+    // connection is idle for interval and then every interval
+    pub fn so_keepalive(mut self, interval: Duration) -> Self {
+        self.socket_options.keepalive = Some(interval);
+        self
+    }
+
+    // read_timeout sets the maximum time to wait for data from the server, failing with a
+    // TimedOut network error so a dead peer is reconnected. It can't be combined with
+    // idle_timeout, which already detects a dead peer by probing it
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.socket_options.read_timeout = Some(timeout);
+        self
+    }
+
+    // rate_limit paces the streamed entries to at most entries_per_sec, delivered to the hook or
+    // the subscriber without dropping any. Zero disables the limit
+    pub fn rate_limit(mut self, entries_per_sec: u32) -> Self {
+        self.rate_limit = Some(entries_per_sec).filter(|&rate| rate > 0);
+        self
+    }
+
+    // tee_flush_interval sets the time between flushes of the entries teed by tee_to
+    pub fn tee_flush_interval(mut self, interval: Duration) -> Self {
+        self.tee_flush_interval = interval;
+        self
+    }
+
+    // compression sets the compression of the event entries data, decompressed before being
+    // processed
+    #[cfg(feature = "compression")]
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    // capture_raw keeps the framed bytes of every data entry, returned by Entry::raw_bytes. It's
+    // disabled by default as it doubles the memory used by the entries.
+    pub fn capture_raw(mut self, capture_raw: bool) -> Self {
+        self.capture_raw = capture_raw;
+        self
+    }
+
+    // timestamp_entries sets the time every data entry is read in Entry::received_at, for
+    // latency analysis. It's disabled by default.
+    pub fn timestamp_entries(mut self, timestamp_entries: bool) -> Self {
+        self.timestamp_entries = timestamp_entries;
+        self
+    }
+
+    // verify_checksums checks the big endian CRC32 trailing the data of every event entry against
+    // the rest of the data, removing it from the entry data. The protocol has no checksum field,
+    // so it's an opt-in for servers appending it to the payloads.
+    pub fn verify_checksums(mut self, verify_checksums: bool) -> Self {
+        self.verify_checksums = verify_checksums;
+        self
+    }
+
+    // strict fails reading a packet or entry type not defined by the protocol with
+    // DecodeError::UnknownType. The default lenient mode skips the unknown packet types as
+    // padding and keeps the unknown entry types as EntryType::Event with their value, so strict
+    // mode rejects application entry types like the zkEVM L2BlockEnd (Event(3)).
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    // supported_versions sets the stream versions accepted in the header, replacing
+    // SUPPORTED_VERSIONS, to follow servers with newer compatible versions
+    pub fn supported_versions(mut self, versions: Vec<u8>) -> Self {
+        self.supported_versions = versions;
+        self
+    }
+
+    // read_buffer_size sets the initial capacity of the buffer reused to read the data entries,
+    // entries bigger than it grow the buffer once
+    pub fn read_buffer_size(mut self, size: usize) -> Self {
+        self.read_buffer_size = size;
+        self
+    }
+
+    // read_ahead_size sets the capacity of the buffer the connection is read through, 64 KiB by
+    // default. The packet type and fixed fields of the entries already received are read from it
+    // without a read from the socket each, larger buffers mean less reads at high throughput.
+    // It's at least 1 byte.
+    pub fn read_ahead_size(mut self, size: usize) -> Self {
+        self.read_ahead_size = size.max(1);
+        self
+    }
+
+    // max_entry_size sets the maximum size of a data entry, 64 MiB by default. A larger declared
+    // length fails with DecodeError and closes the connection instead of allocating the entry.
+    pub fn max_entry_size(mut self, size: usize) -> Self {
+        self.max_entry_size = size;
+        self
+    }
+
+    // page_size sets the data page size of the stream, the zkEVM 1 MiB PAGE_DATA_SIZE by default.
+    // The padding filling a page up to its boundary can't be longer than it. It must be a power
+    // of two large enough to hold a header entry.
+    pub fn page_size(mut self, page_size: u64) -> Self {
+        self.page_size = page_size;
+        self
+    }
+
+    // dedup_entries drops the streamed entries numbered at or below the highest delivered entry,
+    // replayed after a reconnection from an earlier entry or an overlapping bookmark, so the
+    // entries are delivered in strictly increasing order
+    pub fn dedup_entries(mut self, dedup: bool) -> Self {
+        self.dedup_entries = dedup;
+        self
+    }
+
+    // resume_from starts the streaming where the client the checkpoint was taken from left it
+    pub fn resume_from(mut self, checkpoint: Checkpoint) -> Self {
+        self.checkpoint = checkpoint;
+        self
+    }
+
+    // verify_sequence checks every streamed entry is numbered next to the previous one, calling
+    // the on_gap event listener otherwise. The entry is still delivered.
+    pub fn verify_sequence(mut self, verify_sequence: bool) -> Self {
+        self.verify_sequence = verify_sequence;
+        self
+    }
+
+    // fill_gaps fetches the entries missing in a gap found by verify_sequence, delivering them
+    // before the entry received past the gap. Requires verify_sequence.
+    pub fn fill_gaps(mut self, fill_gaps: bool) -> Self {
+        self.fill_gaps = fill_gaps;
+        self
+    }
+
+    // process_entry_hook sets the callback function to process the received entries
+    pub fn process_entry_hook<F>(mut self, f: F) -> Self
+    where
+        F: FnMut(Entry) -> Result<HookAction, ClientError> + Send + 'static,
+    {
+        self.process_entry_hook = Box::new(f);
+        self
+    }
+
+    // async_process_entry_hook sets an async function to process the entries, awaited instead of
+    // the process_entry_hook
+    pub fn async_process_entry_hook<F>(mut self, f: F) -> Self
+    where
+        F: FnMut(Entry) -> ProcessEntryFuture + Send + 'static,
+    {
+        self.async_process_entry_hook = Some(Box::new(f));
+        self
+    }
+
+    // concurrent_process_entry_hook sets an async function to process the entries, called by
+    // worker_count worker tasks instead of the other hooks so the entries are processed
+    // concurrently. The resume point still moves in the streamed order, and on a stop the entries
+    // past the stopping one may have been processed already.
+    pub fn concurrent_process_entry_hook<F>(mut self, f: F) -> Self
+    where
+        F: Fn(Entry) -> ProcessEntryFuture + Send + Sync + 'static,
+    {
+        self.concurrent_process_entry_hook = Some(Arc::new(f));
+        self
+    }
+
+    // worker_count sets the number of worker tasks calling the concurrent_process_entry_hook, 1
+    // by default. More than one requires the concurrent_process_entry_hook.
+    pub fn worker_count(mut self, count: usize) -> Self {
+        self.worker_count = count;
+        self
+    }
+
+    // on_hook_error sets what to do when the process entry hook fails, stopping the streaming by
+    // default so a failed entry doesn't go unnoticed
+    pub fn on_hook_error(mut self, policy: HookErrorPolicy) -> Self {
+        self.hook_error_policy = policy;
+        self
+    }
+
+    // event_listener sets the listener called on the client lifecycle events
+    pub fn event_listener<L: EventListener + 'static>(mut self, listener: L) -> Self {
+        self.event_listener = Some(Box::new(listener));
+        self
+    }
+
+    // tls enables TLS for the connection to the server
+    #[cfg(feature = "tls")]
+    pub fn tls(mut self, tls: bool) -> Self {
+        self.tls = tls;
+        self
+    }
+
+    // tls_root_cert sets the DER encoded root certificate trusted to verify the server
+    #[cfg(feature = "tls")]
+    pub fn tls_root_cert(mut self, cert: Vec<u8>) -> Self {
+        self.tls_root_cert = Some(cert);
+        self
+    }
+
+    // tls_server_name sets the hostname sent as SNI and verified against the server certificate
+    #[cfg(feature = "tls")]
+    pub fn tls_server_name(mut self, server_name: String) -> Self {
+        self.tls_server_name = Some(server_name);
+        self
+    }
+
+    // build validates the configuration and returns the StreamClient
+    pub fn build(self) -> Result<StreamClient, ClientError> {
+        let servers: Vec<String> = match self.servers.is_empty() {
+            true => self.server.into_iter().collect(),
+            false => self.servers,
+        };
+        let server = servers
+            .first()
+            .cloned()
+            .ok_or_else(|| ClientError::InvalidServerAddress("server not set".to_string()))?;
+        for server in &servers {
+            validate_server_address(server)?;
+        }
+        validate_page_size(self.page_size).map_err(ClientError::InvalidConfig)?;
+        if self.fill_gaps && !self.verify_sequence {
+            return Err(ClientError::InvalidConfig(
+                "fill_gaps requires verify_sequence".to_string(),
+            ));
+        }
+        if self.idle_timeout.is_some() && self.socket_options.read_timeout.is_some() {
+            return Err(ClientError::InvalidConfig(
+                "idle_timeout and read_timeout can't be set together".to_string(),
+            ));
+        }
+        if self.worker_count == 0 {
+            return Err(ClientError::InvalidConfig(
+                "worker_count must be at least 1".to_string(),
+            ));
+        }
+        if self.worker_count > 1 && self.concurrent_process_entry_hook.is_none() {
+            return Err(ClientError::InvalidConfig(
+                "worker_count requires a concurrent_process_entry_hook".to_string(),
+            ));
+        }
+
+        #[cfg(all(feature = "tls", unix))]
+        if self.tls && servers.iter().any(|s| s.starts_with(UNIX_SOCKET_PREFIX)) {
+            return Err(ClientError::InvalidServerAddress(
+                "TLS is not supported over a Unix socket".to_string(),
+            ));
+        }
+
+        #[cfg(feature = "tls")]
+        let tls = match self.tls {
+            true => Some(tls_config(
+                &server,
+                self.tls_root_cert,
+                self.tls_server_name,
+            )?),
+            false => None,
+        };
+
+        Ok(StreamClient {
+            server,
+            servers,
+            stream_type: self.stream_type,
+            conn: None,
+            id: String::new(),
+            peer_addr: None,
+            started: false,
+            connected: false,
+            needs_reconnect: false,
+            streaming: false,
+            from_stream: 0,
+            resume_from: self.checkpoint.resume_from,
+            start_mode: self.checkpoint.start_mode,
+            status: ClientStatus::CsStopped,
+            reported_state: ClientState::default(),
+            #[cfg(feature = "compression")]
+            compression: self.compression,
+            capture_raw: self.capture_raw,
+            timestamp_entries: self.timestamp_entries,
+            verify_checksums: self.verify_checksums,
+            strict: self.strict,
+            supported_versions: self.supported_versions,
+            read_buffer: Vec::with_capacity(self.read_buffer_size),
+            read_ahead_size: self.read_ahead_size,
+            max_entry_size: self.max_entry_size,
+            page_size: self.page_size,
+            bytes_consumed: 0,
+            dedup_entries: self.dedup_entries,
+            last_delivered: self.checkpoint.last_delivered,
+            verify_sequence: self.verify_sequence,
+            fill_gaps: self.fill_gaps,
+            entry_filter: None,
+            total_entries: 0,
+            header: None,
+            header_fetched_at: None,
+            header_cache_ttl: self.header_cache_ttl,
+            concurrent_header: self.concurrent_header,
+            skip_initial_header: self.skip_initial_header,
+            header_wanted: AtomicBool::new(false),
+            pending_header: None,
+            backoff: self.backoff,
+            command_timeout: self.command_timeout,
+            connect_timeout: self.connect_timeout,
+            command_retries: self.command_retries,
+            idle_timeout: self.idle_timeout,
+            socket_options: self.socket_options,
+            rate_limiter: self.rate_limit.map(RateLimiter::new),
+            tee: None,
+            tee_flush_interval: self.tee_flush_interval,
+            entry_sender: None,
+            event_listener: self.event_listener,
+            #[cfg(feature = "tls")]
+            tls,
+
+            process_entry_hook: self.process_entry_hook,
+            async_process_entry_hook: self.async_process_entry_hook,
+            concurrent_process_entry_hook: self.concurrent_process_entry_hook,
+            worker_count: self.worker_count,
+            workers: None,
+            hook_error_policy: self.hook_error_policy,
+            hook_error: None,
+        })
+    }
+}
+
+// start_failed returns the StartFailed error of a start step, logging it
+fn start_failed(step: String, e: ClientError) -> ClientError {
+    error!("Error starting the streaming, {}: {}", step, e);
+    ClientError::StartFailed(step, Box::new(e))
+}
+
+// validate_server_address checks the server address is an IP:port or a host:port pair, or a
+// Unix socket path
+fn validate_server_address(server: &str) -> Result<(), ClientError> {
+    Transport::parse(server).map(|_| ())
+}
+
+// tls_config builds the TLS settings trusting root_cert, or the webpki roots if not set, and
+// verifying server_name, or the host of the server address if not set
+#[cfg(feature = "tls")]
+fn tls_config(
+    server: &str,
+    root_cert: Option<Vec<u8>>,
+    server_name: Option<String>,
+) -> Result<TlsConfig, ClientError> {
+    let mut roots = rustls::RootCertStore::empty();
+    match root_cert {
+        Some(cert) => roots
+            .add(cert.into())
+            .map_err(|e| ClientError::TlsError(format!("invalid root certificate: {}", e)))?,
+        None => roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+    }
+
+    let config = rustls::ClientConfig::builder_with_provider(std::sync::Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()
+    .map_err(|e| ClientError::TlsError(e.to_string()))?
+    .with_root_certificates(roots)
+    .with_no_client_auth();
+
+    let server_name = server_name.unwrap_or_else(|| {
+        let host = server.rsplit_once(':').map_or(server, |(host, _)| host);
+        host.trim_start_matches('[')
+            .trim_end_matches(']')
+            .to_string()
+    });
+    let server_name = ServerName::try_from(server_name)
+        .map_err(|e| ClientError::TlsError(format!("invalid server name: {}", e)))?;
+
+    Ok(TlsConfig {
+        connector: TlsConnector::from(std::sync::Arc::new(config)),
+        server_name,
+    })
+}
+
+// command_supported returns whether the server accepted a probe command from its result, failing
+// on the errors that don't come from the server answer
+fn command_supported<T>(res: Result<T, ClientError>) -> Result<bool, ClientError> {
+    match res {
+        Ok(_) => Ok(true),
+        Err(ClientError::CommandFailed(CommandError::CmdErrInvalidCommand, _)) => Ok(false),
+        Err(
+            ClientError::CommandFailed(..)
+            | ClientError::EntryNotFound
+            | ClientError::BookmarkNotFound,
+        ) => Ok(true),
+        Err(e) => Err(e),
+    }
+}
+
+// verify_entry_checksum checks and strips the CRC32 trailing the data of an event entry, failing
+// with DecodeError on a mismatch
+fn verify_entry_checksum(mut e: Entry) -> Result<Entry, ClientError> {
+    if matches!(e.entry_type, EntryType::NotFound | EntryType::Bookmark) {
+        return Ok(e);
+    }
+
+    let Some(payload_length) = e.data.len().checked_sub(CHECKSUM_SIZE) else {
+        return Err(ClientError::DecodeError(format!(
+            "entry {} data of {} bytes has no checksum",
+            e.number,
+            e.data.len()
+        )));
+    };
+    let (payload, checksum) = e.data.split_at(payload_length);
+    let expected = BigEndian::read_u32(checksum);
+    let actual = crc32fast::hash(payload);
+    if actual != expected {
+        return Err(ClientError::DecodeError(format!(
+            "entry {} checksum mismatch: expected {:#010x}, got {:#010x}",
+            e.number, expected, actual
+        )));
+    }
+
+    e.data.truncate(payload_length);
+    e.length = (FIXED_SIZE_FILE_ENTRY + e.data.len()) as u32;
+    Ok(e)
+}
+
+// decompress decompresses the data of an entry
+#[cfg(feature = "compression")]
+fn decompress(compression: Compression, data: &[u8]) -> std::io::Result<Vec<u8>> {
+    use std::io::Read;
+
+    let mut b = Vec::new();
+    match compression {
+        Compression::None => return Ok(data.to_vec()),
+        Compression::Gzip => flate2::read::GzDecoder::new(data).read_to_end(&mut b)?,
+        Compression::Zlib => flate2::read::ZlibDecoder::new(data).read_to_end(&mut b)?,
+    };
+    Ok(b)
+}
+
+// encode_bookmark checks the bytes of an encoded bookmark aren't empty and don't exceed
+// MAX_BOOKMARK_LENGTH. An empty bookmark has no type nor value to match a bookmark entry, so it's
+// rejected instead of sent, while a bookmark of only the type byte is valid.
+fn encode_bookmark(b: Vec<u8>) -> Result<Vec<u8>, ClientError> {
+    if b.is_empty() {
+        return Err(ClientError::InvalidBookmark("empty bookmark".to_string()));
+    }
+    if b.len() > MAX_BOOKMARK_LENGTH {
+        return Err(ClientError::InvalidBookmark(format!(
+            "bookmark length {} exceeds the maximum of {} bytes",
+            b.len(),
+            MAX_BOOKMARK_LENGTH
+        )));
+    }
+    Ok(b)
+}
+
+// decode_binary_to_header_entry decodes from binary bytes slice to a header entry type
+pub(crate) fn decode_binary_to_header_entry(b: &[u8]) -> Result<HeaderEntry, ClientError> {
+    if b.len() != HEADER_SIZE {
+        return Err(ClientError::DecodeError(format!(
+            "invalid binary header entry: expected {} bytes, got {}",
+            HEADER_SIZE,
+            b.len()
+        )));
+    }
+
+    let packet_type = b[0];
+    if packet_type != PacketType::PtHeader as u8 {
+        return Err(ClientError::DecodeError(format!(
+            "unexpected packet type {:#04x} for header entry, expected {:#04x}",
+            packet_type,
+            PacketType::PtHeader as u8
+        )));
+    }
+    let head_length = BigEndian::read_u32(&b[1..5]);
+    let version = b[5];
+    let system_id = ChainId::from(BigEndian::read_u64(&b[6..14]));
+    let stream_type = StreamType::from(BigEndian::read_u64(&b[14..22])); // Convert u64 to StreamType
+    let total_length = BigEndian::read_u64(&b[22..30]);
+    let total_entries = BigEndian::read_u64(&b[30..38]);
+
+    Ok(HeaderEntry {
+        packet_type,
+        head_length,
+        version,
+        system_id,
+        stream_type,
+        total_length,
+        total_entries,
+    })
+}
+
+// decode_binary_to_file_entry decodes from binary bytes slice to file entry type
+pub(crate) fn decode_binary_to_entry(b: &[u8]) -> Result<Entry, ClientError> {
+    if b.len() < FIXED_SIZE_FILE_ENTRY {
+        return Err(ClientError::DecodeError(format!(
+            "invalid binary data entry: expected at least {} bytes, got {}",
+            FIXED_SIZE_FILE_ENTRY,
+            b.len()
+        )));
+    }
+
+    let packet_type = b[0];
+    if packet_type != PacketType::PtData as u8 && packet_type != PacketType::PtDataRsp as u8 {
+        return Err(ClientError::DecodeError(format!(
+            "unexpected packet type {:#04x} for data entry, expected {:#04x} or {:#04x}",
+            packet_type,
+            PacketType::PtData as u8,
+            PacketType::PtDataRsp as u8
+        )));
+    }
+    let mut e = decode_entry_fields(b);
+    let data = &b[FIXED_SIZE_FILE_ENTRY..];
+
+    let data_length = variable_length("data", e.length, FIXED_SIZE_FILE_ENTRY)?;
+    if data.len() != data_length {
+        return Err(ClientError::DecodeError(format!(
+            "invalid binary data entry: expected {} bytes of data, got {}",
+            data_length,
+            data.len()
+        )));
+    }
+
+    e.data = data.to_vec();
+    Ok(e)
+}
+
+// fetch_header gets the header with a header command on a connection of its own, used to get it
+// concurrently with the client connection
+async fn fetch_header(
+    mut conn: Connection,
+    stream_type: StreamType,
+    command_timeout: Duration,
+) -> Result<HeaderEntry, ClientError> {
+    let frame = CommandFrame::new(Command::CmdHeader, stream_type);
+    conn.write_all(frame.as_bytes()).await?;
+
+    let read = async {
+        let mut b = vec![0; FIXED_SIZE_RESULT_ENTRY];
+        conn.read_exact(&mut b).await?;
+        let length = BigEndian::read_u32(&b[1..5]);
+        let error_length = error_str_length(length)?;
+        b.resize(FIXED_SIZE_RESULT_ENTRY + error_length, 0);
+        conn.read_exact(&mut b[FIXED_SIZE_RESULT_ENTRY..]).await?;
+
+        let re = decode_binary_to_result_entry(&b)?;
+        if re.error_num != u32::from(CommandError::CmdErrOK) {
+            return Err(ClientError::CommandFailed(
+                CommandError::from(re.error_num),
+                re.describe(),
+            ));
+        }
+
+        let mut b = vec![0; HEADER_SIZE];
+        conn.read_exact(&mut b).await?;
+        decode_binary_to_header_entry(&b)
+    };
+    timeout(command_timeout, read)
+        .await
+        .map_err(|_| ClientError::Timeout)?
+}
+
+// variable_length returns the length of the variable field of an entry from its length field,
+// failing if the length field is shorter than the fixed size fields
+fn variable_length(kind: &str, length: u32, fixed_size: usize) -> Result<usize, ClientError> {
+    usize::try_from(length)
+        .ok()
+        .and_then(|length| length.checked_sub(fixed_size))
+        .ok_or_else(|| {
+            ClientError::DecodeError(format!(
+                "invalid {} entry length: expected at least {} bytes, got {}",
+                kind, fixed_size, length
+            ))
+        })
+}
+
+// error_str_length returns the length of the error string of a result entry from its length
+// field, failing if it exceeds MAX_ERROR_STR_SIZE so a broken server can't force a large allocation
+fn error_str_length(length: u32) -> Result<usize, ClientError> {
+    let error_length = variable_length("result", length, FIXED_SIZE_RESULT_ENTRY)?;
+    if error_length > MAX_ERROR_STR_SIZE {
+        return Err(ClientError::DecodeError(format!(
+            "result error string length {} exceeds the maximum of {} bytes",
+            error_length, MAX_ERROR_STR_SIZE
+        )));
+    }
+    Ok(error_length)
+}
+
+// check_known_entry_type fails with UnknownType for the entry types not defined by the protocol,
+// kept as EntryType::Event by the lenient decoding
+pub(crate) fn check_known_entry_type(entry_type: EntryType) -> Result<(), ClientError> {
+    match entry_type {
+        EntryType::Event(v) => Err(crate::entry_data::DecodeError::UnknownType(v).into()),
+        _ => Ok(()),
+    }
+}
+
+// decode_entry_fields decodes the fixed size fields of a data entry, leaving the data empty.
+// The slice must hold at least FIXED_SIZE_FILE_ENTRY bytes.
+fn decode_entry_fields(b: &[u8]) -> Entry {
+    Entry {
+        packet_type: b[0],
+        length: BigEndian::read_u32(&b[1..5]),
+        entry_type: EntryType::from(BigEndian::read_u32(&b[5..9])), // Convert u32 to EntryType
+        number: BigEndian::read_u64(&b[9..17]),
+        data: Vec::new(),
+        raw: None,
+        received_at: None,
+    }
+}
+
+// DecodeBinaryToResultEntry decodes from binary bytes slice to a result entry type, checking the
+// declared length matches the bytes of the entry
+pub(crate) fn decode_binary_to_result_entry(b: &[u8]) -> Result<ResultEntry, ClientError> {
+    if b.len() < FIXED_SIZE_RESULT_ENTRY {
+        return Err(ClientError::DecodeError(format!(
+            "invalid binary result entry: expected at least {} bytes, got {}",
+            FIXED_SIZE_RESULT_ENTRY,
+            b.len()
+        )));
+    }
+
+    let packet_type = b[0];
+    if packet_type != PacketType::PtResult as u8 {
+        return Err(ClientError::DecodeError(format!(
+            "unexpected packet type {:#04x} for result entry, expected {:#04x}",
+            packet_type,
+            PacketType::PtResult as u8
+        )));
+    }
+    let length = BigEndian::read_u32(&b[1..5]);
+    if length as usize != b.len() {
+        return Err(ClientError::DecodeError(format!(
+            "invalid binary result entry: length field is {} bytes, got {}",
+            length,
+            b.len()
+        )));
+    }
+    let error_num = BigEndian::read_u32(&b[5..9]);
+    let error_str = b[FIXED_SIZE_RESULT_ENTRY..].to_vec();
+
+    Ok(ResultEntry {
+        packet_type,
+        length,
+        error_num,
+        error_str,
+    })
+}
+
+fn print_received_entry(entry: Entry) -> Result<HookAction, ClientError> {
+    info!("Received entry: {:?}", entry);
+    Ok(HookAction::Continue)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{MockPacket, MockStreamServer};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tracing_test::traced_test;
+
+    #[tokio::test]
+    #[traced_test]
+    async fn test_stream_client_new() {
+        let bookmark = Bookmark::new(0, 0u64.to_be_bytes().to_vec());
+        let server = MockStreamServer::builder()
+            .on(
+                Command::CmdBookmark,
+                vec![
+                    MockPacket::ok(),
+                    MockPacket::data_rsp(0, EntryType::Bookmark, bookmark.encode()),
+                ],
+            )
+            .on(
+                Command::CmdHeader,
+                vec![MockPacket::ok(), MockPacket::header(3)],
+            )
+            .on(
+                Command::CmdStart,
+                vec![
+                    MockPacket::ok(),
+                    MockPacket::data(1, EntryType::Event1, vec![0x01]),
+                    MockPacket::data(2, EntryType::Event2, vec![0x02]),
+                ],
+            )
+            .start()
+            .await
+            .unwrap();
+        let stream_type = StreamType::Sequencer;
+        let mut client = StreamClient::new(server.addr()).unwrap();
+        assert_eq!(client.server, server.addr());
+        assert_eq!(client.stream_type, stream_type);
+
+        client.connect_server().await.unwrap();
+
+        let e = client
+            .exec_command_get_bookmark(Bookmark::try_from(0u64.to_be_bytes().to_vec()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(e.entry_type, EntryType::Bookmark);
+        assert_eq!(Bookmark::decode(&e.data).unwrap(), bookmark);
+
+        // Stream until the last scripted entry
+        let token = CancellationToken::new();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let cancel = token.clone();
+        client.set_process_entry_hook(move |e| {
+            if e.number == 2 {
+                cancel.cancel();
+            }
+            tx.send(e.number).unwrap();
+            Ok(HookAction::Continue)
+        });
+        client.start_with_shutdown(token).await.unwrap();
+
+        assert_eq!(rx.recv().await, Some(1));
+        assert_eq!(rx.recv().await, Some(2));
+        let commands: Vec<u64> = server.received().iter().map(|c| c.command).collect();
+        assert_eq!(
+            commands,
+            vec![
+                Command::CmdBookmark as u64,
+                Command::CmdHeader as u64,
+                Command::CmdStart as u64,
+                Command::CmdStop as u64
+            ]
+        );
+    }
+
+    // read_command reads a client command from the mock server side of the connection
+    async fn read_command(conn: &mut (impl AsyncRead + Unpin), with_param: bool) -> (u64, u64) {
+        let mut buf = [0u8; 8];
+        conn.read_exact(&mut buf).await.unwrap();
+        let cmd = u64::from_be_bytes(buf);
+        conn.read_exact(&mut buf).await.unwrap();
+        let mut param = 0;
+        if with_param {
+            conn.read_exact(&mut buf).await.unwrap();
+            param = u64::from_be_bytes(buf);
+        }
+        (cmd, param)
+    }
+
+    fn ok_result() -> Vec<u8> {
+        ResultEntry {
+            packet_type: PacketType::PtResult as u8,
+            error_num: u32::from(CommandError::CmdErrOK),
+            ..Default::default()
+        }
+        .encode_to_binary()
+    }
+
+    fn header_response(total_entries: u64) -> Vec<u8> {
+        let header = HeaderEntry {
+            packet_type: PacketType::PtHeader as u8,
+            head_length: HEADER_SIZE as u32,
+            version: 1,
+            total_entries,
+            ..Default::default()
+        };
+        [ok_result(), header.encode_to_binary()].concat()
+    }
+
+    fn data_entry(number: u64) -> Vec<u8> {
+        Entry {
+            packet_type: PacketType::PtData as u8,
+            entry_type: EntryType::Event1,
+            number,
+            data: number.to_be_bytes().to_vec(),
+            ..Default::default()
+        }
+        .encode_to_binary()
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn test_start_reconnects_after_disconnect() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+        let (reconnected_tx, reconnected_rx) = tokio::sync::oneshot::channel();
+
+        tokio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            let (cmd, _) = read_command(&mut conn, false).await;
+            assert_eq!(cmd, Command::CmdHeader as u64);
+            conn.write_all(&header_response(1)).await.unwrap();
+            let (cmd, _) = read_command(&mut conn, true).await;
+            assert_eq!(cmd, Command::CmdStart as u64);
+            conn.write_all(&[ok_result(), data_entry(0)].concat())
+                .await
+                .unwrap();
+            // Close the connection mid-stream
+            drop(conn);
+
+            let (mut conn, _) = listener.accept().await.unwrap();
+            let (cmd, _) = read_command(&mut conn, true).await;
+            conn.write_all(&ok_result()).await.unwrap();
+            reconnected_tx.send(cmd).unwrap();
+            // Keep the connection open until the test ends
+            let _ = conn.read(&mut [0u8; 1]).await;
+        });
+
+        let received = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let hook_received = received.clone();
+        let mut client = StreamClient::builder()
+            .server(server)
+            .reconnect_delay(Duration::from_millis(10))
+            .process_entry_hook(move |e| {
+                hook_received.lock().unwrap().push(e.number);
+                Ok(HookAction::Continue)
+            })
+            .build()
+            .unwrap();
+
+        let cmd = tokio::time::timeout(Duration::from_secs(5), async {
+            tokio::select! {
+                res = client.start() => panic!("start returned: {:?}", res),
+                cmd = reconnected_rx => cmd.unwrap(),
+            }
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(cmd, Command::CmdStart as u64);
+        assert_eq!(*received.lock().unwrap(), vec![0]);
+    }
+
+    #[test]
+    fn test_decode_header_entry_size_mismatch() {
+        let res = decode_binary_to_header_entry(&[PacketType::PtHeader as u8; 20]);
+        match res {
+            Err(ClientError::DecodeError(msg)) => {
+                assert!(msg.contains("expected 38 bytes, got 20"), "{msg}")
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_decode_unexpected_packet_type() {
+        let header = HeaderEntry {
+            packet_type: PacketType::PtData as u8,
+            head_length: HEADER_SIZE as u32,
+            ..Default::default()
+        };
+        match decode_binary_to_header_entry(&header.encode_to_binary()) {
+            Err(ClientError::DecodeError(msg)) => {
+                assert!(msg.contains("unexpected packet type 0x02"), "{msg}")
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let entry = Entry {
+            packet_type: PacketType::PtResult as u8,
+            entry_type: EntryType::Event1,
+            ..Default::default()
+        };
+        match decode_binary_to_entry(&entry.encode_to_binary()) {
+            Err(ClientError::DecodeError(msg)) => {
+                assert!(msg.contains("unexpected packet type 0xff"), "{msg}")
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_exec_command_decode_error() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            read_command(&mut conn, false).await;
+            // Result entry declaring a length below the fixed size
+            let mut result = ok_result();
+            result[1..5].copy_from_slice(&4u32.to_be_bytes());
+            conn.write_all(&result).await.unwrap();
+            let _ = conn.read(&mut [0u8; 1]).await;
+        });
+
+        let mut client = StreamClient::new(server).unwrap();
+        client.connect_server().await.unwrap();
+
+        let res = client.exec_command_get_header().await;
+        match res {
+            Err(ClientError::DecodeError(msg)) => {
+                assert!(msg.contains("expected at least 9 bytes, got 4"), "{msg}")
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn test_subscribe() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+        let (dropped_tx, dropped_rx) = tokio::sync::oneshot::channel::<()>();
+
+        tokio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            read_command(&mut conn, false).await;
+            conn.write_all(&header_response(10)).await.unwrap();
+            read_command(&mut conn, true).await;
+            conn.write_all(&ok_result()).await.unwrap();
+            for number in 0..10 {
+                conn.write_all(&data_entry(number)).await.unwrap();
+            }
+            // Once the receiver is dropped the next entry stops the stream
+            dropped_rx.await.unwrap();
+            conn.write_all(&data_entry(10)).await.unwrap();
+            let _ = conn.read(&mut [0u8; 1]).await;
+        });
+
+        let mut client = StreamClient::new(server).unwrap();
+        let mut rx = client.subscribe();
+
+        let consumer = async move {
+            let mut numbers = Vec::new();
+            while numbers.len() < 10 {
+                numbers.push(rx.recv().await.unwrap().number);
+            }
+            drop(rx);
+            dropped_tx.send(()).unwrap();
+            numbers
+        };
+
+        let (res, numbers) = tokio::time::timeout(Duration::from_secs(5), async {
+            tokio::join!(client.start(), consumer)
+        })
+        .await
+        .unwrap();
+
+        assert!(res.is_ok());
+        assert_eq!(numbers, (0..10).collect::<Vec<u64>>());
+        assert!(!client.connected);
+    }
+
+    #[tokio::test]
+    async fn test_follow_from_resumes_after_reconnect() {
+        use futures::StreamExt;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+
+        let server_task = tokio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            read_command(&mut conn, false).await;
+            conn.write_all(&header_response(10)).await.unwrap();
+            let (_, first) = read_command(&mut conn, true).await;
+            conn.write_all(&[ok_result(), data_entry(5), data_entry(6)].concat())
+                .await
+                .unwrap();
+            drop(conn);
+
+            // The streaming is restored next to the last delivered entry
+            let (mut conn, _) = listener.accept().await.unwrap();
+            let (_, resumed) = read_command(&mut conn, true).await;
+            conn.write_all(&[ok_result(), data_entry(7)].concat())
+                .await
+                .unwrap();
+            let _ = conn.read(&mut [0u8; 1]).await;
+            (first, resumed)
+        });
+
+        let mut client = StreamClient::new(server).unwrap();
+        client.follow_from(5);
+        assert_eq!(client.resume_from(), 5);
+
+        let numbers: Vec<u64> = tokio::time::timeout(
+            Duration::from_secs(5),
+            client
+                .into_entry_stream()
+                .take(3)
+                .map(|e| e.unwrap().number)
+                .collect(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(numbers, vec![5, 6, 7]);
+
+        let (first, resumed) = server_task.await.unwrap();
+        assert_eq!(first, 5);
+        assert_eq!(resumed, 7);
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_metrics_entries_counter() {
+        use futures::StreamExt;
+        use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        // The local recorder applies to the whole current thread runtime
+        metrics::with_local_recorder(&recorder, || {
+            rt.block_on(async {
+                let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+                let server = listener.local_addr().unwrap().to_string();
+
+                tokio::spawn(async move {
+                    let (mut conn, _) = listener.accept().await.unwrap();
+                    read_command(&mut conn, false).await;
+                    conn.write_all(&header_response(3)).await.unwrap();
+                    read_command(&mut conn, true).await;
+                    conn.write_all(
+                        &[ok_result(), data_entry(0), data_entry(1), data_entry(2)].concat(),
+                    )
+                    .await
+                    .unwrap();
+                    let _ = conn.read(&mut [0u8; 1]).await;
+                });
+
+                let client = StreamClient::new(server).unwrap();
+                let entries: Vec<_> = client.into_entry_stream().take(3).collect().await;
+                assert_eq!(entries.len(), 3);
+            })
+        });
+
+        let value = |name: &str| {
+            snapshotter
+                .snapshot()
+                .into_vec()
+                .into_iter()
+                .find(|(key, _, _, _)| key.key().name() == name)
+                .map(|(_, _, _, value)| value)
+        };
+        assert_eq!(value(METRIC_ENTRIES), Some(DebugValue::Counter(3)));
+        assert_eq!(
+            value(METRIC_BYTES),
+            Some(DebugValue::Counter(3 * (FIXED_SIZE_FILE_ENTRY as u64 + 8)))
+        );
+        assert_eq!(
+            value(METRIC_LAST_ENTRY),
+            Some(DebugValue::Gauge(2.0.into()))
+        );
+        assert_eq!(value(METRIC_RECONNECTS), None);
+        assert!(matches!(value(METRIC_SOCKET_READS), Some(DebugValue::Counter(n)) if n > 0));
+    }
+
+    #[tokio::test]
+    async fn test_bookmark_start_restored_on_reconnect() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+
+        async fn read_bookmark_command(conn: &mut tokio::net::TcpStream) -> (u64, Vec<u8>) {
+            let (cmd, _) = read_command(conn, false).await;
+            let length = conn.read_u32().await.unwrap();
+            let mut bookmark = vec![0u8; length as usize];
+            conn.read_exact(&mut bookmark).await.unwrap();
+            (cmd, bookmark)
+        }
+
+        let server_task = tokio::spawn(async move {
+            let mut commands = Vec::new();
+            for _ in 0..2 {
+                let (mut conn, _) = listener.accept().await.unwrap();
+                commands.push(read_bookmark_command(&mut conn).await);
+                conn.write_all(&ok_result()).await.unwrap();
+            }
+            commands
+        });
+
+        let bookmark = Bookmark::new(1, 42u64.to_be_bytes().to_vec());
+        let mut client = StreamClient::new(server).unwrap();
+        client.connect_server().await.unwrap();
+        client
+            .exec_command_start_bookmark(bookmark.clone())
+            .await
+            .unwrap();
+        assert_eq!(client.start_mode(), &StartMode::Bookmark(bookmark.encode()));
+
+        // The reconnection restores the streaming with the same bookmark
+        assert!(matches!(client.reconnect().await, Ok(())));
+        assert!(client.streaming);
+
+        let commands = server_task.await.unwrap();
+        for (cmd, b) in commands {
+            assert_eq!(cmd, Command::CmdStartBookmark as u64);
+            assert_eq!(b, bookmark.encode());
+        }
+
+        // Once an entry is delivered the streaming is restored from the next entry number
+        client.delivered(9);
+        assert_eq!(client.start_mode(), &StartMode::Entry);
+        assert_eq!(client.resume_from(), 10);
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn test_idle_timeout_reconnects() {
+        use futures::StreamExt;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+
+        let server_task = tokio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            read_command(&mut conn, false).await;
+            conn.write_all(&header_response(2)).await.unwrap();
+            read_command(&mut conn, true).await;
+            conn.write_all(&[ok_result(), data_entry(0)].concat())
+                .await
+                .unwrap();
+
+            // Stop sending, the probe is never answered
+            let (probe, _) = read_command(&mut conn, false).await;
+
+            let (mut conn, _) = listener.accept().await.unwrap();
+            let (_, resumed) = read_command(&mut conn, true).await;
+            conn.write_all(&[ok_result(), data_entry(1)].concat())
+                .await
+                .unwrap();
+            let _ = conn.read(&mut [0u8; 1]).await;
+            (probe, resumed)
+        });
+
+        let client = StreamClient::builder()
+            .server(server)
+            .idle_timeout(Duration::from_millis(100))
+            .command_timeout(Duration::from_millis(100))
+            .build()
+            .unwrap();
+        let numbers: Vec<u64> = tokio::time::timeout(
+            Duration::from_secs(5),
+            client
+                .into_entry_stream()
+                .take(2)
+                .map(|e| e.unwrap().number)
+                .collect(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(numbers, vec![0, 1]);
+
+        let (probe, resumed) = server_task.await.unwrap();
+        assert_eq!(probe, Command::CmdHeader as u64);
+        assert_eq!(resumed, 1);
+        assert!(logs_contain("probing connection"));
+    }
+
+    #[tokio::test]
+    async fn test_entry_type_filter() {
+        use futures::StreamExt;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            read_command(&mut conn, false).await;
+            conn.write_all(&header_response(6)).await.unwrap();
+            read_command(&mut conn, true).await;
+            let mut b = ok_result();
+            for number in 0..6 {
+                let entry_type = match number % 2 {
+                    0 => EntryType::Event1,
+                    _ => EntryType::Event2,
+                };
+                let e = Entry {
+                    packet_type: PacketType::PtData as u8,
+                    entry_type,
+                    number,
+                    data: vec![number as u8],
+                    ..Default::default()
+                };
+                b.extend_from_slice(&e.encode_to_binary());
+            }
+            conn.write_all(&b).await.unwrap();
+            let _ = conn.read(&mut [0u8; 1]).await;
+        });
+
+        let mut client = StreamClient::new(server).unwrap();
+        // The catch-all variant selects the entries decoded as the named one
+        client.set_entry_type_filter(EntryType::Event(2));
+
+        let entries: Vec<Entry> = tokio::time::timeout(
+            Duration::from_secs(5),
+            client
+                .into_entry_stream()
+                .take(3)
+                .map(|e| e.unwrap())
+                .collect(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            entries.iter().map(|e| e.number).collect::<Vec<_>>(),
+            vec![1, 3, 5]
+        );
+        assert!(entries.iter().all(|e| e.entry_type == EntryType::Event2));
+        assert_eq!(entries[2].data, vec![5]);
+    }
+
+    #[tokio::test]
+    async fn test_connect_invalid_address() {
+        let mut client = StreamClient::new("127.0.0.1:6900".to_string()).unwrap();
+
+        for server in ["garbage:::", "nonexistent.invalid:6900"] {
+            client.server = server.to_string();
+            let res = tokio::time::timeout(Duration::from_secs(5), client.connect_server())
+                .await
+                .expect("connect_server should fail without retrying");
+            assert!(
+                matches!(res, Err(ClientError::InvalidServerAddress(_))),
+                "{server}: {res:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_connect_ipv6() {
+        // Skipped where the IPv6 loopback isn't available
+        let Ok(listener) = tokio::net::TcpListener::bind("[::1]:0").await else {
+            return;
+        };
+        let server = format!("[::1]:{}", listener.local_addr().unwrap().port());
+
+        tokio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            read_command(&mut conn, false).await;
+            conn.write_all(&header_response(3)).await.unwrap();
+        });
+
+        let mut client = StreamClient::new(server).unwrap();
+        client.connect_server().await.unwrap();
+        assert!(client.id.starts_with("[::1]:"), "{}", client.id);
+
+        let header = client.exec_command_get_header().await.unwrap();
+        assert_eq!(header.total_entries, 3);
+    }
+
+    #[tokio::test]
+    async fn test_connect_tries_each_address() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let reachable = listener.local_addr().unwrap();
+        let closed = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        let mut client = StreamClient::new(reachable.to_string()).unwrap();
+        let conn = client.open_connection(&[closed, reachable]).await.unwrap();
+        let (accepted, _) = listener.accept().await.unwrap();
+        assert_eq!(
+            conn.local_id().unwrap(),
+            accepted.peer_addr().unwrap().to_string()
+        );
+
+        assert!(client.open_connection(&[closed]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_client_id_and_peer_addr() {
+        let server = MockStreamServer::builder().start().await.unwrap();
+
+        let mut client = StreamClient::new(server.addr()).unwrap();
+        assert_eq!(client.client_id(), "");
+        assert_eq!(client.peer_addr(), None);
+
+        client.connect_server().await.unwrap();
+        let local_addr = client.conn.as_ref().unwrap().get_ref().local_id().unwrap();
+        assert_eq!(client.client_id(), local_addr);
+        assert_eq!(client.peer_addr(), Some(server.addr().parse().unwrap()));
+
+        // The id is kept after disconnecting, the peer address is cleared
+        client.close_connection();
+        assert_eq!(client.client_id(), local_addr);
+        assert_eq!(client.peer_addr(), None);
+    }
+
+    #[tokio::test]
+    async fn test_stream_type_override() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+
+        // Echo the received stream type in the header and the entry number
+        tokio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            while let Ok(cmd) = conn.read_u64().await {
+                let stream_type = conn.read_u64().await.unwrap();
+                let response = if cmd == Command::CmdHeader as u64 {
+                    let header = HeaderEntry {
+                        packet_type: PacketType::PtHeader as u8,
+                        head_length: HEADER_SIZE as u32,
+                        version: 1,
+                        stream_type: StreamType::from(stream_type),
+                        ..Default::default()
+                    };
+                    [ok_result(), header.encode_to_binary()].concat()
+                } else {
+                    conn.read_u64().await.unwrap();
+                    let mut e = data_entry(stream_type);
+                    e[0] = PacketType::PtDataRsp as u8;
+                    [ok_result(), e].concat()
+                };
+                conn.write_all(&response).await.unwrap();
+            }
+        });
+
+        let mut client = StreamClient::new(server).unwrap();
+        client.connect_server().await.unwrap();
+
+        let header = client
+            .exec_command_get_header_for(StreamType::Other(2))
+            .await
+            .unwrap();
+        assert_eq!(header.stream_type, StreamType::Other(2));
+        assert!(client.header().is_none());
+
+        let e = client
+            .exec_command_get_entry_for(StreamType::Other(7), 0)
+            .await
+            .unwrap();
+        assert_eq!(e.number, 7);
+
+        // The client stream type is still used by default
+        let header = client.exec_command_get_header().await.unwrap();
+        assert_eq!(header.stream_type, StreamType::Sequencer);
+        let e = client.exec_command_get_entry(0).await.unwrap();
+        assert_eq!(e.number, 1);
+    }
+
+    #[tokio::test]
+    async fn test_read_packet_skips_padding() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            let padding = vec![PacketType::PtPadding as u8; 100_000];
+            conn.write_all(&[data_entry(0), padding, data_entry(1), vec![0x42]].concat())
+                .await
+                .unwrap();
+            let _ = conn.read(&mut [0u8; 1]).await;
+        });
+
+        // Strict so the unknown packet type fails instead of being skipped
+        let mut client = StreamClient::builder()
+            .server(server)
+            .strict(true)
+            .build()
+            .unwrap();
+        client.connect_server().await.unwrap();
+
+        assert_eq!(client.read_packet().await.unwrap().unwrap().number, 0);
+        // The whole padding region is consumed at once
+        assert_eq!(client.read_packet().await.unwrap(), None);
+        assert_eq!(client.read_packet().await.unwrap().unwrap().number, 1);
+
+        match client.read_packet().await {
+            Err(ClientError::Decode(crate::entry_data::DecodeError::UnknownType(0x42))) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_status_transitions() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            read_command(&mut conn, false).await;
+            conn.write_all(&header_response(3)).await.unwrap();
+            read_command(&mut conn, true).await;
+            conn.write_all(&[ok_result(), data_entry(0), data_entry(1), data_entry(2)].concat())
+                .await
+                .unwrap();
+            read_command(&mut conn, false).await;
+            conn.write_all(&ok_result()).await.unwrap();
+            let _ = conn.read(&mut [0u8; 1]).await;
+        });
+
+        let mut client = StreamClient::builder()
+            .server(server)
+            .process_entry_hook(|_| Ok(HookAction::Continue))
+            .build()
+            .unwrap();
+        assert_eq!(client.status(), ClientStatus::CsStopped);
+        client.connect_server().await.unwrap();
+        client.exec_command_get_header().await.unwrap();
+        client.exec_command_start(0).await.unwrap();
+        assert_eq!(client.status(), ClientStatus::CsSyncing);
+
+        for _ in 0..2 {
+            client.read_entries().await.unwrap();
+            assert_eq!(client.status(), ClientStatus::CsSyncing);
+        }
+        client.read_entries().await.unwrap();
+        assert_eq!(client.status(), ClientStatus::CsSynced);
+
+        client.exec_command_stop().await.unwrap();
+        assert_eq!(client.status(), ClientStatus::CsStopped);
+    }
+
+    #[tokio::test]
+    async fn test_live_tail() {
+        struct Listener(Arc<std::sync::Mutex<Vec<u64>>>);
+
+        impl EventListener for Listener {
+            fn on_synced(&self, _client_id: &str, number: u64) {
+                self.0.lock().unwrap().push(number);
+            }
+        }
+
+        // The stream grows to 5 entries after the header reported 2
+        let entries = (0..5)
+            .map(|n| MockPacket::data(n, EntryType::Event1, vec![n as u8]))
+            .collect::<Vec<_>>();
+        let server = MockStreamServer::builder()
+            .on(
+                Command::CmdHeader,
+                vec![MockPacket::ok(), MockPacket::header(2)],
+            )
+            .on(
+                Command::CmdStart,
+                [vec![MockPacket::ok()], entries].concat(),
+            )
+            .start()
+            .await
+            .unwrap();
+
+        let synced = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let received = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut client = StreamClient::builder()
+            .server(server.addr())
+            .event_listener(Listener(synced.clone()))
+            .process_entry_hook({
+                let received = received.clone();
+                move |e| {
+                    received.lock().unwrap().push(e.number);
+                    Ok(HookAction::Continue)
+                }
+            })
+            .build()
+            .unwrap();
+        client.start_streaming().await.unwrap();
+        assert_eq!(client.status(), ClientStatus::CsSyncing);
+
+        client.read_entries().await.unwrap();
+        assert_eq!(client.status(), ClientStatus::CsSyncing);
+        for _ in 1..5 {
+            client.read_entries().await.unwrap();
+            assert_eq!(client.status(), ClientStatus::CsSynced);
+        }
+        assert_eq!(*received.lock().unwrap(), vec![0, 1, 2, 3, 4]);
+        assert_eq!(*synced.lock().unwrap(), vec![1]);
+        assert!(client.is_started());
+        assert_eq!(client.resume_from(), 5);
+    }
+
+    struct GapListener(Arc<std::sync::Mutex<Vec<(u64, u64)>>>);
+
+    impl EventListener for GapListener {
+        fn on_gap(&self, _client_id: &str, expected: u64, actual: u64) {
+            self.0.lock().unwrap().push((expected, actual));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_header() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            // The client connection streams the entries right away
+            let (mut conn, _) = listener.accept().await.unwrap();
+            let (cmd, _) = read_command(&mut conn, true).await;
+            assert_eq!(cmd, Command::CmdStart as u64);
+            let entries: Vec<u8> = (0..3).flat_map(data_entry).collect();
+            conn.write_all(&[ok_result(), entries].concat())
+                .await
+                .unwrap();
+
+            // The header connection answers late
+            let (mut side, _) = listener.accept().await.unwrap();
+            let (cmd, _) = read_command(&mut side, false).await;
+            assert_eq!(cmd, Command::CmdHeader as u64);
+            sleep(Duration::from_millis(300)).await;
+            side.write_all(&header_response(3)).await.unwrap();
+            let _ = conn.read(&mut [0u8; 1]).await;
+        });
+
+        let mut client = StreamClient::builder()
+            .server(server)
+            .concurrent_header(true)
+            .process_entry_hook(|_| Ok(HookAction::Continue))
+            .build()
+            .unwrap();
+        let started = Instant::now();
+        client.start_streaming().await.unwrap();
+        client.read_entries().await.unwrap();
+        assert!(started.elapsed() < Duration::from_millis(300));
+        assert!(client.header().is_none());
+        assert_eq!(client.total_entries(), 0);
+        assert_eq!(client.status(), ClientStatus::CsSyncing);
+
+        sleep(Duration::from_millis(500)).await;
+        client.read_entries().await.unwrap();
+        assert_eq!(client.total_entries(), 3);
+        assert_eq!(client.status(), ClientStatus::CsSyncing);
+        client.read_entries().await.unwrap();
+        assert_eq!(client.status(), ClientStatus::CsSynced);
+    }
+
+    #[tokio::test]
+    async fn test_skip_initial_header() {
+        let entries = (0..5)
+            .map(|n| MockPacket::data(n, EntryType::Event1, vec![n as u8]))
+            .collect::<Vec<_>>();
+        let server = MockStreamServer::builder()
+            .on(
+                Command::CmdHeader,
+                vec![MockPacket::ok(), MockPacket::header(5)],
+            )
+            .on(
+                Command::CmdStart,
+                [vec![MockPacket::ok()], entries].concat(),
+            )
+            .on(Command::CmdStop, vec![MockPacket::ok()])
+            .start()
+            .await
+            .unwrap();
+
+        let mut client = StreamClient::builder()
+            .server(server.addr())
+            .skip_initial_header(true)
+            .process_entry_hook(|e| match e.number {
+                2 => Ok(HookAction::Stop),
+                _ => Ok(HookAction::Continue),
+            })
+            .build()
+            .unwrap();
+        timeout(Duration::from_secs(5), client.start())
+            .await
+            .unwrap()
+            .unwrap();
+        let commands: Vec<u64> = server.received().iter().map(|c| c.command).collect();
+        assert_eq!(
+            commands,
+            vec![Command::CmdStart as u64, Command::CmdStop as u64]
+        );
+        assert_eq!(client.resume_from(), 3);
+        assert!(client.header().is_none());
+        assert_eq!(client.progress(), None);
+
+        // The header is only got once the total entries are asked for
+        let mut client = StreamClient::builder()
+            .server(server.addr())
+            .skip_initial_header(true)
+            .process_entry_hook(|_| Ok(HookAction::Continue))
+            .build()
+            .unwrap();
+        client.start_streaming().await.unwrap();
+        client.read_entries().await.unwrap();
+        assert_eq!(client.status(), ClientStatus::CsSyncing);
+        assert_eq!(client.total_entries(), 0);
+
+        client.read_entries().await.unwrap();
+        sleep(Duration::from_millis(100)).await;
+        client.read_entries().await.unwrap();
+        assert_eq!(client.total_entries(), 5);
+        client.read_entries().await.unwrap();
+        client.read_entries().await.unwrap();
+        assert_eq!(client.status(), ClientStatus::CsSynced);
+        let commands: Vec<u64> = server.received()[2..].iter().map(|c| c.command).collect();
+        assert_eq!(
+            commands,
+            vec![Command::CmdStart as u64, Command::CmdHeader as u64]
+        );
+    }
+
+    #[test]
+    fn test_entry_debug() {
+        let e = Entry {
+            packet_type: PacketType::PtData as u8,
+            length: FIXED_SIZE_FILE_ENTRY as u32 + 10240,
+            entry_type: EntryType::Event1,
+            number: 7,
+            data: (0..10240).map(|i| i as u8).collect(),
+            raw: None,
+            received_at: None,
+        };
+
+        let debug = format!("{:?}", e);
+        assert_eq!(
+            debug,
+            "Entry { packet_type: 2, length: 10257, entry_type: Event1, number: 7, \
+             data: <10240 bytes: 0001020304050607..f8f9fafbfcfdfeff>, raw: None, \
+             received_at: None }"
+        );
+        assert!(e.full_debug().len() > 10240);
+        assert!(e.full_debug().contains("data: [0, 1, 2, 3,"));
+
+        let e = Entry {
+            data: vec![0xca, 0xfe],
+            raw: Some(vec![0xff; 19]),
+            ..e
+        };
+        let debug = format!("{:?}", e);
+        assert!(debug.contains("data: <2 bytes: cafe>"), "{debug}");
+        assert!(
+            debug.contains("raw: Some(<19 bytes: ffffffffffffffff..ffffffffffffffff>)"),
+            "{debug}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resume_from_checkpoint() {
+        let entries = (0..5)
+            .map(|n| MockPacket::data(n, EntryType::Event1, vec![n as u8]))
+            .collect::<Vec<_>>();
+        let server = MockStreamServer::builder()
+            .on(
+                Command::CmdHeader,
+                vec![MockPacket::ok(), MockPacket::header(5)],
+            )
+            .on(
+                Command::CmdStart,
+                [vec![MockPacket::ok()], entries[..3].to_vec()].concat(),
+            )
+            .on(
+                Command::CmdStart,
+                [vec![MockPacket::ok()], entries[3..].to_vec()].concat(),
+            )
+            .on(
+                Command::CmdStartBookmark,
+                vec![
+                    MockPacket::ok(),
+                    MockPacket::data(2, EntryType::Event1, vec![2]),
+                ],
+            )
+            .start()
+            .await
+            .unwrap();
+        let builder = || {
+            StreamClient::builder()
+                .server(server.addr())
+                .dedup_entries(true)
+                .process_entry_hook(|_| Ok(HookAction::Continue))
+        };
+
+        let mut client = builder().build().unwrap();
+        client.start_streaming().await.unwrap();
+        for _ in 0..3 {
+            client.read_entries().await.unwrap();
+        }
+        let checkpoint = client.checkpoint();
+        assert_eq!(checkpoint.resume_from(), 3);
+        drop(client);
+
+        // A new client resumes from the entry after the last delivered one
+        let mut client = builder().resume_from(checkpoint.clone()).build().unwrap();
+        assert_eq!(client.checkpoint(), checkpoint);
+        client.start_streaming().await.unwrap();
+        client.read_entries().await.unwrap();
+        assert_eq!(client.checkpoint().resume_from(), 4);
+
+        // A checkpoint taken before any entry is delivered resumes from the start bookmark
+        let bookmark = Bookmark::new(1, vec![2]);
+        let mut client = builder().build().unwrap();
+        client.connect_server().await.unwrap();
+        client
+            .exec_command_start_bookmark(bookmark.clone())
+            .await
+            .unwrap();
+        let checkpoint = client.checkpoint();
+        drop(client);
+
+        let mut client = builder().resume_from(checkpoint).build().unwrap();
+        client.start_streaming().await.unwrap();
+        client.read_entries().await.unwrap();
+        assert_eq!(client.checkpoint().resume_from(), 3);
+
+        let starts = server
+            .received()
+            .into_iter()
+            // The dropped clients stop the streaming
+            .filter(|c| ![Command::CmdHeader as u64, Command::CmdStop as u64].contains(&c.command))
+            .map(|c| (c.command, c.from_entry, c.bookmark))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            starts,
+            vec![
+                (Command::CmdStart as u64, 0, vec![]),
+                (Command::CmdStart as u64, 3, vec![]),
+                (Command::CmdStartBookmark as u64, 0, bookmark.encode()),
+                (Command::CmdStartBookmark as u64, 0, bookmark.encode()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_strict_unknown_packet_type() {
+        for strict in [false, true] {
+            let server = MockStreamServer::builder()
+                .on(
+                    Command::CmdHeader,
+                    vec![MockPacket::ok(), MockPacket::header(3)],
+                )
+                .on(
+                    Command::CmdStart,
+                    vec![
+                        MockPacket::ok(),
+                        MockPacket::data(0, EntryType::Event1, vec![0]),
+                        MockPacket::Raw(vec![0x07]),
+                        MockPacket::data(1, EntryType::Event2, vec![1]),
+                    ],
+                )
+                .start()
+                .await
+                .unwrap();
+
+            let mut client = StreamClient::builder()
+                .server(server.addr())
+                .strict(strict)
+                .build()
+                .unwrap();
+            client.start_streaming().await.unwrap();
+            let e = client.read_packet().await.unwrap().unwrap();
+            assert_eq!(e.number, 0);
+
+            let res = loop {
+                match client.read_packet().await {
+                    Ok(None) => {}
+                    res => break res,
+                }
+            };
+            match strict {
+                true => assert!(
+                    matches!(
+                        res,
+                        Err(ClientError::Decode(
+                            crate::entry_data::DecodeError::UnknownType(0x07)
+                        ))
+                    ),
+                    "{res:?}"
+                ),
+                // The unknown packet type is skipped as padding
+                false => assert_eq!(res.unwrap().unwrap().number, 1),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_strict_unknown_entry_type() {
+        for strict in [false, true] {
+            let server = MockStreamServer::builder()
+                .on(
+                    Command::CmdHeader,
+                    vec![MockPacket::ok(), MockPacket::header(2)],
+                )
+                .on(
+                    Command::CmdStart,
+                    vec![
+                        MockPacket::ok(),
+                        MockPacket::data(0, EntryType::Event(7), vec![0]),
+                    ],
+                )
+                .start()
+                .await
+                .unwrap();
+
+            let mut client = StreamClient::builder()
+                .server(server.addr())
+                .strict(strict)
+                .build()
+                .unwrap();
+            client.start_streaming().await.unwrap();
+
+            let res = client.read_packet().await;
+            match strict {
+                true => assert!(
+                    matches!(
+                        res,
+                        Err(ClientError::Decode(
+                            crate::entry_data::DecodeError::UnknownType(7)
+                        ))
+                    ),
+                    "{res:?}"
+                ),
+                false => assert_eq!(res.unwrap().unwrap().entry_type, EntryType::Event(7)),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_verify_sequence() {
+        let entries = [0, 1, 2, 3, 5, 6]
+            .into_iter()
+            .map(|n| MockPacket::data(n, EntryType::Event1, vec![n as u8]))
+            .collect::<Vec<_>>();
+        let server = MockStreamServer::builder()
+            .on(
+                Command::CmdHeader,
+                vec![MockPacket::ok(), MockPacket::header(7)],
+            )
+            .on(
+                Command::CmdStart,
+                [vec![MockPacket::ok()], entries].concat(),
+            )
+            .start()
+            .await
+            .unwrap();
+
+        let gaps = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let received = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut client = StreamClient::builder()
+            .server(server.addr())
+            .verify_sequence(true)
+            .event_listener(GapListener(gaps.clone()))
+            .process_entry_hook({
+                let received = received.clone();
+                move |e| {
+                    received.lock().unwrap().push(e.number);
+                    Ok(HookAction::Continue)
+                }
+            })
+            .build()
+            .unwrap();
+        client.start_streaming().await.unwrap();
+        for _ in 0..6 {
+            client.read_entries().await.unwrap();
+        }
+
+        assert_eq!(*gaps.lock().unwrap(), vec![(4, 5)]);
+        assert_eq!(*received.lock().unwrap(), vec![0, 1, 2, 3, 5, 6]);
+    }
+
+    #[tokio::test]
+    async fn test_fill_gaps() {
+        let entries = [0, 1, 2, 3, 5]
+            .into_iter()
+            .map(|n| MockPacket::data(n, EntryType::Event1, vec![n as u8]))
+            .collect::<Vec<_>>();
+        let server = MockStreamServer::builder()
+            .on(
+                Command::CmdHeader,
+                vec![MockPacket::ok(), MockPacket::header(7)],
+            )
+            .on(
+                Command::CmdStart,
+                [vec![MockPacket::ok()], entries].concat(),
+            )
+            // The range fetch of the missing entry
+            .on(
+                Command::CmdStart,
+                vec![
+                    MockPacket::ok(),
+                    MockPacket::data(4, EntryType::Event1, vec![4]),
+                ],
+            )
+            // The streaming restarted past the gap
+            .on(
+                Command::CmdStart,
+                vec![
+                    MockPacket::ok(),
+                    MockPacket::data(6, EntryType::Event1, vec![6]),
+                ],
+            )
+            .on(Command::CmdStop, vec![MockPacket::ok()])
+            .start()
+            .await
+            .unwrap();
+
+        let gaps = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let received = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut client = StreamClient::builder()
+            .server(server.addr())
+            .verify_sequence(true)
+            .fill_gaps(true)
+            .event_listener(GapListener(gaps.clone()))
+            .process_entry_hook({
+                let received = received.clone();
+                move |e| {
+                    received.lock().unwrap().push(e.number);
+                    Ok(HookAction::Continue)
+                }
+            })
+            .build()
+            .unwrap();
+        client.start_streaming().await.unwrap();
+        for _ in 0..6 {
+            client.read_entries().await.unwrap();
+        }
+
+        assert_eq!(*gaps.lock().unwrap(), vec![(4, 5)]);
+        assert_eq!(*received.lock().unwrap(), vec![0, 1, 2, 3, 4, 5, 6]);
+        assert!(client.is_streaming());
+        assert_eq!(client.resume_from(), 7);
+        let starts = server
+            .received()
+            .into_iter()
+            .filter(|c| c.command == Command::CmdStart as u64)
+            .map(|c| c.from_entry)
+            .collect::<Vec<_>>();
+        assert_eq!(starts, vec![0, 4, 6]);
+
+        assert!(matches!(
+            StreamClient::builder()
+                .server(server.addr())
+                .fill_gaps(true)
+                .build(),
+            Err(ClientError::InvalidConfig(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_command_responses_while_streaming() {
+        let server = MockStreamServer::builder()
+            .on(
+                Command::CmdHeader,
+                vec![MockPacket::ok(), MockPacket::header(3)],
+            )
+            .on(
+                Command::CmdStart,
+                vec![
+                    MockPacket::ok(),
+                    MockPacket::data(0, EntryType::Event1, vec![0]),
+                    MockPacket::error(CommandError::CmdErrAlreadyStarted, "already started"),
+                    MockPacket::data(1, EntryType::Event1, vec![1]),
+                    MockPacket::data_rsp(7, EntryType::Event2, vec![7, 7]),
+                    MockPacket::header(3),
+                    MockPacket::ok(),
+                    MockPacket::data(2, EntryType::Event1, vec![2]),
+                ],
+            )
+            .start()
+            .await
+            .unwrap();
+
+        let mut client = StreamClient::new(server.addr()).unwrap();
+        for n in 0..3u64 {
+            let e = client.next_entry().await.unwrap();
+            assert_eq!(e.number, n);
+            assert_eq!(e.data, vec![n as u8]);
+        }
+    }
+
+    #[cfg(feature = "compression")]
+    #[tokio::test]
+    async fn test_gzip_compressed_entries() {
+        use flate2::{write::GzEncoder, Compression as Level};
+        use std::io::Write;
+
+        let payload = b"compressed entry payload".repeat(10);
+        let mut encoder = GzEncoder::new(Vec::new(), Level::default());
+        encoder.write_all(&payload).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            let mut b = Vec::new();
+            for data in [compressed, vec![0xde, 0xad]] {
+                let e = Entry {
+                    packet_type: PacketType::PtData as u8,
+                    entry_type: EntryType::Event1,
+                    data,
+                    ..Default::default()
+                };
+                b.extend_from_slice(&e.encode_to_binary());
+            }
+            conn.write_all(&b).await.unwrap();
+            let _ = conn.read(&mut [0u8; 1]).await;
+        });
+
+        let mut client = StreamClient::builder()
+            .server(server)
+            .compression(Compression::Gzip)
+            .build()
+            .unwrap();
+        client.connect_server().await.unwrap();
+
+        let e = client.read_packet().await.unwrap().unwrap();
+        assert_eq!(e.data, payload);
+        assert_eq!(e.length as usize, FIXED_SIZE_FILE_ENTRY + payload.len());
+
+        match client.read_packet().await {
+            Err(ClientError::DecodeError(msg)) => {
+                assert!(msg.contains("error decompressing"), "{msg}")
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_command_timeout() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            read_command(&mut conn, false).await;
+            // Never answer the command
+            let _ = conn.read(&mut [0u8; 1]).await;
+        });
+
+        let mut client = StreamClient::builder()
+            .server(server)
+            .command_timeout(Duration::from_millis(100))
+            .build()
+            .unwrap();
+        assert_eq!(
+            StreamClient::new("127.0.0.1:6900".to_string())
+                .unwrap()
+                .command_timeout,
+            DEFAULT_COMMAND_TIMEOUT
+        );
+        client.connect_server().await.unwrap();
+
+        let res = client.exec_command_get_header().await;
+        assert!(matches!(res, Err(ClientError::Timeout)));
+        assert!(!client.connected);
+        assert!(client.conn.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_socket_options() {
+        let server = MockStreamServer::builder().start().await.unwrap();
+
+        let mut client = StreamClient::builder()
+            .server(server.addr())
+            .tcp_nodelay(true)
+            .so_keepalive(Duration::from_secs(30))
+            .read_timeout(Duration::from_millis(100))
+            .build()
+            .unwrap();
+        client.connect_server().await.unwrap();
+
+        let Some(Connection::Plain(stream)) = client.conn.as_ref().map(|c| c.get_ref()) else {
+            panic!("expected a plain connection");
+        };
+        assert!(stream.nodelay().unwrap());
+        assert!(socket2::SockRef::from(stream).keepalive().unwrap());
+
+        // No data is ever sent without a command
+        let start = std::time::Instant::now();
+        let res = client.read_packet().await;
+        assert!(
+            matches!(res, Err(ClientError::NetworkError(ref e)) if e.kind() == std::io::ErrorKind::TimedOut)
+        );
+        assert!(start.elapsed() >= Duration::from_millis(100));
+
+        // Defaults leave nodelay off
+        let mut client = StreamClient::new(server.addr()).unwrap();
+        client.connect_server().await.unwrap();
+        let Some(Connection::Plain(stream)) = client.conn.as_ref().map(|c| c.get_ref()) else {
+            panic!("expected a plain connection");
+        };
+        assert!(!stream.nodelay().unwrap());
+
+        // The idle probe and the read timeout would race on the same read
+        let res = StreamClient::builder()
+            .server(server.addr())
+            .idle_timeout(Duration::from_millis(100))
+            .read_timeout(Duration::from_millis(100))
+            .build();
+        assert!(matches!(res, Err(ClientError::InvalidConfig(_))));
+    }
+
+    #[tokio::test]
+    async fn test_command_failed() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            read_command(&mut conn, true).await;
+            let result = ResultEntry {
+                packet_type: PacketType::PtResult as u8,
+                error_num: u32::from(CommandError::CmdErrBadFromEntry),
+                error_str: b"bad entry 99".to_vec(),
+                ..Default::default()
+            };
+            conn.write_all(&result.encode_to_binary()).await.unwrap();
+        });
+
+        let mut client = StreamClient::new(server).unwrap();
+        client.connect_server().await.unwrap();
+
+        let err = client.exec_command_get_entry(99).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Command failed: CmdErrBadFromEntry: bad entry 99"
+        );
+        assert!(matches!(
+            err,
+            ClientError::CommandFailed(CommandError::CmdErrBadFromEntry, ref s)
+                if s == "CmdErrBadFromEntry: bad entry 99"
+        ));
+    }
+
+    #[test]
+    fn test_result_entry_describe() {
+        let result = ResultEntry {
+            packet_type: PacketType::PtResult as u8,
+            error_num: u32::from(CommandError::CmdErrBadFromEntry),
+            error_str: b"entry 99999 out of range".to_vec(),
+            ..Default::default()
+        };
+        assert_eq!(
+            result.describe(),
+            "CmdErrBadFromEntry: entry 99999 out of range"
+        );
+
+        let result = ResultEntry {
+            error_num: u32::from(CommandError::CmdErrAlreadyStarted),
+            error_str: b"client 127.0.0.1:5000 already streaming".to_vec(),
+            ..result
+        };
+        assert_eq!(
+            result.describe(),
+            "CmdErrAlreadyStarted: client 127.0.0.1:5000 already streaming"
+        );
+
+        // Without an error string the error is described
+        let result = ResultEntry {
+            error_num: u32::from(CommandError::CmdErrBadFromBookmark),
+            error_str: Vec::new(),
+            ..result
+        };
+        assert_eq!(
+            result.describe(),
+            "CmdErrBadFromBookmark: invalid starting bookmark"
+        );
+
+        // Unknown error codes keep the code sent by the server
+        let result = ResultEntry {
+            error_num: 42,
+            ..result
+        };
+        assert_eq!(result.describe(), "Unknown(42): unknown error code 42");
+    }
+
+    #[test]
+    fn test_command_error_from_u32() {
+        assert_eq!(CommandError::from(0), CommandError::CmdErrOK);
+        assert_eq!(CommandError::from(1), CommandError::CmdErrAlreadyStarted);
+        assert_eq!(CommandError::from(4), CommandError::CmdErrBadFromBookmark);
+        assert_eq!(CommandError::from(9), CommandError::CmdErrInvalidCommand);
+        assert_eq!(CommandError::from(42), CommandError::Unknown(42));
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_version() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            while let Ok((mut conn, _)) = listener.accept().await {
+                read_command(&mut conn, false).await;
+                let header = HeaderEntry {
+                    packet_type: PacketType::PtHeader as u8,
+                    head_length: HEADER_SIZE as u32,
+                    version: 99,
+                    total_entries: 5,
+                    ..Default::default()
+                };
+                conn.write_all(&[ok_result(), header.encode_to_binary()].concat())
+                    .await
+                    .unwrap();
+            }
+        });
+
+        let mut client = StreamClient::new(server.clone()).unwrap();
+        client.connect_server().await.unwrap();
+        assert!(matches!(
+            client.exec_command_get_header().await,
+            Err(ClientError::UnsupportedVersion(99))
+        ));
+        assert!(client.header().is_none());
+
+        // Newer versions are accepted once added to the supported ones
+        let mut client = StreamClient::builder()
+            .server(server)
+            .supported_versions(vec![3, 99])
+            .build()
+            .unwrap();
+        client.connect_server().await.unwrap();
+        let header = client.exec_command_get_header().await.unwrap();
+        assert_eq!(header.version, 99);
+        assert_eq!(client.total_entries(), 5);
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn test_tracing_spans() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            read_command(&mut conn, false).await;
+            conn.write_all(&header_response(10)).await.unwrap();
+            conn.write_all(&data_entry(7)).await.unwrap();
+            let _ = conn.read(&mut [0u8; 1]).await;
+        });
+
+        let mut client = StreamClient::builder()
+            .server(server)
+            .process_entry_hook(|e| {
+                info!("Hook processing entry {}", e.number);
+                Ok(HookAction::Continue)
+            })
+            .build()
+            .unwrap();
+        client.connect_server().await.unwrap();
+
+        // The messages are logged within the spans
+        let logged_in = |span: String, message: &'static str| {
+            logs_assert(|lines: &[&str]| {
+                match lines
+                    .iter()
+                    .any(|l| l.contains(&span) && l.contains(message))
+                {
+                    true => Ok(()),
+                    false => Err(format!("{} not logged in {}", message, span)),
+                }
+            })
+        };
+
+        client.exec_command_get_header().await.unwrap();
+        logged_in(
+            format!(
+                "command{{client_id={} command=CmdHeader from_entry=0}}",
+                client.id
+            ),
+            "Executing command CmdHeader",
+        );
+
+        client.read_entries().await.unwrap();
+        logged_in(
+            format!("entry{{client_id={} entry_number=7}}", client.id),
+            "Hook processing entry 7",
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stop_drains_streamed_entries() {
+        let server = MockStreamServer::builder()
+            .on(Command::CmdStart, vec![MockPacket::ok()])
+            .on(
+                Command::CmdStop,
+                vec![
+                    MockPacket::data(0, EntryType::Event1, vec![0x00]),
+                    MockPacket::data(1, EntryType::Event1, vec![0x01]),
+                    MockPacket::ok(),
+                ],
+            )
+            .on(
+                Command::CmdStop,
+                vec![MockPacket::error(
+                    CommandError::CmdErrAlreadyStopped,
+                    "already stopped",
+                )],
+            )
+            .on(
+                Command::CmdHeader,
+                vec![MockPacket::ok(), MockPacket::header(2)],
+            )
+            .start()
+            .await
+            .unwrap();
+
+        let mut client = StreamClient::new(server.addr()).unwrap();
+        client.connect_server().await.unwrap();
+        client.exec_command_start(0).await.unwrap();
+        assert!(client.streaming);
+
+        // The entries sent before the stop result are discarded
+        client.exec_command_stop().await.unwrap();
+        assert!(!client.streaming);
+        assert_eq!(client.status(), ClientStatus::CsStopped);
+        let header = client.exec_command_get_header().await.unwrap();
+        assert_eq!(header.total_entries, 2);
+
+        // A stop of a stopped stream confirms the streaming is stopped
+        client.streaming = true;
+        assert!(matches!(
+            client.exec_command_stop().await,
+            Err(ClientError::CommandFailed(
+                CommandError::CmdErrAlreadyStopped,
+                _
+            ))
+        ));
+        assert!(!client.streaming);
+    }
+
+    #[tokio::test]
+    async fn test_hook_actions() {
+        let entries = (0..5)
+            .map(|n| MockPacket::data(n, EntryType::Event1, vec![n as u8]))
+            .collect::<Vec<_>>();
+        let server = MockStreamServer::builder()
+            .on(
+                Command::CmdHeader,
+                vec![MockPacket::ok(), MockPacket::header(5)],
+            )
+            .on(
+                Command::CmdStart,
+                [vec![MockPacket::ok()], entries].concat(),
+            )
+            .on(Command::CmdStop, vec![MockPacket::ok()])
+            .start()
+            .await
+            .unwrap();
+
+        // Skip the first entry and stop on the third one
+        let received = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let hook_received = received.clone();
+        let mut client = StreamClient::builder()
+            .server(server.addr())
+            .process_entry_hook(move |e| {
+                hook_received.lock().unwrap().push(e.number);
+                match e.number {
+                    0 => Ok(HookAction::Skip),
+                    2 => Ok(HookAction::Stop),
+                    _ => Ok(HookAction::Continue),
+                }
+            })
+            .build()
+            .unwrap();
+
+        tokio::time::timeout(Duration::from_secs(5), client.start())
+            .await
+            .expect("start should return once the hook stops")
+            .unwrap();
+
+        assert_eq!(*received.lock().unwrap(), vec![0, 1, 2]);
+        let commands: Vec<u64> = server.received().iter().map(|c| c.command).collect();
+        assert_eq!(commands.last(), Some(&(Command::CmdStop as u64)));
+        assert_eq!(client.resume_from(), 3);
+        assert_eq!(client.status(), ClientStatus::CsStopped);
+
+        // The connection is kept for other commands
+        let header = client.exec_command_get_header().await.unwrap();
+        assert_eq!(header.total_entries, 5);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit() {
+        let entries = (0..10)
+            .map(|n| MockPacket::data(n, EntryType::Event1, vec![n as u8]))
+            .collect::<Vec<_>>();
+        let server = MockStreamServer::builder()
+            .on(
+                Command::CmdHeader,
+                vec![MockPacket::ok(), MockPacket::header(10)],
+            )
+            .on(
+                Command::CmdStart,
+                [vec![MockPacket::ok()], entries].concat(),
+            )
+            .on(Command::CmdStop, vec![MockPacket::ok()])
+            .start()
+            .await
+            .unwrap();
+
+        let received = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let hook_received = received.clone();
+        let mut client = StreamClient::builder()
+            .server(server.addr())
+            .rate_limit(5)
+            .process_entry_hook(move |e| {
+                hook_received.lock().unwrap().push(e.number);
+                match e.number {
+                    9 => Ok(HookAction::Stop),
+                    _ => Ok(HookAction::Continue),
+                }
+            })
+            .build()
+            .unwrap();
+
+        let start = std::time::Instant::now();
+        tokio::time::timeout(Duration::from_secs(10), client.start())
+            .await
+            .expect("start should return once the hook stops")
+            .unwrap();
+
+        // Paced at 5 per second, without dropping entries
+        assert!(
+            start.elapsed() >= Duration::from_secs(1),
+            "{:?}",
+            start.elapsed()
+        );
+        assert_eq!(*received.lock().unwrap(), (0..10).collect::<Vec<u64>>());
+        assert!(StreamClient::builder().rate_limit(0).rate_limit.is_none());
+    }
+
+    // checksummed appends the big endian CRC32 of payload
+    fn checksummed(payload: &[u8]) -> Vec<u8> {
+        [payload, &crc32fast::hash(payload).to_be_bytes()].concat()
+    }
+
+    // SharedBuffer type for an in-memory file written by the client and read by the test
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<std::sync::Mutex<std::io::Cursor<Vec<u8>>>>);
+
+    impl SharedBuffer {
+        fn contents(&self) -> Vec<u8> {
+            self.0.lock().unwrap().get_ref().clone()
+        }
+    }
+
+    impl AsyncWrite for SharedBuffer {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<std::io::Result<usize>> {
+            Poll::Ready(std::io::Write::write(&mut *self.0.lock().unwrap(), buf))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    impl AsyncSeek for SharedBuffer {
+        fn start_seek(self: Pin<&mut Self>, position: std::io::SeekFrom) -> std::io::Result<()> {
+            std::io::Seek::seek(&mut *self.0.lock().unwrap(), position).map(|_| ())
+        }
+
+        fn poll_complete(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+        ) -> Poll<std::io::Result<u64>> {
+            Poll::Ready(Ok(self.0.lock().unwrap().position()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tee_to() {
+        let entries = (0..5)
+            .map(|n| MockPacket::data(n, EntryType::Event1, n.to_be_bytes().to_vec()))
+            .collect::<Vec<_>>();
+        let server = MockStreamServer::builder()
+            .on(
+                Command::CmdHeader,
+                vec![MockPacket::ok(), MockPacket::header(5)],
+            )
+            .on(
+                Command::CmdStart,
+                [vec![MockPacket::ok()], entries].concat(),
+            )
+            .start()
+            .await
+            .unwrap();
+
+        // Two entries of 25 bytes fit in a data page
+        let mut client = StreamClient::builder()
+            .server(server.addr())
+            .page_size(64)
+            .tee_flush_interval(Duration::ZERO)
+            .process_entry_hook(|_| Ok(HookAction::Continue))
+            .build()
+            .unwrap();
+        client.connect_server().await.unwrap();
+        let buffer = SharedBuffer::default();
+        client.tee_to(buffer.clone()).await.unwrap();
+        client.start_streaming().await.unwrap();
+
+        let read = |buffer: &SharedBuffer| {
+            let mut reader =
+                crate::stream_file::FileStreamReader::new(std::io::Cursor::new(buffer.contents()))
+                    .unwrap()
+                    .with_page_size(64)
+                    .unwrap();
+            let header = reader.header().clone();
+            let entries = reader
+                .entries()
+                .collect::<std::io::Result<Vec<_>>>()
+                .unwrap();
+            (header, entries)
+        };
+
+        // The entries are flushed as they're received
+        for _ in 0..3 {
+            client.read_entries().await.unwrap();
+        }
+        let (header, entries) = read(&buffer);
+        assert_eq!(header.total_entries, 3);
+        assert_eq!(header.version, 1);
+        assert_eq!(
+            entries.iter().map(|e| e.number).collect::<Vec<_>>(),
+            vec![0, 1, 2]
+        );
+
+        for _ in 0..2 {
+            client.read_entries().await.unwrap();
+        }
+        client.finish_tee().await.unwrap();
+        let (header, entries) = read(&buffer);
+        assert_eq!(header.total_entries, 5);
+        assert_eq!(
+            header.total_length,
+            crate::stream_file::PAGE_HEADER_SIZE + 2 * 64 + 25
+        );
+        for (n, e) in entries.iter().enumerate() {
+            assert_eq!(e.number, n as u64);
+            assert_eq!(e.data, (n as u64).to_be_bytes().to_vec());
+        }
+        assert_eq!(entries.len(), 5);
+        assert!(client.tee.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_validate_stream() {
+        let mut corrupted = checksummed(&[0x04]);
+        corrupted[0] ^= 0xff;
+        let server = MockStreamServer::builder()
+            .on(
+                Command::CmdHeader,
+                vec![MockPacket::ok(), MockPacket::header(7)],
+            )
+            .on(
+                Command::CmdStart,
+                vec![
+                    MockPacket::ok(),
+                    MockPacket::data(0, EntryType::Bookmark, vec![0x00, 0x01]),
+                    MockPacket::data(1, EntryType::Event1, checksummed(&[0x01])),
+                    MockPacket::data(2, EntryType::Event2, checksummed(&[0x02])),
+                    MockPacket::Raw(vec![PacketType::PtPadding as u8; 4]),
+                    MockPacket::data(3, EntryType::Event1, checksummed(&[0x03])),
+                    MockPacket::data(4, EntryType::Event1, corrupted),
+                    MockPacket::data(5, EntryType::Event(9), checksummed(&[0x05])),
+                    MockPacket::data(6, EntryType::Event1, checksummed(&[0x06])),
+                ],
+            )
+            .start()
+            .await
+            .unwrap();
+
+        let mut client = StreamClient::builder()
+            .server(server.addr())
+            .verify_checksums(true)
+            .process_entry_hook(|e| panic!("hook called for entry {}", e.number))
+            .build()
+            .unwrap();
+
+        let report = client.validate_stream(6).await.unwrap();
+        assert_eq!(report.entries, 6);
+        assert_eq!(report.decode_errors, 1);
+        assert_eq!(
+            report.bytes,
+            6 * FIXED_SIZE_FILE_ENTRY as u64 + 2 + 5 * CHECKSUM_SIZE as u64 + 5
+        );
+        assert_eq!(
+            report.entry_types,
+            HashMap::from([
+                (EntryType::Bookmark, 1),
+                (EntryType::Event1, 3),
+                (EntryType::Event2, 1),
+                (EntryType::Event(9), 1),
+            ])
+        );
+        assert_eq!(client.resume_from(), 6);
+
+        // The streaming goes on from the next entry
+        let report = client.validate_stream(1).await.unwrap();
+        assert_eq!(report.entries, 1);
+        assert_eq!(report.entry_types, HashMap::from([(EntryType::Event1, 1)]));
+    }
+
+    #[tokio::test]
+    async fn test_verify_checksums() {
+        let mut corrupted = checksummed(&[0x01, 0x02, 0x03]);
+        corrupted[1] ^= 0xff;
+        let server = MockStreamServer::builder()
+            .on(
+                Command::CmdEntry,
+                vec![
+                    MockPacket::ok(),
+                    MockPacket::data_rsp(1, EntryType::Event1, checksummed(&[0x01, 0x02, 0x03])),
+                ],
+            )
+            .on(
+                Command::CmdEntry,
+                vec![
+                    MockPacket::ok(),
+                    MockPacket::data_rsp(2, EntryType::Event1, corrupted),
+                ],
+            )
+            .on(
+                Command::CmdEntry,
+                vec![
+                    MockPacket::ok(),
+                    MockPacket::data_rsp(3, EntryType::Event1, vec![0x01]),
+                ],
+            )
+            .start()
+            .await
+            .unwrap();
+
+        let mut client = StreamClient::builder()
+            .server(server.addr())
+            .verify_checksums(true)
+            .build()
+            .unwrap();
+        client.connect_server().await.unwrap();
+
+        // The checksum is removed from a matching payload
+        let e = client.exec_command_get_entry(1).await.unwrap();
+        assert_eq!(e.data, vec![0x01, 0x02, 0x03]);
+        assert_eq!(e.length, (FIXED_SIZE_FILE_ENTRY + 3) as u32);
+
+        let res = client.exec_command_get_entry(2).await;
+        assert!(
+            matches!(&res, Err(ClientError::DecodeError(msg)) if msg.contains("checksum mismatch")),
+            "{:?}",
+            res
+        );
+
+        // Payloads too short to carry a checksum are rejected
+        let res = client.exec_command_get_entry(3).await;
+        assert!(matches!(res, Err(ClientError::DecodeError(_))), "{:?}", res);
+    }
+
+    #[tokio::test]
+    async fn test_async_process_hook() {
+        let entries = (0..5)
+            .map(|n| MockPacket::data(n, EntryType::Event1, vec![n as u8]))
+            .collect::<Vec<_>>();
+        let server = MockStreamServer::builder()
+            .on(
+                Command::CmdHeader,
+                vec![MockPacket::ok(), MockPacket::header(5)],
+            )
+            .on(
+                Command::CmdStart,
+                [vec![MockPacket::ok()], entries].concat(),
+            )
+            .on(Command::CmdStop, vec![MockPacket::ok()])
+            .start()
+            .await
+            .unwrap();
+
+        let received = Arc::new(tokio::sync::Mutex::new(Vec::new()));
+        let hook_received = received.clone();
+        let mut client = StreamClient::new(server.addr()).unwrap();
+        client.set_async_process_hook(move |e| {
+            let received = hook_received.clone();
+            Box::pin(async move {
+                // Later entries are not processed before the sleep completes
+                sleep(Duration::from_millis(10 * (5 - e.number))).await;
+                received.lock().await.push(e.number);
+                match e.number {
+                    4 => Ok(HookAction::Stop),
+                    _ => Ok(HookAction::Continue),
+                }
+            })
+        });
+
+        tokio::time::timeout(Duration::from_secs(5), client.start())
+            .await
+            .expect("start should return once the hook stops")
+            .unwrap();
+
+        assert_eq!(*received.lock().await, (0..5).collect::<Vec<u64>>());
+        assert_eq!(client.resume_from(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_next_entry_into() {
+        let entries = (0..3)
+            .map(|n| MockPacket::data(n, EntryType::Event1, vec![n as u8; 8 + n as usize]))
+            .collect::<Vec<_>>();
+        let server = MockStreamServer::builder()
+            .on(
+                Command::CmdHeader,
+                vec![MockPacket::ok(), MockPacket::header(3)],
+            )
+            .on(
+                Command::CmdStart,
+                [vec![MockPacket::ok()], entries].concat(),
+            )
+            .start()
+            .await
+            .unwrap();
+
+        let mut client = StreamClient::new(server.addr()).unwrap();
+        let mut buf = Vec::with_capacity(64);
+        let ptr = buf.as_ptr();
+
+        for n in 0..3u64 {
+            buf.clear();
+            let header = client.next_entry_into(&mut buf).await.unwrap();
+            assert_eq!(header.number, n);
+            assert_eq!(header.entry_type, EntryType::Event1);
+            assert_eq!(
+                header.length,
+                (FIXED_SIZE_FILE_ENTRY + 8 + n as usize) as u32
+            );
+            assert_eq!(buf, vec![n as u8; 8 + n as usize]);
+            // The same allocation is reused
+            assert_eq!(buf.as_ptr(), ptr);
+        }
+        assert_eq!(client.resume_from(), 3);
+
+        // The data is appended to the buffer
+        let mut buf = vec![0xff];
+        client.follow_from(0);
+        client.close_connection();
+        client.next_entry_into(&mut buf).await.unwrap();
+        assert_eq!(buf, [vec![0xff], vec![0; 8]].concat());
+    }
+
+    #[tokio::test]
+    async fn test_next_entry_into_packets() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            // Command responses are streamed between the entries, then the connection is closed
+            // in a padding region
+            let (mut conn, _) = listener.accept().await.unwrap();
+            read_command(&mut conn, false).await;
+            conn.write_all(&header_response(3)).await.unwrap();
+            read_command(&mut conn, true).await;
+            let mut data_rsp = data_entry(7);
+            data_rsp[0] = PacketType::PtDataRsp as u8;
+            let packets = [
+                ok_result(),
+                data_entry(0),
+                ok_result(),
+                header_response(3),
+                data_rsp,
+                data_entry(1),
+                vec![PacketType::PtPadding as u8; 2],
+            ];
+            conn.write_all(&packets.concat()).await.unwrap();
+            drop(conn);
+
+            // The streaming is restored from the next entry
+            let (mut conn, _) = listener.accept().await.unwrap();
+            let (_, from_entry) = read_command(&mut conn, true).await;
+            conn.write_all(&[ok_result(), data_entry(from_entry)].concat())
+                .await
+                .unwrap();
+            let _ = conn.read(&mut [0u8; 1]).await;
+        });
+
+        let mut client = StreamClient::builder()
+            .server(server)
+            .reconnect_delay(Duration::from_millis(10))
+            .build()
+            .unwrap();
+        let mut buf = Vec::new();
+        for n in 0..3u64 {
+            buf.clear();
+            let header = client.next_entry_into(&mut buf).await.unwrap();
+            assert_eq!(header.number, n);
+            assert_eq!(buf, n.to_be_bytes());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_start_header_retry() {
+        // The first header result is cut short, timing out the command
+        let server = MockStreamServer::builder()
+            .on(
+                Command::CmdHeader,
+                vec![MockPacket::Raw(vec![PacketType::PtResult as u8])],
+            )
+            .on(
+                Command::CmdHeader,
+                vec![MockPacket::ok(), MockPacket::header(1)],
+            )
+            .on(
+                Command::CmdStart,
+                vec![
+                    MockPacket::ok(),
+                    MockPacket::data(0, EntryType::Event1, vec![1]),
+                ],
+            )
+            .start()
+            .await
+            .unwrap();
+
+        let mut client = StreamClient::builder()
+            .server(server.addr())
+            .command_timeout(Duration::from_millis(100))
+            .reconnect_delay(Duration::from_millis(10))
+            .max_reconnect_retries(2)
+            .build()
+            .unwrap();
+        let mut buf = Vec::new();
+        let header = client.next_entry_into(&mut buf).await.unwrap();
+        assert_eq!(header.number, 0);
+        assert_eq!(client.total_entries, 1);
+
+        let received = server.received();
+        let commands = received.iter().map(|c| c.command).collect::<Vec<_>>();
+        assert_eq!(
+            commands,
+            vec![
+                Command::CmdHeader as u64,
+                Command::CmdHeader as u64,
+                Command::CmdStart as u64
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_start_header_retries_exhausted() {
+        let server = MockStreamServer::builder()
+            .on(
+                Command::CmdHeader,
+                vec![MockPacket::Raw(vec![PacketType::PtResult as u8])],
+            )
+            .start()
+            .await
+            .unwrap();
+
+        let mut client = StreamClient::builder()
+            .server(server.addr())
+            .command_timeout(Duration::from_millis(100))
+            .reconnect_delay(Duration::from_millis(10))
+            .max_reconnect_retries(2)
+            .build()
+            .unwrap();
+        let res = client.next_entry_into(&mut Vec::new()).await;
+        assert!(matches!(res, Err(ClientError::Timeout)), "{res:?}");
+        assert_eq!(server.received().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_start_header_rejected() {
+        let server = MockStreamServer::builder()
+            .on(
+                Command::CmdHeader,
+                vec![MockPacket::error(
+                    CommandError::CmdErrInvalidCommand,
+                    "unknown stream type",
+                )],
+            )
+            .start()
+            .await
+            .unwrap();
+
+        // The error result is returned without retrying, even with unlimited retries
+        let mut client = StreamClient::builder()
+            .server(server.addr())
+            .reconnect_delay(Duration::from_millis(10))
+            .build()
+            .unwrap();
+        let res = timeout(Duration::from_secs(5), client.start())
+            .await
+            .unwrap();
+        let Err(ClientError::StartFailed(_, e)) = res else {
+            panic!("unexpected result {res:?}");
+        };
+        assert!(matches!(
+            *e,
+            ClientError::CommandFailed(CommandError::CmdErrInvalidCommand, _)
+        ));
+        assert_eq!(server.received().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_servers_failover() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let down = listener.local_addr().unwrap().to_string();
+        drop(listener);
+        let server = MockStreamServer::builder()
+            .on(
+                Command::CmdHeader,
+                vec![MockPacket::ok(), MockPacket::header(1)],
+            )
+            .start()
+            .await
+            .unwrap();
+
+        let mut client = StreamClient::builder()
+            .servers(vec![down.clone(), server.addr()])
+            .reconnect_delay(Duration::from_millis(10))
+            .max_reconnect_retries(3)
+            .build()
+            .unwrap();
+        assert_eq!(client.server, down);
+
+        client.connect_server().await.unwrap();
+        assert_eq!(client.server, server.addr());
+        assert_eq!(client.peer_addr().unwrap().to_string(), server.addr());
+        assert_eq!(
+            client
+                .exec_command_get_header()
+                .await
+                .unwrap()
+                .total_entries,
+            1
+        );
+
+        // The rotation wraps around the list
+        client.rotate_server();
+        assert_eq!(client.server, down);
+
+        let res = StreamClient::builder()
+            .servers(vec![server.addr(), "localhost".to_string()])
+            .build();
+        assert!(matches!(res, Err(ClientError::InvalidServerAddress(_))));
+    }
+
+    #[tokio::test]
+    async fn test_dedup_entries() {
+        // Entries 0..5 replayed twice
+        let entries = (0..5)
+            .chain(0..5)
+            .map(|n| MockPacket::data(n, EntryType::Event1, vec![n as u8]))
+            .collect::<Vec<_>>();
+        let server = MockStreamServer::builder()
+            .on(
+                Command::CmdStart,
+                [vec![MockPacket::ok()], entries].concat(),
+            )
+            .start()
+            .await
+            .unwrap();
+
+        let received = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let hook_received = received.clone();
+        let mut client = StreamClient::builder()
+            .server(server.addr())
+            .dedup_entries(true)
+            .process_entry_hook(move |e| {
+                hook_received.lock().unwrap().push(e.number);
+                Ok(HookAction::Continue)
+            })
+            .build()
+            .unwrap();
+        client.connect_server().await.unwrap();
+        client.exec_command_start(0).await.unwrap();
+
+        for _ in 0..10 {
+            client.read_entries().await.unwrap();
+        }
+        assert_eq!(*received.lock().unwrap(), (0..5).collect::<Vec<u64>>());
+        assert_eq!(client.resume_from(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_bytes_consumed() {
+        let entries = (0..3)
+            .map(|n| MockPacket::data(n, EntryType::Event1, vec![n as u8; 10 * n as usize]))
+            .collect::<Vec<_>>();
+        let entries_size: usize = entries.iter().map(|p| p.encode_to_binary().len()).sum();
+        let server = MockStreamServer::builder()
+            .on(
+                Command::CmdHeader,
+                vec![
+                    MockPacket::ok(),
+                    MockPacket::Header(HeaderEntry {
+                        packet_type: PacketType::PtHeader as u8,
+                        head_length: HEADER_SIZE as u32,
+                        version: 1,
+                        total_entries: 3,
+                        total_length: 200,
+                        ..Default::default()
+                    }),
+                ],
+            )
+            .on(
+                Command::CmdStart,
+                [vec![MockPacket::ok()], entries].concat(),
+            )
+            .start()
+            .await
+            .unwrap();
+
+        let mut client = StreamClient::new(server.addr()).unwrap();
+        assert_eq!(client.progress(), None);
+        client.connect_server().await.unwrap();
+        client.exec_command_get_header().await.unwrap();
+        assert_eq!(
+            client.bytes_consumed(),
+            (FIXED_SIZE_RESULT_ENTRY + HEADER_SIZE) as u64
+        );
+
+        client.exec_command_start(0).await.unwrap();
+        let before = client.bytes_consumed();
+        for _ in 0..3 {
+            client.read_entries().await.unwrap();
+        }
+        assert_eq!(client.bytes_consumed() - before, entries_size as u64);
+
+        let expected = client.bytes_consumed() as f64 / 200.0;
+        assert_eq!(client.progress(), Some(expected));
+    }
+
+    #[tokio::test]
+    async fn test_server_capabilities() {
+        let server = MockStreamServer::builder()
+            .on(
+                Command::CmdHeader,
+                vec![MockPacket::ok(), MockPacket::header(0)],
+            )
+            .on(
+                Command::CmdEntry,
+                vec![MockPacket::error(
+                    CommandError::CmdErrBadFromEntry,
+                    "entry not found",
+                )],
+            )
+            .on(
+                Command::CmdBookmark,
+                vec![MockPacket::error(
+                    CommandError::CmdErrInvalidCommand,
+                    "invalid command",
+                )],
+            )
+            .start()
+            .await
+            .unwrap();
+
+        let mut client = StreamClient::new(server.addr()).unwrap();
+        let capabilities = client.server_capabilities().await.unwrap();
+        assert_eq!(
+            capabilities,
+            ServerCapabilities {
+                header: true,
+                entry: true,
+                bookmark: false,
+            }
+        );
+
+        // Only read-only commands are sent
+        let commands: Vec<u64> = server.received().iter().map(|c| c.command).collect();
+        assert_eq!(
+            commands,
+            vec![
+                Command::CmdHeader as u64,
+                Command::CmdEntry as u64,
+                Command::CmdBookmark as u64
+            ]
+        );
+        assert!(client.is_connected());
+    }
+
+    #[tokio::test]
+    async fn test_server_capabilities_reconnects_after_rejection() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            // The rejected command closes the connection, like the stream server does
+            let (mut conn, _) = listener.accept().await.unwrap();
+            let (cmd, _) = read_command(&mut conn, false).await;
+            assert_eq!(cmd, Command::CmdHeader as u64);
+            let result = ResultEntry {
+                packet_type: PacketType::PtResult as u8,
+                error_num: u32::from(CommandError::CmdErrInvalidCommand),
+                error_str: b"Invalid command".to_vec(),
+                ..Default::default()
+            };
+            conn.write_all(&result.encode_to_binary()).await.unwrap();
+            drop(conn);
+
+            let (mut conn, _) = listener.accept().await.unwrap();
+            let (cmd, _) = read_command(&mut conn, true).await;
+            assert_eq!(cmd, Command::CmdEntry as u64);
+            conn.write_all(&[ok_result(), data_entry(0)].concat())
+                .await
+                .unwrap();
+            let (cmd, _) = read_command(&mut conn, false).await;
+            assert_eq!(cmd, Command::CmdBookmark as u64);
+            let mut bookmark = [0u8; 5];
+            conn.read_exact(&mut bookmark).await.unwrap();
+            let result = ResultEntry {
+                packet_type: PacketType::PtResult as u8,
+                error_num: u32::from(CommandError::CmdErrBadFromBookmark),
+                ..Default::default()
+            };
+            conn.write_all(&result.encode_to_binary()).await.unwrap();
+            let _ = conn.read(&mut [0u8; 1]).await;
+        });
+
+        let mut client = StreamClient::new(server).unwrap();
+        let capabilities = timeout(Duration::from_secs(5), client.server_capabilities())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            capabilities,
+            ServerCapabilities {
+                header: false,
+                entry: true,
+                bookmark: true,
+            }
+        );
+        assert!(client.is_connected());
+    }
+
+    #[tokio::test]
+    async fn test_start_from_last() {
+        let server = MockStreamServer::builder()
+            .on(
+                Command::CmdHeader,
+                vec![MockPacket::ok(), MockPacket::header(5000)],
+            )
+            .on(
+                Command::CmdHeader,
+                vec![MockPacket::ok(), MockPacket::header(500)],
+            )
+            .on(
+                Command::CmdStart,
+                vec![
+                    MockPacket::ok(),
+                    MockPacket::data(4000, EntryType::Event1, vec![0x01]),
+                ],
+            )
+            .on(Command::CmdStop, vec![MockPacket::ok()])
+            .start()
+            .await
+            .unwrap();
+
+        let mut client = StreamClient::builder()
+            .server(server.addr())
+            .process_entry_hook(|_| Ok(HookAction::Stop))
+            .build()
+            .unwrap();
+
+        let starts = |server: &MockStreamServer| -> Vec<u64> {
+            server
+                .received()
+                .iter()
+                .filter(|c| c.command == Command::CmdStart as u64)
+                .map(|c| c.from_entry)
+                .collect()
+        };
+
+        // The last 1000 of 5000 entries
+        client.start_from_last(1000).await.unwrap();
+        assert_eq!(starts(&server), vec![4000]);
+        assert_eq!(client.resume_from(), 4001);
+
+        // Fewer entries than requested start from the first one
+        client.start_from_last(1000).await.unwrap();
+        assert_eq!(starts(&server), vec![4000, 0]);
+        let headers = server
+            .received()
+            .iter()
+            .filter(|c| c.command == Command::CmdHeader as u64)
+            .count();
+        assert_eq!(headers, 2);
+    }
+
+    #[tokio::test]
+    async fn test_max_entry_size() {
+        // Entry response declaring a 4 GiB length, with no data behind it
+        let mut oversized = vec![PacketType::PtDataRsp as u8];
+        oversized.extend_from_slice(&u32::MAX.to_be_bytes());
+        oversized.extend_from_slice(&[0u8; 12]);
+        let server = MockStreamServer::builder()
+            .on(
+                Command::CmdEntry,
+                vec![MockPacket::ok(), MockPacket::Raw(oversized)],
+            )
+            .start()
+            .await
+            .unwrap();
+
+        let mut client = StreamClient::new(server.addr()).unwrap();
+        client.connect_server().await.unwrap();
+        let res = client.exec_command_get_entry(0).await;
+        assert!(
+            matches!(&res, Err(ClientError::DecodeError(msg)) if msg.contains("maximum entry size")),
+            "{:?}",
+            res
+        );
+        assert!(!client.is_connected());
+        assert!(client.read_buffer.capacity() < DEFAULT_MAX_ENTRY_SIZE);
+
+        // A configured limit
+        let server = MockStreamServer::builder()
+            .on(
+                Command::CmdEntry,
+                vec![
+                    MockPacket::ok(),
+                    MockPacket::data_rsp(0, EntryType::Event1, vec![0; 100]),
+                ],
+            )
+            .start()
+            .await
+            .unwrap();
+        let mut client = StreamClient::builder()
+            .server(server.addr())
+            .max_entry_size(64)
+            .build()
+            .unwrap();
+        client.connect_server().await.unwrap();
+        let res = client.exec_command_get_entry(0).await;
+        assert!(matches!(res, Err(ClientError::DecodeError(_))), "{:?}", res);
+        assert!(!client.is_connected());
+    }
+
+    #[tokio::test]
+    async fn test_client_state() {
+        struct Listener(Arc<std::sync::Mutex<Vec<ClientState>>>);
+
+        impl EventListener for Listener {
+            fn on_state_change(&self, _client_id: &str, state: ClientState) {
+                self.0.lock().unwrap().push(state);
+            }
+        }
+
+        let entries = (0..3)
+            .map(|n| MockPacket::data(n, EntryType::Event1, vec![n as u8]))
+            .collect::<Vec<_>>();
+        let server = MockStreamServer::builder()
+            .on(
+                Command::CmdHeader,
+                vec![MockPacket::ok(), MockPacket::header(3)],
+            )
+            .on(
+                Command::CmdStart,
+                [vec![MockPacket::ok()], entries].concat(),
+            )
+            .on(Command::CmdStop, vec![MockPacket::ok()])
+            .start()
+            .await
+            .unwrap();
+
+        let states = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut client = StreamClient::builder()
+            .server(server.addr())
+            .event_listener(Listener(states.clone()))
+            .process_entry_hook(|e| match e.number {
+                2 => Ok(HookAction::Stop),
+                _ => Ok(HookAction::Continue),
+            })
+            .build()
+            .unwrap();
+        assert_eq!(client.state(), ClientState::default());
+
+        client.connect_server().await.unwrap();
+        assert!(client.is_connected());
+        assert!(!client.is_started());
+        assert!(!client.is_streaming());
+
+        client.start().await.unwrap();
+
+        // Stopped by the hook keeping the connection
+        assert!(client.is_connected());
+        assert!(!client.is_started());
+        assert!(!client.is_streaming());
+
+        let connected = ClientState {
+            connected: true,
+            ..Default::default()
+        };
+        let states = states.lock().unwrap().clone();
+        assert_eq!(states.first(), Some(&connected));
+        assert!(states.contains(&ClientState {
+            connected: true,
+            started: true,
+            streaming: true,
+        }));
+        assert_eq!(states.last(), Some(&connected));
+        // Only the changes are reported
+        assert!(states.windows(2).all(|w| w[0] != w[1]));
+
+        client.close_connection();
+        assert_eq!(client.state(), ClientState::default());
+    }
+
+    #[tokio::test]
+    async fn test_event_listener() {
+        #[derive(Default)]
+        struct Counter {
+            connected: AtomicUsize,
+            disconnected: AtomicUsize,
+            entries: AtomicUsize,
+            errors: AtomicUsize,
+        }
+
+        struct Listener(Arc<Counter>);
+
+        impl EventListener for Listener {
+            fn on_connected(&self, client_id: &str, _server: &str) {
+                assert!(!client_id.is_empty());
+                self.0.connected.fetch_add(1, Ordering::SeqCst);
+            }
+            fn on_disconnected(&self, _client_id: &str) {
+                self.0.disconnected.fetch_add(1, Ordering::SeqCst);
+            }
+            fn on_entry(&self, _entry: &Entry) {
+                self.0.entries.fetch_add(1, Ordering::SeqCst);
+            }
+            fn on_error(&self, _error: &ClientError) {
+                self.0.errors.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let entries = (0..3)
+            .map(|n| MockPacket::data(n, EntryType::Event1, vec![n as u8]))
+            .collect::<Vec<_>>();
+        let server = MockStreamServer::builder()
+            .on(
+                Command::CmdStart,
+                [vec![MockPacket::ok()], entries].concat(),
+            )
+            .start()
+            .await
+            .unwrap();
+
+        let counter = Arc::new(Counter::default());
+        let mut client = StreamClient::builder()
+            .server(server.addr())
+            .event_listener(Listener(counter.clone()))
+            .process_entry_hook(|e| match e.number {
+                1 => Err(ClientError::DecodeError("bad entry".to_string())),
+                _ => Ok(HookAction::Continue),
+            })
+            .on_hook_error(HookErrorPolicy::Ignore)
+            .build()
+            .unwrap();
+
+        client.connect_server().await.unwrap();
+        client.exec_command_start(0).await.unwrap();
+        for _ in 0..3 {
+            client.read_entries().await.unwrap();
+        }
+        client.close_connection();
+        // Closing a closed connection isn't notified again
+        client.close_connection();
+
+        assert_eq!(counter.connected.load(Ordering::SeqCst), 1);
+        assert_eq!(counter.entries.load(Ordering::SeqCst), 3);
+        assert_eq!(counter.errors.load(Ordering::SeqCst), 1);
+        assert_eq!(counter.disconnected.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_network_error_source() {
+        use std::error::Error;
+
+        fn read() -> Result<(), ClientError> {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::ConnectionReset,
+                "peer reset",
+            ))?
+        }
+
+        let err = read().unwrap_err();
+        assert_eq!(err.to_string(), "network error: peer reset");
+        let source = err.source().unwrap();
+        assert_eq!(source.to_string(), "peer reset");
+    }
+
+    #[tokio::test]
+    async fn test_exec_command_closed_connection() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            let (conn, _) = listener.accept().await.unwrap();
+            drop(conn);
+        });
+
+        let mut client = StreamClient::new(server).unwrap();
+        client.connect_server().await.unwrap();
+
+        // Sending to and reading from the closed connection fail instead of panicking
+        for _ in 0..3 {
+            let res = client.exec_command_get_entry(0).await;
+            assert!(
+                matches!(res, Err(ClientError::NetworkError(_))),
+                "{:?}",
+                res
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_exec_command_retry() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            // The first connection is closed right away
+            let (conn, _) = listener.accept().await.unwrap();
+            drop(conn);
+
+            let (mut conn, _) = listener.accept().await.unwrap();
+            loop {
+                let (_, from_entry) = read_command(&mut conn, true).await;
+                let entry = Entry {
+                    packet_type: PacketType::PtDataRsp as u8,
+                    entry_type: EntryType::Event1,
+                    number: from_entry,
+                    data: vec![0xaa],
+                    ..Default::default()
+                };
+                let response = [ok_result(), entry.encode_to_binary()].concat();
+                conn.write_all(&response).await.unwrap();
+            }
+        });
+
+        let mut client = StreamClient::builder()
+            .server(server)
+            .command_retries(2)
+            .build()
+            .unwrap();
+        client.connect_server().await.unwrap();
+        let first_id = client.client_id().to_string();
+
+        let e = client.exec_command_get_entry(7).await.unwrap();
+        assert_eq!(e.number, 7);
+        assert_eq!(e.data, vec![0xaa]);
+        assert_ne!(client.client_id(), first_id);
+        assert!(!client.streaming);
+    }
+
+    #[tokio::test]
+    async fn test_get_last_entry() {
+        let server = MockStreamServer::builder()
+            .on(
+                Command::CmdHeader,
+                vec![MockPacket::ok(), MockPacket::header(100)],
+            )
+            .on(
+                Command::CmdHeader,
+                vec![MockPacket::ok(), MockPacket::header(0)],
+            )
+            .on(
+                Command::CmdEntry,
+                vec![
+                    MockPacket::ok(),
+                    MockPacket::data_rsp(99, EntryType::Event2, vec![0x99]),
+                ],
+            )
+            .start()
+            .await
+            .unwrap();
+
+        let mut client = StreamClient::new(server.addr()).unwrap();
+        client.connect_server().await.unwrap();
+
+        let e = client.get_last_entry().await.unwrap();
+        assert_eq!(e.number, 99);
+        assert_eq!(e.data, vec![0x99]);
+        let received = server.received();
+        assert_eq!(received[1].command, Command::CmdEntry as u64);
+        assert_eq!(received[1].from_entry, 99);
+
+        // Empty stream, once the cached header is refreshed
+        client.refresh_header().await.unwrap();
+        assert!(matches!(
+            client.get_last_entry().await,
+            Err(ClientError::EntryNotFound)
+        ));
+        assert_eq!(server.received().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_header_cache() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            for total_entries in [10, 12] {
+                read_command(&mut conn, false).await;
+                let header = HeaderEntry {
+                    packet_type: PacketType::PtHeader as u8,
+                    head_length: HEADER_SIZE as u32,
+                    version: 3,
+                    system_id: ChainId(1101),
+                    total_entries,
+                    ..Default::default()
+                };
+                conn.write_all(&[ok_result(), header.encode_to_binary()].concat())
+                    .await
+                    .unwrap();
+            }
+            let _ = conn.read(&mut [0u8; 1]).await;
+        });
+
+        let mut client = StreamClient::new(server).unwrap();
+        assert!(client.header().is_none());
+        assert_eq!(client.total_entries(), 0);
+        client.connect_server().await.unwrap();
+
+        client.exec_command_get_header().await.unwrap();
+        let header = client.header().unwrap();
+        assert_eq!(header.system_id, ChainId(1101));
+        assert_eq!(header.version, 3);
+        assert_eq!(client.total_entries(), 10);
+
+        // The cache is refreshed on every header command
+        client.exec_command_get_header().await.unwrap();
+        assert_eq!(client.header().unwrap().total_entries, 12);
+        assert_eq!(client.total_entries(), 12);
+    }
+
+    #[tokio::test]
+    async fn test_header_cache_ttl() {
+        let server = MockStreamServer::builder()
+            .on(
+                Command::CmdHeader,
+                vec![MockPacket::ok(), MockPacket::header(10)],
+            )
+            .on(
+                Command::CmdHeader,
+                vec![MockPacket::ok(), MockPacket::header(11)],
+            )
+            .start()
+            .await
+            .unwrap();
+
+        let mut client = StreamClient::builder()
+            .server(server.addr())
+            .header_cache_ttl(Duration::from_millis(200))
+            .build()
+            .unwrap();
+        client.connect_server().await.unwrap();
+
+        // Two rapid header calls send a single command
+        assert_eq!(client.get_header().await.unwrap().total_entries, 10);
+        assert_eq!(client.get_header().await.unwrap().total_entries, 10);
+        assert_eq!(server.received().len(), 1);
+
+        // A refresh always fetches the header
+        assert_eq!(client.refresh_header().await.unwrap().total_entries, 11);
+        assert_eq!(server.received().len(), 2);
+
+        // The header is fetched again once the ttl expired
+        sleep(Duration::from_millis(250)).await;
+        client.get_header().await.unwrap();
+        assert_eq!(server.received().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_chain_id() {
+        let server = MockStreamServer::builder()
+            .on(
+                Command::CmdHeader,
+                vec![
+                    MockPacket::ok(),
+                    MockPacket::Header(HeaderEntry {
+                        packet_type: PacketType::PtHeader as u8,
+                        head_length: HEADER_SIZE as u32,
+                        version: 1,
+                        system_id: ChainId(1101),
+                        ..Default::default()
+                    }),
+                ],
+            )
+            .start()
+            .await
+            .unwrap();
+
+        let mut client = StreamClient::new(server.addr()).unwrap();
+        assert_eq!(client.chain_id(), None);
+        client.connect_server().await.unwrap();
+
+        client.exec_command_get_header().await.unwrap();
+        let chain_id = client.chain_id().unwrap();
+        assert_eq!(chain_id, ChainId::from(1101));
+        assert_eq!(u64::from(chain_id), 1101);
+        assert_eq!(chain_id.to_string(), "1101");
+    }
+
+    #[cfg(feature = "tls")]
+    #[tokio::test]
+    async fn test_tls_connection() {
+        use tokio_rustls::rustls::pki_types::PrivatePkcs8KeyDer;
+
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let cert_der = cert.cert.der().to_vec();
+        let config = rustls::ServerConfig::builder_with_provider(std::sync::Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![cert_der.clone().into()],
+            PrivatePkcs8KeyDer::from(cert.key_pair.serialize_der()).into(),
+        )
+        .unwrap();
+        let acceptor = tokio_rustls::TlsAcceptor::from(std::sync::Arc::new(config));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            let (conn, _) = listener.accept().await.unwrap();
+            let mut conn = acceptor.accept(conn).await.unwrap();
+            read_command(&mut conn, false).await;
+            conn.write_all(&header_response(7)).await.unwrap();
+            let _ = conn.read(&mut [0u8; 1]).await;
+        });
+
+        let mut client = StreamClient::builder()
+            .server(server)
+            .tls(true)
+            .tls_root_cert(cert_der)
+            .tls_server_name("localhost".to_string())
+            .build()
+            .unwrap();
+        client.connect_server().await.unwrap();
+        assert!(matches!(
+            client.conn.as_ref().map(|c| c.get_ref()),
+            Some(Connection::Tls(_))
+        ));
+
+        let header = client.exec_command_get_header().await.unwrap();
+        assert_eq!(header.total_entries, 7);
+    }
+
+    #[cfg(feature = "tls")]
+    #[test]
+    fn test_tls_config_errors() {
+        let res = StreamClient::builder()
+            .server("127.0.0.1:6900".to_string())
+            .tls(true)
+            .tls_root_cert(vec![0x01, 0x02])
+            .build();
+        assert!(matches!(res, Err(ClientError::TlsError(_))));
+
+        // The SNI hostname defaults to the server address host
+        let client = StreamClient::builder()
+            .server("stream.zkevm-rpc.com:6900".to_string())
+            .tls(true)
+            .build()
+            .unwrap();
+        assert_eq!(
+            client.tls.as_ref().unwrap().server_name,
+            ServerName::try_from("stream.zkevm-rpc.com").unwrap()
+        );
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn test_into_entry_stream() {
+        use futures::StreamExt;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            read_command(&mut conn, false).await;
+            conn.write_all(&header_response(10)).await.unwrap();
+            read_command(&mut conn, true).await;
+            conn.write_all(&ok_result()).await.unwrap();
+            for number in 0..3 {
+                conn.write_all(&data_entry(number)).await.unwrap();
+            }
+            // Drop the connection, the stream reconnects and restores the streaming
+            drop(conn);
+
+            let (mut conn, _) = listener.accept().await.unwrap();
+            read_command(&mut conn, true).await;
+            conn.write_all(&ok_result()).await.unwrap();
+            for number in 3..10 {
+                conn.write_all(&data_entry(number)).await.unwrap();
+            }
+            let _ = conn.read(&mut [0u8; 1]).await;
+        });
+
+        let client = StreamClient::builder()
+            .server(server)
+            .reconnect_delay(Duration::from_millis(10))
+            .build()
+            .unwrap();
+
+        let entries: Vec<Result<Entry, ClientError>> = tokio::time::timeout(
+            Duration::from_secs(5),
+            client.into_entry_stream().take(5).collect(),
+        )
+        .await
+        .unwrap();
+        let numbers: Vec<u64> = entries.into_iter().map(|e| e.unwrap().number).collect();
+        assert_eq!(numbers, vec![0, 1, 2, 3, 4]);
+    }
+
+    #[tokio::test]
+    async fn test_read_data_entry_undersized_length() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            read_command(&mut conn, true).await;
+            // Data entry declaring a length between the result and the data entry fixed sizes
+            let mut entry = data_entry(0);
+            entry[0] = PacketType::PtDataRsp as u8;
+            entry[1..5].copy_from_slice(&12u32.to_be_bytes());
+            conn.write_all(&[ok_result(), entry].concat())
+                .await
+                .unwrap();
+            let _ = conn.read(&mut [0u8; 1]).await;
+        });
+
+        let mut client = StreamClient::new(server).unwrap();
+        client.connect_server().await.unwrap();
+
+        match client.exec_command_get_entry(0).await {
+            Err(ClientError::DecodeError(msg)) => {
+                assert!(msg.contains("expected at least 17 bytes, got 12"), "{msg}")
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_decoders_random_short_buffers() {
+        let packet_types = [
+            PacketType::PtData as u8,
+            PacketType::PtDataRsp as u8,
+            PacketType::PtHeader as u8,
+            PacketType::PtResult as u8,
+        ];
+
+        // Malformed buffers are rejected without panicking
+        for _ in 0..10000 {
+            let len = rand::random::<usize>() % 48;
+            let mut b: Vec<u8> = (0..len).map(|_| rand::random()).collect();
+            if let Some(packet) = b.first_mut() {
+                *packet = packet_types[rand::random::<usize>() % packet_types.len()];
+            }
+            // Mostly small length fields to hit the boundaries of the fixed sizes
+            if len >= 5 && rand::random::<bool>() {
+                let length = rand::random::<u32>() % 48;
+                b[1..5].copy_from_slice(&length.to_be_bytes());
+            }
+
+            if let Ok(e) = decode_binary_to_entry(&b) {
+                assert_eq!(e.length as usize, b.len());
+            }
+            if let Ok(r) = decode_binary_to_result_entry(&b) {
+                assert_eq!(r.length as usize, b.len());
+            }
+            let _ = decode_binary_to_header_entry(&b);
+            let _ = Bookmark::decode(&b);
+        }
+
+        // A length field shorter than the fixed fields
+        let mut b = data_entry(0);
+        b[1..5].copy_from_slice(&5u32.to_be_bytes());
+        match decode_binary_to_entry(&b) {
+            Err(ClientError::DecodeError(msg)) => {
+                assert!(msg.contains("expected at least 17 bytes, got 5"), "{msg}")
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(variable_length("data", u32::MAX, FIXED_SIZE_FILE_ENTRY).is_ok());
+        assert!(variable_length("result", 0, FIXED_SIZE_RESULT_ENTRY).is_err());
+        assert!(error_str_length((FIXED_SIZE_RESULT_ENTRY + MAX_ERROR_STR_SIZE) as u32).is_ok());
+        assert!(error_str_length(u32::MAX).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_oversized_result_entry() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            read_command(&mut conn, false).await;
+            // A result entry declaring a 4 GiB error string
+            let mut result = ok_result();
+            result[1..5].copy_from_slice(&u32::MAX.to_be_bytes());
+            conn.write_all(&result).await.unwrap();
+            let _ = conn.read(&mut [0u8; 1]).await;
+        });
+
+        let mut client = StreamClient::new(server).unwrap();
+        client.connect_server().await.unwrap();
+        match client.exec_command_get_header().await {
+            Err(ClientError::DecodeError(msg)) => {
+                assert!(msg.contains("exceeds the maximum"), "{msg}")
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(!client.is_connected());
+    }
+
+    #[tokio::test]
+    async fn test_read_result_entry_undersized_length() {
+        let server = MockStreamServer::builder()
+            .on(
+                Command::CmdHeader,
+                vec![MockPacket::Raw(vec![0xff, 0, 0, 0, 3, 0, 0, 0, 0])],
+            )
+            .start()
+            .await
+            .unwrap();
+
+        let mut client = StreamClient::new(server.addr()).unwrap();
+        client.connect_server().await.unwrap();
+
+        match client.exec_command_get_header().await {
+            Err(ClientError::DecodeError(msg)) => {
+                assert!(msg.contains("expected at least 9 bytes, got 3"), "{msg}")
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_read_buffer_reused() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            let big = Entry {
+                packet_type: PacketType::PtData as u8,
+                entry_type: EntryType::Event2,
+                number: 1,
+                data: vec![0xab; 1000],
+                ..Default::default()
+            };
+            conn.write_all(&[data_entry(0), big.encode_to_binary(), data_entry(2)].concat())
+                .await
+                .unwrap();
+            let _ = conn.read(&mut [0u8; 1]).await;
+        });
+
+        let mut client = StreamClient::builder()
+            .server(server)
+            .read_buffer_size(64)
+            .build()
+            .unwrap();
+        assert_eq!(client.read_buffer.capacity(), 64);
+        client.connect_server().await.unwrap();
+
+        let e = client.read_packet().await.unwrap().unwrap();
+        assert_eq!(e.number, 0);
+        assert_eq!(client.read_buffer.capacity(), 64);
+
+        // A bigger entry grows the buffer, which is kept for the next entries
+        let e = client.read_packet().await.unwrap().unwrap();
+        assert_eq!(e.data, vec![0xab; 1000]);
+        let capacity = client.read_buffer.capacity();
+        assert!(capacity >= FIXED_SIZE_FILE_ENTRY + 1000);
+
+        let e = client.read_packet().await.unwrap().unwrap();
+        assert_eq!(e.number, 2);
+        assert_eq!(e.data.len(), 8);
+        assert_eq!(client.read_buffer.capacity(), capacity);
+    }
+
+    #[tokio::test]
+    async fn test_drop_closes_connection() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        tokio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            read_command(&mut conn, false).await;
+            conn.write_all(&header_response(1)).await.unwrap();
+            read_command(&mut conn, true).await;
+            conn.write_all(&[ok_result(), data_entry(0)].concat())
+                .await
+                .unwrap();
+
+            // The stop command is sent and then the connection is closed
+            let (cmd, _) = read_command(&mut conn, false).await;
+            let closed = conn.read(&mut [0u8; 1]).await.unwrap() == 0;
+            tx.send((cmd, closed)).unwrap();
+        });
+
+        let mut client = StreamClient::new(server).unwrap();
+        client.next_entry().await.unwrap();
+        drop(client);
+
+        let (cmd, closed) = tokio::time::timeout(Duration::from_secs(5), rx)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(cmd, Command::CmdStop as u64);
+        assert!(closed);
+    }
+
+    #[tokio::test]
+    async fn test_hook_error_policy() {
+        // calls, delivered entries and result of start for a hook failing twice on entry 1
+        async fn run(policy: HookErrorPolicy) -> (usize, Vec<u64>, Result<(), ClientError>, u64) {
+            let entries = (0..3)
+                .map(|n| MockPacket::data(n, EntryType::Event1, vec![n as u8]))
+                .collect::<Vec<_>>();
+            let server = MockStreamServer::builder()
+                .on(
+                    Command::CmdHeader,
+                    vec![MockPacket::ok(), MockPacket::header(3)],
+                )
+                .on(
+                    Command::CmdStart,
+                    [vec![MockPacket::ok()], entries].concat(),
+                )
+                .on(Command::CmdStop, vec![MockPacket::ok()])
+                .start()
+                .await
+                .unwrap();
+
+            let calls = Arc::new(AtomicUsize::new(0));
+            let delivered = Arc::new(std::sync::Mutex::new(Vec::new()));
+            let mut client = StreamClient::builder()
+                .server(server.addr())
+                .on_hook_error(policy)
+                .process_entry_hook({
+                    let (calls, delivered) = (calls.clone(), delivered.clone());
+                    move |e| {
+                        if e.number == 1 && calls.fetch_add(1, Ordering::SeqCst) < 2 {
+                            return Err(ClientError::DecodeError("bad entry".to_string()));
+                        }
+                        delivered.lock().unwrap().push(e.number);
+                        // Stop once the last entry is processed
+                        match e.number {
+                            2 => Ok(HookAction::Stop),
+                            _ => Ok(HookAction::Continue),
+                        }
+                    }
+                })
+                .build()
+                .unwrap();
+
+            let res = timeout(Duration::from_secs(5), client.start())
+                .await
+                .unwrap();
+            let calls = calls.load(Ordering::SeqCst);
+            let delivered = delivered.lock().unwrap().clone();
+            (calls, delivered, res, client.resume_from())
+        }
+
+        let (calls, delivered, res, _) = run(HookErrorPolicy::Ignore).await;
+        assert_eq!(calls, 1);
+        assert_eq!(delivered, vec![0, 2]);
+        assert!(res.is_ok());
+
+        // The streaming is stopped at the failed entry
+        let (calls, delivered, res, resume_from) = run(HookErrorPolicy::default()).await;
+        assert_eq!(calls, 1);
+        assert_eq!(delivered, vec![0]);
+        assert!(
+            matches!(&res, Err(ClientError::DecodeError(msg)) if msg == "bad entry"),
+            "{res:?}"
+        );
+        assert_eq!(resume_from, 1);
+
+        let (calls, delivered, res, _) = run(HookErrorPolicy::Retry(2)).await;
+        assert_eq!(calls, 3);
+        assert_eq!(delivered, vec![0, 1, 2]);
+        assert!(res.is_ok());
+
+        let (calls, delivered, res, resume_from) = run(HookErrorPolicy::Retry(1)).await;
+        assert_eq!(calls, 2);
+        assert_eq!(delivered, vec![0]);
+        assert!(matches!(res, Err(ClientError::DecodeError(_))));
+        assert_eq!(resume_from, 1);
+    }
+
+    #[tokio::test]
+    async fn test_worker_pool() {
+        // order the hook calls are done in, maximum concurrent calls and resume point for the
+        // hook stopping at entry stop_at, with the later entries done first
+        async fn run(stop_at: u64) -> (Vec<u64>, usize, u64) {
+            let entries = (0..10)
+                .map(|n| MockPacket::data(n, EntryType::Event1, vec![n as u8]))
+                .collect::<Vec<_>>();
+            let server = MockStreamServer::builder()
+                .on(
+                    Command::CmdHeader,
+                    vec![MockPacket::ok(), MockPacket::header(10)],
+                )
+                .on(
+                    Command::CmdStart,
+                    [vec![MockPacket::ok()], entries].concat(),
+                )
+                .on(Command::CmdStop, vec![MockPacket::ok()])
+                .start()
+                .await
+                .unwrap();
+
+            let done = Arc::new(std::sync::Mutex::new(Vec::new()));
+            let (running, max_running) =
+                (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+            let mut client = StreamClient::builder()
+                .server(server.addr())
+                .worker_count(4)
+                .concurrent_process_entry_hook({
+                    let (done, running, max_running) =
+                        (done.clone(), running.clone(), max_running.clone());
+                    move |e| {
+                        let (done, running, max_running) =
+                            (done.clone(), running.clone(), max_running.clone());
+                        Box::pin(async move {
+                            let n = running.fetch_add(1, Ordering::SeqCst) + 1;
+                            max_running.fetch_max(n, Ordering::SeqCst);
+                            sleep(Duration::from_millis(5 * (10 - e.number))).await;
+                            running.fetch_sub(1, Ordering::SeqCst);
+                            done.lock().unwrap().push(e.number);
+                            match e.number == stop_at {
+                                true => Ok(HookAction::Stop),
+                                false => Ok(HookAction::Continue),
+                            }
+                        })
+                    }
+                })
+                .build()
+                .unwrap();
+
+            timeout(Duration::from_secs(5), client.start())
+                .await
+                .unwrap()
+                .unwrap();
+            let done = done.lock().unwrap().clone();
+            (
+                done,
+                max_running.load(Ordering::SeqCst),
+                client.resume_from(),
+            )
+        }
+
+        let (done, max_running, resume_from) = run(9).await;
+        assert!(max_running > 1, "{max_running}");
+        assert_eq!(done.len(), 10);
+        assert_ne!(done, (0..10).collect::<Vec<_>>());
+        assert_eq!(resume_from, 10);
+
+        // The entries done after the stopping one aren't delivered
+        let (done, _, resume_from) = run(2).await;
+        assert!(done.contains(&3), "{done:?}");
+        assert_eq!(resume_from, 3);
+
+        let res = StreamClient::builder()
+            .server("127.0.0.1:6900".to_string())
+            .worker_count(2)
+            .build();
+        assert!(matches!(res, Err(ClientError::InvalidConfig(_))));
+    }
+
+    #[tokio::test]
+    async fn test_ping() {
+        let server = MockStreamServer::builder()
+            .on(
+                Command::CmdHeader,
+                vec![MockPacket::ok(), MockPacket::header(5)],
+            )
+            .latency(Duration::from_millis(20))
+            .start()
+            .await
+            .unwrap();
+
+        let mut client = StreamClient::new(server.addr()).unwrap();
+        assert!(matches!(
+            client.ping().await,
+            Err(ClientError::ClientNotStarted(_))
+        ));
+        client.connect_server().await.unwrap();
+        let status = client.status();
+
+        let rtt = client.ping().await.unwrap();
+        assert!(rtt >= Duration::from_millis(20), "{rtt:?}");
+        assert_eq!(server.received().len(), 1);
+
+        // The header isn't cached and the connection is ready for the next command
+        assert!(client.header().is_none());
+        assert_eq!(client.status(), status);
+        assert!(client.ping().await.unwrap() > Duration::ZERO);
+        assert_eq!(
+            client
+                .exec_command_get_header()
+                .await
+                .unwrap()
+                .total_entries,
+            5
+        );
+
+        // A timed out ping drops the socket but leaves the client state alone
+        let mut client = StreamClient::builder()
+            .server(server.addr())
+            .command_timeout(Duration::from_millis(5))
+            .build()
+            .unwrap();
+        client.connect_server().await.unwrap();
+        let status = client.status();
+        assert!(matches!(client.ping().await, Err(ClientError::Timeout)));
+        assert!(!client.connected);
+        assert!(client.conn.is_none());
+        assert_eq!(client.status(), status);
+
+        // The next ping opens a new connection
+        let received = server.received().len();
+        assert!(matches!(client.ping().await, Err(ClientError::Timeout)));
+        assert_eq!(server.received().len(), received + 1);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_bookmark() {
+        let bookmark = Bookmark::new(1, 7u64.to_be_bytes().to_vec());
+        let server = MockStreamServer::builder()
+            .on(
+                Command::CmdBookmark,
+                vec![
+                    MockPacket::ok(),
+                    MockPacket::data_rsp(12, EntryType::Bookmark, bookmark.encode()),
+                ],
+            )
+            .on(
+                Command::CmdBookmark,
+                vec![
+                    MockPacket::ok(),
+                    MockPacket::data_rsp(0, EntryType::NotFound, vec![]),
+                ],
+            )
+            .on(Command::CmdStart, vec![MockPacket::ok()])
+            .on(Command::CmdStop, vec![MockPacket::ok()])
+            .start()
+            .await
+            .unwrap();
+
+        let mut client = StreamClient::new(server.addr()).unwrap();
+        client.connect_server().await.unwrap();
+
+        let number = client.resolve_bookmark(bookmark.clone()).await.unwrap();
+        assert_eq!(number, 12);
+        client.exec_command_start(number).await.unwrap();
+        client.exec_command_stop().await.unwrap();
+
+        let missing = Bookmark::new(1, 8u64.to_be_bytes().to_vec());
+        assert!(matches!(
+            client.resolve_bookmark(missing).await,
+            Err(ClientError::BookmarkNotFound)
+        ));
+
+        let received = server.received();
+        assert_eq!(received[0].bookmark, bookmark.encode());
+        assert_eq!(received[1].command, Command::CmdStart as u64);
+        assert_eq!(received[1].from_entry, 12);
+    }
+
+    #[tokio::test]
+    async fn test_get_bookmark_or_after() {
+        let later = Bookmark::new(0, 5u64.to_be_bytes().to_vec());
+        let server = MockStreamServer::builder()
+            .on(
+                Command::CmdBookmark,
+                vec![
+                    MockPacket::ok(),
+                    MockPacket::data_rsp(0, EntryType::NotFound, vec![]),
+                ],
+            )
+            .on(
+                Command::CmdBookmark,
+                vec![
+                    MockPacket::ok(),
+                    MockPacket::data_rsp(9, EntryType::Bookmark, later.encode()),
+                ],
+            )
+            .on(
+                Command::CmdStartBookmark,
+                vec![
+                    MockPacket::ok(),
+                    MockPacket::data(9, EntryType::Bookmark, later.encode()),
+                    MockPacket::data(10, EntryType::Event1, vec![1]),
+                ],
+            )
+            .on(Command::CmdStop, vec![MockPacket::ok()])
+            .start()
+            .await
+            .unwrap();
+
+        let mut client = StreamClient::new(server.addr()).unwrap();
+        client.connect_server().await.unwrap();
+
+        // The exact bookmark is missing, the first entry after it is returned
+        let missing = Bookmark::new(0, 4u64.to_be_bytes().to_vec());
+        let e = client.get_bookmark_or_after(missing.clone()).await.unwrap();
+        assert_eq!(e.number, 9);
+        assert_eq!(Bookmark::decode(&e.data).unwrap(), later);
+        assert!(!client.streaming);
+
+        // The exact bookmark is found without streaming
+        let e = client.get_bookmark_or_after(later.clone()).await.unwrap();
+        assert_eq!(e.number, 9);
+
+        let commands = server
+            .received()
+            .iter()
+            .map(|c| c.command)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            commands,
+            vec![
+                Command::CmdBookmark as u64,
+                Command::CmdStartBookmark as u64,
+                Command::CmdStop as u64,
+                Command::CmdBookmark as u64,
+            ]
+        );
+
+        // The exact variant fails on the missing bookmark
+        let server = MockStreamServer::builder()
+            .on(
+                Command::CmdBookmark,
+                vec![
+                    MockPacket::ok(),
+                    MockPacket::data_rsp(0, EntryType::NotFound, vec![]),
+                ],
+            )
+            .start()
+            .await
+            .unwrap();
+        let mut client = StreamClient::new(server.addr()).unwrap();
+        client.connect_server().await.unwrap();
+        assert!(matches!(
+            client.exec_command_get_bookmark(missing.clone()).await,
+            Err(ClientError::BookmarkNotFound)
+        ));
+
+        // No entry streamed after the bookmark times out, closing the connection
+        let server = MockStreamServer::builder()
+            .on(
+                Command::CmdBookmark,
+                vec![
+                    MockPacket::ok(),
+                    MockPacket::data_rsp(0, EntryType::NotFound, vec![]),
+                ],
+            )
+            .on(Command::CmdStartBookmark, vec![MockPacket::ok()])
+            .on(
+                Command::CmdHeader,
+                vec![MockPacket::ok(), MockPacket::header(1)],
+            )
+            .start()
+            .await
+            .unwrap();
+        let mut client = StreamClient::builder()
+            .server(server.addr())
+            .command_timeout(Duration::from_millis(100))
+            .build()
+            .unwrap();
+        client.connect_server().await.unwrap();
+        let buffer = SharedBuffer::default();
+        client.tee_to(buffer.clone()).await.unwrap();
+        let res = client.get_bookmark_or_after(missing).await;
+        assert!(matches!(res, Err(ClientError::Timeout)), "{res:?}");
+        assert!(!client.is_connected());
+        assert!(!client.streaming);
+        assert!(server
+            .received()
+            .iter()
+            .all(|c| c.command != Command::CmdStop as u64));
+        assert!(client.tee.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_read_ahead_partial_reads() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            read_command(&mut conn, false).await;
+            conn.write_all(&header_response(3)).await.unwrap();
+            read_command(&mut conn, true).await;
+
+            // The packets are split across the writes, and across the read ahead buffer
+            let b = [
+                ok_result(),
+                data_entry(0),
+                vec![0; 5],
+                data_entry(1),
+                data_entry(2),
+            ]
+            .concat();
+            for chunk in b.chunks(7) {
+                conn.write_all(chunk).await.unwrap();
+                conn.flush().await.unwrap();
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+            let _ = conn.read(&mut [0u8; 1]).await;
+        });
+
+        let mut client = StreamClient::builder()
+            .server(server)
+            .read_ahead_size(5)
+            .build()
+            .unwrap();
+        assert_eq!(client.read_ahead_size, 5);
+        for n in 0..3u64 {
+            let e = client.next_entry().await.unwrap();
+            assert_eq!(e.number, n);
+            assert_eq!(e, decode_binary_to_entry(&data_entry(n)).unwrap());
+        }
+
+        let client = StreamClient::builder()
+            .server("127.0.0.1:6900".to_string())
+            .read_ahead_size(0)
+            .build()
+            .unwrap();
+        assert_eq!(client.read_ahead_size, 1);
+    }
+
+    #[tokio::test]
+    async fn test_entries_between_bookmarks() {
+        let block = |n: u64| Bookmark::new(0, n.to_be_bytes().to_vec());
+        let server = MockStreamServer::builder()
+            .on(
+                Command::CmdHeader,
+                vec![MockPacket::ok(), MockPacket::header(6)],
+            )
+            .on(
+                Command::CmdStartBookmark,
+                vec![
+                    MockPacket::ok(),
+                    MockPacket::data(0, EntryType::Bookmark, block(1).encode()),
+                    MockPacket::data(1, EntryType::Event1, vec![1]),
+                    MockPacket::data(2, EntryType::Event2, vec![2]),
+                    MockPacket::data(3, EntryType::Bookmark, block(2).encode()),
+                    MockPacket::data(4, EntryType::Event1, vec![4]),
+                    MockPacket::data(5, EntryType::Event2, vec![5]),
+                ],
+            )
+            .on(Command::CmdStop, vec![MockPacket::ok()])
+            .start()
+            .await
+            .unwrap();
+
+        let mut client = StreamClient::new(server.addr()).unwrap();
+        client.connect_server().await.unwrap();
+
+        let entries = client
+            .entries_between_bookmarks(block(1), block(2))
+            .await
+            .unwrap();
+        assert_eq!(
+            entries.iter().map(|e| e.number).collect::<Vec<_>>(),
+            vec![0, 1, 2]
+        );
+        assert!(!client.streaming);
+
+        // The to bookmark is never reached
+        let res = client.entries_between_bookmarks(block(2), block(3)).await;
+        assert!(matches!(res, Err(ClientError::BookmarkNotFound)));
+        assert!(!client.streaming);
+
+        let stops = server
+            .received()
+            .iter()
+            .filter(|c| c.command == Command::CmdStop as u64)
+            .count();
+        assert_eq!(stops, 2);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_unix_socket_transport() {
+        let path = std::env::temp_dir().join(format!("datastream-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = tokio::net::UnixListener::bind(&path).unwrap();
+
+        tokio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            read_command(&mut conn, false).await;
+            conn.write_all(&header_response(2)).await.unwrap();
+            read_command(&mut conn, true).await;
+            conn.write_all(&[ok_result(), data_entry(0), data_entry(1)].concat())
+                .await
+                .unwrap();
+            let _ = conn.read(&mut [0u8; 1]).await;
+        });
+
+        let server = format!("unix:{}", path.display());
+        assert_eq!(
+            Transport::parse(&server).unwrap(),
+            Transport::Unix(path.clone())
+        );
+        let mut client = StreamClient::new(server).unwrap();
+        for n in 0..2u64 {
+            assert_eq!(client.next_entry().await.unwrap().number, n);
+        }
+        assert!(client.client_id().starts_with(UNIX_SOCKET_PREFIX));
+        assert_eq!(client.peer_addr(), None);
+
+        drop(client);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(
+            Transport::parse("unix:"),
+            Err(ClientError::InvalidServerAddress(_))
+        ));
+        assert_eq!(
+            Transport::parse("localhost:6900").unwrap(),
+            Transport::Tcp("localhost:6900".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_start_error_context() {
+        let server = MockStreamServer::builder()
+            .on(
+                Command::CmdHeader,
+                vec![MockPacket::error(
+                    CommandError::CmdErrInvalidCommand,
+                    "no header",
+                )],
+            )
+            .start()
+            .await
+            .unwrap();
+
+        let mut client = StreamClient::builder()
+            .server(server.addr())
+            .max_reconnect_retries(0)
+            .build()
+            .unwrap();
+        let err = client.start().await.unwrap_err();
+        match &err {
+            ClientError::StartFailed(step, e) => {
+                assert_eq!(step, "getting the header");
+                assert!(matches!(
+                    **e,
+                    ClientError::CommandFailed(CommandError::CmdErrInvalidCommand, _)
+                ));
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+        assert!(err.to_string().contains("getting the header"), "{err}");
+        assert!(err.to_string().contains("no header"), "{err}");
+
+        // The failed start command names the entry it started from
+        let server = MockStreamServer::builder()
+            .on(
+                Command::CmdHeader,
+                vec![MockPacket::ok(), MockPacket::header(10)],
+            )
+            .on(
+                Command::CmdStart,
+                vec![MockPacket::error(
+                    CommandError::CmdErrBadFromEntry,
+                    "bad entry",
+                )],
+            )
+            .start()
+            .await
+            .unwrap();
+        let mut client = StreamClient::new(server.addr()).unwrap();
+        client.follow_from(4);
+        match client.start().await {
+            Err(ClientError::StartFailed(step, _)) => assert_eq!(step, "starting from entry 4"),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_page_size_padding() {
+        // The first page of 64 bytes holds an entry of 25 bytes and 39 of padding
+        let padded = |padding: usize| {
+            vec![
+                MockPacket::ok(),
+                MockPacket::data(0, EntryType::Event1, vec![0; 8]),
+                MockPacket::Raw(vec![PacketType::PtPadding as u8; padding]),
+                MockPacket::data(1, EntryType::Event1, vec![1; 8]),
+            ]
+        };
+        let server = MockStreamServer::builder()
+            .on(
+                Command::CmdHeader,
+                vec![MockPacket::ok(), MockPacket::header(2)],
+            )
+            .on(Command::CmdStart, padded(39))
+            .on(Command::CmdStart, padded(100))
+            .start()
+            .await
+            .unwrap();
+
+        let mut client = StreamClient::builder()
+            .server(server.addr())
+            .page_size(64)
+            .build()
+            .unwrap();
+        for n in 0..2u64 {
+            assert_eq!(client.next_entry().await.unwrap().number, n);
+        }
+
+        // Padding longer than a page means the stream is misaligned
+        let mut client = StreamClient::builder()
+            .server(server.addr())
+            .page_size(64)
+            .build()
+            .unwrap();
+        assert_eq!(client.next_entry().await.unwrap().number, 0);
+        assert!(matches!(
+            client.next_entry().await,
+            Err(ClientError::DecodeError(msg)) if msg.contains("data page of 64 bytes")
+        ));
+
+        for page_size in [0, 16, 1000] {
+            let res = StreamClient::builder()
+                .server(server.addr())
+                .page_size(page_size)
+                .build();
+            assert!(
+                matches!(res, Err(ClientError::InvalidConfig(_))),
+                "{page_size}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cancel_get_entry() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            // The first connection stalls after the command
+            let (mut stalled, _) = listener.accept().await.unwrap();
+            read_command(&mut stalled, true).await;
+
+            let (mut conn, _) = listener.accept().await.unwrap();
+            let (_, from_entry) = read_command(&mut conn, true).await;
+            let mut entry = data_entry(from_entry);
+            entry[0] = PacketType::PtDataRsp as u8;
+            conn.write_all(&[ok_result(), entry].concat())
+                .await
+                .unwrap();
+            let _ = conn.read(&mut [0u8; 1]).await;
+            drop(stalled);
+        });
+
+        let mut client = StreamClient::new(server).unwrap();
+        client.connect_server().await.unwrap();
+        let token = CancellationToken::new();
+        let cancel = token.clone();
+        tokio::spawn(async move {
+            sleep(Duration::from_millis(50)).await;
+            cancel.cancel();
+        });
+        let res = tokio::time::timeout(
+            Duration::from_secs(5),
+            client.exec_command_get_entry_with_cancel(3, Some(&token)),
+        )
+        .await
+        .expect("the command should be cancelled");
+        assert!(matches!(res, Err(ClientError::Cancelled)));
+        assert!(!client.connected);
+
+        // The next command reconnects, with or without a token
+        let e = client.exec_command_get_entry(4).await.unwrap();
+        assert_eq!(e.number, 4);
+        assert!(client.connected);
+    }
+
+    #[tokio::test]
+    async fn test_timestamp_entries() {
+        let entries = (0..4)
+            .map(|n| MockPacket::data(n, EntryType::Event1, vec![n as u8]))
+            .collect::<Vec<_>>();
+        let server = MockStreamServer::builder()
+            .on(
+                Command::CmdHeader,
+                vec![MockPacket::ok(), MockPacket::header(4)],
+            )
+            .on(
+                Command::CmdStart,
+                [vec![MockPacket::ok()], entries].concat(),
+            )
+            .start()
+            .await
+            .unwrap();
+
+        let mut client = StreamClient::builder()
+            .server(server.addr())
+            .timestamp_entries(true)
+            .build()
+            .unwrap();
+        let before = std::time::Instant::now();
+        let mut last = before;
+        for _ in 0..4 {
+            let received_at = client.next_entry().await.unwrap().received_at.unwrap();
+            assert!(received_at >= last);
+            last = received_at;
+        }
+        assert!(last > before);
+
+        // Disabled by default
+        let mut client = StreamClient::new(server.addr()).unwrap();
+        assert_eq!(client.next_entry().await.unwrap().received_at, None);
+    }
+
+    #[tokio::test]
+    async fn test_capture_raw() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            while let Ok((mut conn, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    conn.write_all(&data_entry(7)).await.unwrap();
+                    let _ = conn.read(&mut [0u8; 1]).await;
+                });
+            }
+        });
+
+        let mut client = StreamClient::builder()
+            .server(server.clone())
+            .capture_raw(true)
+            .build()
+            .unwrap();
+        client.connect_server().await.unwrap();
+
+        let e = client.read_packet().await.unwrap().unwrap();
+        assert_eq!(e.raw_bytes(), data_entry(7).as_slice());
+
+        // The captured bytes decode back to the same entry
+        let decoded = decode_binary_to_entry(e.raw_bytes()).unwrap();
+        assert_eq!(decoded, Entry { raw: None, ..e });
+
+        // Disabled by default
+        let mut client = StreamClient::new(server).unwrap();
+        client.connect_server().await.unwrap();
+        let e = client.read_packet().await.unwrap().unwrap();
+        assert_eq!(e.number, 7);
+        assert!(e.raw_bytes().is_empty());
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn test_start_with_shutdown() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+        let token = CancellationToken::new();
+
+        let server_task = tokio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            read_command(&mut conn, false).await;
+            conn.write_all(&header_response(2)).await.unwrap();
+            read_command(&mut conn, true).await;
+            conn.write_all(&ok_result()).await.unwrap();
+            conn.write_all(&[data_entry(0), data_entry(1)].concat())
+                .await
+                .unwrap();
+
+            let (cmd, _) = read_command(&mut conn, false).await;
+            conn.write_all(&ok_result()).await.unwrap();
+            // The client closes the connection after the stop
+            let closed = conn.read(&mut [0u8; 1]).await.unwrap() == 0;
+            (cmd, closed)
+        });
+
+        // Shutdown once the last entry is processed
+        let shutdown = token.clone();
+        let mut client = StreamClient::builder()
+            .server(server)
+            .process_entry_hook(move |e| {
+                if e.number == 1 {
+                    shutdown.cancel();
+                }
+                Ok(HookAction::Continue)
+            })
+            .build()
+            .unwrap();
+        let res = tokio::time::timeout(Duration::from_secs(5), client.start_with_shutdown(token))
+            .await
+            .unwrap();
+        assert!(res.is_ok());
+        assert!(!client.connected);
+
+        let (cmd, closed) = server_task.await.unwrap();
+        assert_eq!(cmd, Command::CmdStop as u64);
+        assert!(closed);
+    }
+
+    #[tokio::test]
+    async fn test_close_connection() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+
+        let mut client = StreamClient::new(server).unwrap();
+        client.connect_server().await.unwrap();
+        let (mut peer, _) = listener.accept().await.unwrap();
+        assert!(client.connected);
+
+        client.close_connection();
+        assert!(client.conn.is_none());
+        assert!(!client.connected);
+        assert!(!client.started);
+        assert!(!client.streaming);
+
+        // The server side sees the socket closed
+        let mut buf = [0u8; 1];
+        assert_eq!(peer.read(&mut buf).await.unwrap(), 0);
+
+        // A following command can't be written
+        let res = client.exec_command_get_header().await;
+        assert!(matches!(res, Err(ClientError::ClientNotStarted(_))));
+    }
+
+    #[test]
+    fn test_stream_client_builder() {
+        let client = StreamClient::builder()
+            .server("stream.zkevm-rpc.com:6900".to_string())
+            .stream_type(StreamType::Sequencer)
+            .reconnect_delay(Duration::from_millis(250))
+            .build()
+            .unwrap();
+        assert_eq!(client.server, "stream.zkevm-rpc.com:6900");
+        assert_eq!(client.backoff.base_delay, Duration::from_millis(250));
+
+        let client = StreamClient::new("127.0.0.1:6900".to_string()).unwrap();
+        assert_eq!(client.backoff.base_delay, DEFAULT_RECONNECT_DELAY);
+
+        for server in [
+            "",
+            "localhost",
+            "not a host:6900",
+            "localhost:port",
+            "::1:6900",
+        ] {
+            let res = StreamClient::builder().server(server.to_string()).build();
+            assert!(
+                matches!(res, Err(ClientError::InvalidServerAddress(_))),
+                "{server}"
+            );
+        }
+        assert!(StreamClient::builder().build().is_err());
+        assert!(StreamClient::builder()
+            .server("[::1]:6900".to_string())
+            .build()
+            .is_ok());
+    }
+
+    #[test]
+    fn test_backoff_delays() {
+        let backoff = Backoff {
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+            max_retries: None,
+            jitter: 0.0,
+        };
+        let delays: Vec<u64> = (0..6)
+            .map(|retry| backoff.jittered_delay(retry).as_millis() as u64)
+            .collect();
+        assert_eq!(delays, vec![100, 200, 400, 800, 1000, 1000]);
+        assert_eq!(backoff.delay(64), Duration::from_secs(1));
+
+        let backoff = Backoff {
+            jitter: 0.5,
+            ..backoff
+        };
+        for retry in 0..6 {
+            let delay = backoff.jittered_delay(retry);
+            assert!(delay <= backoff.delay(retry));
+            assert!(delay >= backoff.delay(retry) / 2);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_connect_server_max_retries() {
+        // Get a free local port with nothing listening on it
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+        drop(listener);
+
+        let mut client = StreamClient::builder()
+            .server(server)
+            .reconnect_delay(Duration::from_millis(100))
+            .max_reconnect_delay(Duration::from_millis(300))
+            .max_reconnect_retries(3)
+            .reconnect_jitter(0.0)
+            .build()
+            .unwrap();
+
+        let start = tokio::time::Instant::now();
+        let res = client.connect_server().await;
+        assert!(matches!(res, Err(ClientError::ConnectionFailed(_, 4))));
+        // Waited 100ms + 200ms + 300ms (capped) of virtual time before giving up
+        assert_eq!(start.elapsed().as_millis(), 600);
+    }
+
+    #[tokio::test]
+    async fn test_connect_timeout() {
+        // A listener never accepting drops the connections past its backlog, so they hang
+        let socket =
+            socket2::Socket::new(socket2::Domain::IPV4, socket2::Type::STREAM, None).unwrap();
+        socket
+            .bind(&"127.0.0.1:0".parse::<SocketAddr>().unwrap().into())
+            .unwrap();
+        socket.listen(0).unwrap();
+        let addr = socket.local_addr().unwrap().as_socket().unwrap();
+        let mut backlog = Vec::new();
+        while let Ok(conn) = timeout(Duration::from_millis(200), TcpStream::connect(addr)).await {
+            backlog.push(conn.unwrap());
+            assert!(backlog.len() < 64, "the listener backlog never filled");
+        }
+
+        let mut client = StreamClient::builder()
+            .server(addr.to_string())
+            .connect_timeout(Duration::from_millis(300))
+            .max_reconnect_retries(0)
+            .build()
+            .unwrap();
+
+        let start = Instant::now();
+        let res = client.connect_server().await;
+        assert!(
+            matches!(res, Err(ClientError::ConnectionFailed(_, 1))),
+            "{res:?}"
+        );
+        assert!(start.elapsed() >= Duration::from_millis(300));
+        assert!(
+            start.elapsed() < Duration::from_secs(2),
+            "{:?}",
+            start.elapsed()
+        );
+    }
+
+    #[test]
+    fn test_set_process_entry_hook_closure() {
+        let mut client = StreamClient::new("127.0.0.1:6900".to_string()).unwrap();
+
+        let received = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let hook_received = received.clone();
+        let mut count = 0;
+        client.set_process_entry_hook(move |e| {
+            count += 1;
+            hook_received.lock().unwrap().push((count, e.number));
+            Ok(HookAction::Continue)
+        });
+
+        for number in 10..13 {
+            (client.process_entry_hook)(Entry {
+                number,
+                ..Default::default()
+            })
+            .unwrap();
+        }
+
+        assert_eq!(*received.lock().unwrap(), vec![(1, 10), (2, 11), (3, 12)]);
+    }
+
+    #[test]
+    fn test_bookmark_encode_decode() {
+        let bookmark = Bookmark::new(1, 100u64.to_be_bytes().to_vec());
+        let b = bookmark.encode();
+        assert_eq!(b, vec![1, 0, 0, 0, 0, 0, 0, 0, 100]);
+        assert_eq!(Bookmark::decode(&b).unwrap(), bookmark);
+        assert_eq!(Bookmark::try_from(b).unwrap(), bookmark);
+        assert!(Bookmark::decode(&[]).is_err());
+
+        // Raw bytes aren't made up into a bookmark
+        assert!(matches!(
+            Bookmark::try_from(Vec::new()),
+            Err(ClientError::InvalidBookmark(_))
+        ));
+        assert_eq!(
+            Bookmark::try_from(vec![0]).unwrap(),
+            Bookmark::new(0, vec![])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bookmark_length_limit() {
+        let mut client = StreamClient::new("127.0.0.1:6900".to_string()).unwrap();
+
+        let too_long = Bookmark::new(1, vec![0; MAX_BOOKMARK_LENGTH]);
+        let res = client.exec_command_get_bookmark(too_long.clone()).await;
+        assert!(matches!(res, Err(ClientError::InvalidBookmark(_))));
+        let res = client.exec_command_start_bookmark(too_long).await;
+        assert!(matches!(res, Err(ClientError::InvalidBookmark(_))));
+
+        // A bookmark within the limit goes through to the command execution
+        let max = Bookmark::new(1, vec![0; MAX_BOOKMARK_LENGTH - 1]);
+        let res = client.exec_command_get_bookmark(max).await;
+        assert!(matches!(res, Err(ClientError::ClientNotStarted(_))));
+    }
+
+    #[tokio::test]
+    async fn test_empty_bookmark() {
+        let server = MockStreamServer::builder()
+            .on(
+                Command::CmdBookmark,
+                vec![
+                    MockPacket::ok(),
+                    MockPacket::data_rsp(3, EntryType::Event1, vec![3]),
+                ],
+            )
+            .start()
+            .await
+            .unwrap();
+        let mut client = StreamClient::new(server.addr()).unwrap();
+        client.connect_server().await.unwrap();
+
+        let res = Bookmark::try_from(Vec::new());
+        assert!(
+            matches!(&res, Err(ClientError::InvalidBookmark(msg)) if msg == "empty bookmark"),
+            "{res:?}"
+        );
+        assert!(server.received().is_empty());
+
+        // A bookmark of only the type byte is sent
+        let only_type = Bookmark::try_from(vec![0]).unwrap();
+        let e = client.exec_command_get_bookmark(only_type).await.unwrap();
+        assert_eq!(e.number, 3);
+        let e = client
+            .exec_command_get_bookmark(Bookmark::default())
+            .await
+            .unwrap();
+        assert_eq!(e.number, 3);
+        let received = server.received();
+        assert_eq!(received.len(), 2);
+        assert!(received.iter().all(|c| c.bookmark == vec![0]));
+
+        // The longest bookmark is sent whole
+        let max = Bookmark::new(1, (1..MAX_BOOKMARK_LENGTH as u8).collect());
+        let e = client.exec_command_get_bookmark(max.clone()).await.unwrap();
+        assert_eq!(e.number, 3);
+        assert_eq!(server.received()[2].bookmark, max.encode());
+        assert_eq!(server.received()[2].bookmark.len(), MAX_BOOKMARK_LENGTH);
+    }
+
+    #[test]
+    fn test_entry_encode_decode_round_trip() {
+        let fixtures = vec![
+            Entry {
+                packet_type: PacketType::PtData as u8,
+                length: FIXED_SIZE_FILE_ENTRY as u32 + 4,
+                entry_type: EntryType::Event1,
+                number: 42,
+                data: vec![0xde, 0xad, 0xbe, 0xef],
+                ..Default::default()
+            },
+            Entry {
+                packet_type: PacketType::PtData as u8,
+                length: FIXED_SIZE_FILE_ENTRY as u32,
+                entry_type: EntryType::Event2,
+                number: 0,
+                data: vec![],
+                ..Default::default()
+            },
+            Entry {
+                packet_type: PacketType::PtDataRsp as u8,
+                length: FIXED_SIZE_FILE_ENTRY as u32 + 9,
+                entry_type: EntryType::Bookmark,
+                number: 7,
+                data: [vec![1u8], 100u64.to_be_bytes().to_vec()].concat(),
+                ..Default::default()
+            },
+            Entry {
+                packet_type: PacketType::PtData as u8,
+                length: FIXED_SIZE_FILE_ENTRY as u32 + 1,
+                entry_type: EntryType::Event(3),
+                number: 8,
+                data: vec![0x01],
+                ..Default::default()
+            },
+        ];
+
+        for entry in fixtures {
+            let b = entry.encode_to_binary();
+            assert_eq!(b.len(), entry.length as usize);
+            assert_eq!(decode_binary_to_entry(&b).unwrap(), entry);
+        }
+    }
+
+    #[test]
+    fn test_entry_type_from_u32() {
+        assert_eq!(EntryType::from(1), EntryType::Event1);
+        assert_eq!(EntryType::from(2), EntryType::Event2);
+        assert_eq!(EntryType::from(3), EntryType::Event(3));
+        assert_eq!(EntryType::from(0), EntryType::Event(0));
+        assert_eq!(EntryType::from(0xb0), EntryType::Bookmark);
+        assert_eq!(EntryType::from(u32::MAX), EntryType::NotFound);
+        assert_eq!(u32::from(EntryType::NotFound), ENTRY_TYPE_NOT_FOUND);
+        assert_eq!(u32::from(EntryType::Event(3)), 3);
+    }
+
+    #[tokio::test]
+    async fn test_get_entry_not_found() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            read_command(&mut conn, true).await;
+            let mut rsp = Entry {
+                packet_type: PacketType::PtDataRsp as u8,
+                ..Default::default()
+            }
+            .encode_to_binary();
+            rsp[5..9].copy_from_slice(&u32::MAX.to_be_bytes());
+            conn.write_all(&[ok_result(), rsp].concat()).await.unwrap();
+        });
+
+        let mut client = StreamClient::new(server).unwrap();
+        client.connect_server().await.unwrap();
+
+        let res = client.exec_command_get_entry(100).await;
+        assert!(matches!(res, Err(ClientError::EntryNotFound)));
+    }
+
+    #[tokio::test]
+    async fn test_get_entries_range() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+
+        // Stream of 20 entries, a start streams every entry up to the end
+        tokio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            while let Ok(cmd) = conn.read_u64().await {
+                conn.read_u64().await.unwrap();
+                let response = if cmd == Command::CmdHeader as u64 {
+                    header_response(20)
+                } else if cmd == Command::CmdStart as u64 {
+                    let from = conn.read_u64().await.unwrap();
+                    let mut response = ok_result();
+                    for n in from..20 {
+                        response.extend(data_entry(n));
+                    }
+                    response
+                } else {
+                    ok_result()
+                };
+                conn.write_all(&response).await.unwrap();
+            }
+        });
+
+        let mut client = StreamClient::new(server).unwrap();
+        client.connect_server().await.unwrap();
+
+        let entries = client.get_entries_range(5, 10).await.unwrap();
+        let numbers: Vec<u64> = entries.iter().map(|e| e.number).collect();
+        assert_eq!(numbers, (5..=10).collect::<Vec<u64>>());
+        assert_eq!(entries[0].data, 5u64.to_be_bytes().to_vec());
+        assert_eq!(client.status(), ClientStatus::CsStopped);
+        assert_eq!(client.resume_from(), 0);
+
+        // The entries streamed past the range were drained, the range is cut at the last entry
+        let entries = client.get_entries_range(15, 30).await.unwrap();
+        let numbers: Vec<u64> = entries.iter().map(|e| e.number).collect();
+        assert_eq!(numbers, (15..20).collect::<Vec<u64>>());
+
+        assert!(matches!(
+            client.get_entries_range(25, 30).await,
+            Err(ClientError::EntryNotFound)
+        ));
+        assert!(client.get_entries_range(10, 5).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_get_entries_range_errors() {
+        let data = |n: u64| MockPacket::data(n, EntryType::Event1, vec![n as u8]);
+        let mut bad = data(1).encode_to_binary();
+        bad[1..5].copy_from_slice(&5u32.to_be_bytes());
+        let server = MockStreamServer::builder()
+            .on(
+                Command::CmdHeader,
+                vec![MockPacket::ok(), MockPacket::header(2)],
+            )
+            .on(
+                Command::CmdHeader,
+                vec![MockPacket::ok(), MockPacket::header(3)],
+            )
+            .on(
+                Command::CmdStart,
+                vec![MockPacket::ok(), data(0), MockPacket::Raw(bad)],
+            )
+            .on(Command::CmdStart, vec![MockPacket::ok(), data(0), data(1)])
+            .on(
+                Command::CmdStart,
+                vec![MockPacket::ok(), data(0), data(1), data(2)],
+            )
+            .on(Command::CmdStop, vec![MockPacket::ok()])
+            .start()
+            .await
+            .unwrap();
+
+        let mut client = StreamClient::builder()
+            .server(server.addr())
+            .command_timeout(Duration::from_millis(200))
+            .build()
+            .unwrap();
+        client.connect_server().await.unwrap();
+        let buffer = SharedBuffer::default();
+        client.tee_to(buffer.clone()).await.unwrap();
+        assert_eq!(client.get_header().await.unwrap().total_entries, 2);
+
+        // A range failing to be read closes the connection left streaming
+        assert!(matches!(
+            client.get_entries_range(0, 5).await,
+            Err(ClientError::DecodeError(_))
+        ));
+        assert!(!client.is_connected());
+        assert!(!client.streaming);
+
+        // A range the server doesn't send in full times out
+        assert!(matches!(
+            client.get_entries_range(0, 5).await,
+            Err(ClientError::Timeout)
+        ));
+        assert!(!client.is_connected());
+
+        // The range is cut at the last entry of a fresh header, not the cached one
+        let entries = client.get_entries_range(0, 5).await.unwrap();
+        let numbers: Vec<u64> = entries.iter().map(|e| e.number).collect();
+        assert_eq!(numbers, vec![0, 1, 2]);
+
+        // The range entries aren't teed
+        client.finish_tee().await.unwrap();
+        let mut reader =
+            crate::stream_file::FileStreamReader::new(std::io::Cursor::new(buffer.contents()))
+                .unwrap();
+        assert_eq!(reader.header().total_entries, 0);
+        assert_eq!(reader.entries().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_entries_between_bookmarks_errors() {
+        let block = |n: u64| MockPacket::data(0, EntryType::Bookmark, n.to_be_bytes().to_vec());
+        let data = |n: u64| MockPacket::data(n, EntryType::Event1, vec![n as u8]);
+        let mut bad = data(1).encode_to_binary();
+        bad[1..5].copy_from_slice(&5u32.to_be_bytes());
+        let server = MockStreamServer::builder()
+            .on(
+                Command::CmdHeader,
+                vec![MockPacket::ok(), MockPacket::header(3)],
+            )
+            .on(
+                Command::CmdStartBookmark,
+                vec![MockPacket::ok(), block(1), MockPacket::Raw(bad)],
+            )
+            .on(Command::CmdStartBookmark, vec![MockPacket::ok(), block(1)])
+            .on(
+                Command::CmdStartBookmark,
+                vec![MockPacket::ok(), block(1), data(1), data(2)],
+            )
+            .on(Command::CmdStop, vec![MockPacket::ok()])
+            .start()
+            .await
+            .unwrap();
+
+        let mut client = StreamClient::builder()
+            .server(server.addr())
+            .command_timeout(Duration::from_millis(200))
+            .build()
+            .unwrap();
+        client.connect_server().await.unwrap();
+        let buffer = SharedBuffer::default();
+        client.tee_to(buffer.clone()).await.unwrap();
+        let bookmark = |n: u64| Bookmark::new(0, n.to_be_bytes()[1..].to_vec());
+
+        // A range failing to be read closes the connection left streaming
+        let res = client
+            .entries_between_bookmarks(bookmark(1), bookmark(2))
+            .await;
+        assert!(matches!(res, Err(ClientError::DecodeError(_))), "{res:?}");
+        assert!(!client.is_connected());
+        assert!(!client.streaming);
+
+        // A range the server stops sending times out
+        let res = client
+            .entries_between_bookmarks(bookmark(1), bookmark(2))
+            .await;
+        assert!(matches!(res, Err(ClientError::Timeout)), "{res:?}");
+        assert!(!client.is_connected());
+
+        // Reaching the last entry without the to bookmark stops the streaming
+        let res = client
+            .entries_between_bookmarks(bookmark(1), bookmark(2))
+            .await;
+        assert!(matches!(res, Err(ClientError::BookmarkNotFound)), "{res:?}");
+        assert!(client.is_connected());
+        let stops = server
+            .received()
+            .iter()
+            .filter(|c| c.command == Command::CmdStop as u64)
+            .count();
+        assert_eq!(stops, 1);
+
+        // The range entries aren't teed
+        client.finish_tee().await.unwrap();
+        let mut reader =
+            crate::stream_file::FileStreamReader::new(std::io::Cursor::new(buffer.contents()))
+                .unwrap();
+        assert_eq!(reader.entries().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_snapshot() {
+        let entries = (0..8)
+            .map(|n| MockPacket::data(n, EntryType::Event1, vec![n as u8]))
+            .collect::<Vec<_>>();
+        let server = MockStreamServer::builder()
+            .on(
+                Command::CmdHeader,
+                vec![MockPacket::ok(), MockPacket::header(8)],
+            )
+            .on(
+                Command::CmdStart,
+                [vec![MockPacket::ok()], entries].concat(),
+            )
+            .on(Command::CmdStop, vec![MockPacket::ok()])
+            .start()
+            .await
+            .unwrap();
+
+        let mut client = StreamClient::new(server.addr()).unwrap();
+        client.connect_server().await.unwrap();
+
+        let entries = client.snapshot().await.unwrap();
+        let numbers: Vec<u64> = entries.iter().map(|e| e.number).collect();
+        assert_eq!(numbers, (0..8).collect::<Vec<u64>>());
+        assert_eq!(entries[7].data, vec![7]);
+        assert_eq!(client.status(), ClientStatus::CsStopped);
+
+        // A capped snapshot is flagged as partial
+        let snapshot = client.snapshot_with_limit(3).await.unwrap();
+        assert_eq!(snapshot.entries.len(), 3);
+        assert!(snapshot.partial);
+        let snapshot = client.snapshot_with_limit(8).await.unwrap();
+        assert_eq!(snapshot.entries.len(), 8);
+        assert!(!snapshot.partial);
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_empty_stream() {
+        let server = MockStreamServer::builder()
+            .on(
+                Command::CmdHeader,
+                vec![MockPacket::ok(), MockPacket::header(0)],
+            )
+            .start()
+            .await
+            .unwrap();
+
+        let mut client = StreamClient::new(server.addr()).unwrap();
+        client.connect_server().await.unwrap();
+
+        assert!(client.snapshot().await.unwrap().is_empty());
+        let commands: Vec<u64> = server.received().iter().map(|c| c.command).collect();
+        assert_eq!(commands, vec![Command::CmdHeader as u64]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_entry_serde_json_round_trip() {
+        let entry = Entry {
+            packet_type: PacketType::PtData as u8,
+            length: FIXED_SIZE_FILE_ENTRY as u32 + 3,
+            entry_type: EntryType::Event2,
+            number: 9,
+            data: vec![1, 2, 3],
+            ..Default::default()
+        };
+
+        let json = serde_json::to_string(&entry).unwrap();
+        assert!(json.contains(r#""data":"AQID""#), "{json}");
+        let decoded: Entry = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, entry);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_checkpoint_serde_json_round_trip() {
+        let checkpoints = [
+            Checkpoint {
+                last_delivered: Some(41),
+                resume_from: 42,
+                start_mode: StartMode::Entry,
+            },
+            Checkpoint {
+                last_delivered: None,
+                resume_from: 0,
+                start_mode: StartMode::Bookmark(Bookmark::new(1, vec![7]).encode()),
+            },
+        ];
+        for checkpoint in checkpoints {
+            let json = serde_json::to_string(&checkpoint).unwrap();
+            let decoded: Checkpoint = serde_json::from_str(&json).unwrap();
+            assert_eq!(decoded, checkpoint);
+        }
+    }
+
+    #[test]
+    fn test_header_entry_encode_decode_round_trip() {
+        let header = HeaderEntry {
+            packet_type: PacketType::PtHeader as u8,
+            head_length: HEADER_SIZE as u32,
+            version: 1,
+            system_id: ChainId(1101),
+            stream_type: StreamType::Sequencer,
+            total_length: 1 << 20,
+            total_entries: 5000,
+        };
+
+        let b = header.encode_to_binary();
+        assert_eq!(b.len(), HEADER_SIZE);
+        assert_eq!(decode_binary_to_header_entry(&b).unwrap(), header);
+    }
+
+    #[test]
+    fn test_stream_type_round_trip() {
+        for v in [0, 1, 2, u64::MAX] {
+            assert_eq!(u64::from(StreamType::from(v)), v);
+        }
+        assert_eq!(StreamType::from(1), StreamType::Sequencer);
+        assert_eq!(StreamType::from(2), StreamType::Other(2));
+
+        // The header keeps the stream type number
+        let header = HeaderEntry {
+            packet_type: PacketType::PtHeader as u8,
+            head_length: HEADER_SIZE as u32,
+            version: 1,
+            stream_type: StreamType::Other(2),
+            ..Default::default()
+        };
+        let b = header.encode_to_binary();
+        assert_eq!(BigEndian::read_u64(&b[14..22]), 2);
+        let decoded = decode_binary_to_header_entry(&b).unwrap();
+        assert_eq!(decoded.stream_type, StreamType::Other(2));
+    }
+
+    #[test]
+    fn test_result_entry_encode_decode_round_trip() {
+        let fixtures = vec![
+            ResultEntry {
+                packet_type: PacketType::PtResult as u8,
+                length: FIXED_SIZE_RESULT_ENTRY as u32,
+                error_num: u32::from(CommandError::CmdErrOK),
+                error_str: vec![],
+            },
+            ResultEntry {
+                packet_type: PacketType::PtResult as u8,
+                length: FIXED_SIZE_RESULT_ENTRY as u32 + 12,
+                error_num: u32::from(CommandError::CmdErrBadFromEntry),
+                error_str: b"bad entry 99".to_vec(),
+            },
+        ];
+
+        for result in fixtures {
+            let b = result.encode_to_binary();
+            assert_eq!(b.len(), result.length as usize);
+            assert_eq!(decode_binary_to_result_entry(&b).unwrap(), result);
+        }
+    }
+
+    #[test]
+    fn test_result_entry_length_mismatch() {
+        let mut b = ResultEntry {
+            packet_type: PacketType::PtResult as u8,
+            error_num: u32::from(CommandError::CmdErrBadFromEntry),
+            error_str: b"bad entry 99".to_vec(),
+            ..Default::default()
+        }
+        .encode_to_binary();
+
+        // Declared length longer and shorter than the error string sent
+        for length in [b.len() + 4, b.len() - 4] {
+            b[1..5].copy_from_slice(&(length as u32).to_be_bytes());
+            match decode_binary_to_result_entry(&b) {
+                Err(ClientError::DecodeError(msg)) => assert!(
+                    msg.contains(&format!("length field is {} bytes, got 21", length)),
+                    "{msg}"
+                ),
+                other => panic!("unexpected result: {:?}", other),
+            }
+        }
+
+        assert!(matches!(
+            decode_binary_to_result_entry(&b[..5]),
+            Err(ClientError::DecodeError(_))
+        ));
     }
 }
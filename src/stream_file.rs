@@ -0,0 +1,510 @@
+use crate::protocol::{EntryType, PacketType, StreamType};
+use crate::stream_client::{
+    decode_binary_to_entry, decode_binary_to_header_entry, Entry, HeaderEntry,
+    FIXED_SIZE_FILE_ENTRY, HEADER_SIZE,
+};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+pub const PAGE_HEADER_SIZE: u64 = 4096; // Header page size, the header entry is padded up to it
+pub const PAGE_DATA_SIZE: u64 = 1024 * 1024; // Data page size, entries never span two data pages and the rest of a page is zero padded
+
+// validate_page_size checks a data page size is a power of two large enough to hold a header entry
+pub fn validate_page_size(page_size: u64) -> Result<(), String> {
+    if !page_size.is_power_of_two() || page_size < HEADER_SIZE as u64 {
+        return Err(format!(
+            "page size {} must be a power of two of at least {} bytes",
+            page_size, HEADER_SIZE
+        ));
+    }
+    Ok(())
+}
+
+// FileStreamReader type to read the entries of a datastream file
+pub struct FileStreamReader<R> {
+    reader: R,
+    header: HeaderEntry,
+    pos: u64,       // Current position in the file
+    page_size: u64, // Data page size, PAGE_DATA_SIZE by default
+    done: bool,     // Flag end of file or error reached
+}
+
+impl FileStreamReader<BufReader<File>> {
+    // open opens a datastream file and reads its header
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = File::open(path)?;
+        FileStreamReader::new(BufReader::new(file))
+    }
+}
+
+impl<R: Read + Seek> FileStreamReader<R> {
+    // new reads the header from a datastream file reader and positions it at the first data page
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut buffer = vec![0; HEADER_SIZE];
+        reader.seek(SeekFrom::Start(0))?;
+        reader.read_exact(&mut buffer)?;
+        let header = decode_binary_to_header_entry(&buffer).map_err(invalid_data)?;
+
+        reader.seek(SeekFrom::Start(PAGE_HEADER_SIZE))?;
+
+        Ok(FileStreamReader {
+            reader,
+            header,
+            pos: PAGE_HEADER_SIZE,
+            page_size: PAGE_DATA_SIZE,
+            done: false,
+        })
+    }
+
+    // with_page_size sets the data page size of the file, for streams not using PAGE_DATA_SIZE
+    pub fn with_page_size(mut self, page_size: u64) -> io::Result<Self> {
+        validate_page_size(page_size).map_err(|e| io::Error::new(ErrorKind::InvalidInput, e))?;
+        self.page_size = page_size;
+        Ok(self)
+    }
+
+    // header returns the header entry of the file
+    pub fn header(&self) -> &HeaderEntry {
+        &self.header
+    }
+
+    // entries returns an iterator over the data entries of the file
+    pub fn entries(&mut self) -> impl Iterator<Item = io::Result<Entry>> + '_ {
+        std::iter::from_fn(move || {
+            if self.done {
+                return None;
+            }
+            let res = self.read_entry().transpose();
+            if !matches!(res, Some(Ok(_))) {
+                self.done = true;
+            }
+            res
+        })
+    }
+
+    // read_entry reads the next data entry skipping the page padding, returns None at the end of the data
+    fn read_entry(&mut self) -> io::Result<Option<Entry>> {
+        loop {
+            if self.pos >= self.header.total_length {
+                return Ok(None);
+            }
+
+            let mut packet = [0u8; 1];
+            self.reader.read_exact(&mut packet)?;
+
+            match PacketType::from(packet[0]) {
+                // Unknown packet types are also converted to PtPadding
+                PacketType::PtPadding if packet[0] == PacketType::PtPadding as u8 => {
+                    // Skip the rest of the page
+                    self.pos = next_page(self.pos, self.page_size);
+                    self.reader.seek(SeekFrom::Start(self.pos))?;
+                }
+                PacketType::PtData => {
+                    let mut length_field = [0u8; 4];
+                    self.reader.read_exact(&mut length_field)?;
+                    let length = u32::from_be_bytes(length_field) as u64;
+
+                    // An entry can't be shorter than its fixed fields nor end past the data
+                    let end = self.pos.checked_add(length);
+                    if length < FIXED_SIZE_FILE_ENTRY as u64
+                        || end.is_none_or(|end| end > self.header.total_length)
+                    {
+                        return Err(io::Error::new(
+                            ErrorKind::InvalidData,
+                            format!(
+                                "invalid data entry length at position {}: {}",
+                                self.pos, length
+                            ),
+                        ));
+                    }
+
+                    let mut buffer = vec![0; length as usize];
+                    buffer[0] = packet[0];
+                    buffer[1..5].copy_from_slice(&length_field);
+                    self.reader.read_exact(&mut buffer[5..])?;
+                    self.pos += length;
+
+                    return decode_binary_to_entry(&buffer)
+                        .map(Some)
+                        .map_err(invalid_data);
+                }
+                _ => {
+                    return Err(io::Error::new(
+                        ErrorKind::InvalidData,
+                        format!(
+                            "unexpected packet type {:#04x} at position {}",
+                            packet[0], self.pos
+                        ),
+                    ));
+                }
+            }
+        }
+    }
+}
+
+// FileStreamWriter type to write a datastream file readable by FileStreamReader and the Go
+// datastreamer. The header is only updated on flush and close.
+pub struct FileStreamWriter<W: Write + Seek> {
+    writer: W,
+    header: HeaderEntry,
+    page_size: u64, // Data page size, PAGE_DATA_SIZE by default
+}
+
+impl FileStreamWriter<BufWriter<File>> {
+    // create creates a new datastream file, truncating it if it exists
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        version: u8,
+        system_id: u64,
+        stream_type: StreamType,
+    ) -> io::Result<Self> {
+        let file = File::create(path)?;
+        FileStreamWriter::new(BufWriter::new(file), version, system_id, stream_type)
+    }
+}
+
+impl<W: Write + Seek> FileStreamWriter<W> {
+    // new writes the header page of an empty datastream and positions the writer at the first data page
+    pub fn new(
+        writer: W,
+        version: u8,
+        system_id: u64,
+        stream_type: StreamType,
+    ) -> io::Result<Self> {
+        let header = HeaderEntry {
+            version,
+            system_id: system_id.into(),
+            stream_type,
+            ..Default::default()
+        };
+        FileStreamWriter::from_header(writer, header)
+    }
+
+    // from_header writes the header page of an empty datastream with the version, system id and
+    // stream type of header, and positions the writer at the first data page
+    pub(crate) fn from_header(mut writer: W, header: HeaderEntry) -> io::Result<Self> {
+        let header = HeaderEntry {
+            packet_type: PacketType::PtHeader as u8,
+            head_length: HEADER_SIZE as u32,
+            total_length: PAGE_HEADER_SIZE,
+            total_entries: 0,
+            ..header
+        };
+
+        let mut page = header.encode_to_binary();
+        page.resize(PAGE_HEADER_SIZE as usize, 0);
+        writer.seek(SeekFrom::Start(0))?;
+        writer.write_all(&page)?;
+
+        Ok(FileStreamWriter {
+            writer,
+            header,
+            page_size: PAGE_DATA_SIZE,
+        })
+    }
+
+    // with_page_size sets the data page size of the file, before any entry is appended
+    pub fn with_page_size(mut self, page_size: u64) -> io::Result<Self> {
+        validate_page_size(page_size).map_err(|e| io::Error::new(ErrorKind::InvalidInput, e))?;
+        if self.header.total_entries > 0 {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "page size set after appending entries",
+            ));
+        }
+        self.page_size = page_size;
+        Ok(self)
+    }
+
+    // header returns the header entry with the entries appended so far
+    pub fn header(&self) -> &HeaderEntry {
+        &self.header
+    }
+
+    // append_entry appends a data entry numbered after the last one and returns its number,
+    // padding the current data page if the entry doesn't fit in it
+    pub fn append_entry(&mut self, entry_type: EntryType, data: Vec<u8>) -> io::Result<u64> {
+        let entry = Entry {
+            packet_type: PacketType::PtData as u8,
+            length: (FIXED_SIZE_FILE_ENTRY + data.len()) as u32,
+            entry_type,
+            number: self.header.total_entries,
+            data,
+            raw: None,
+            received_at: None,
+        };
+        self.append_encoded(&entry.encode_to_binary())?;
+
+        Ok(entry.number)
+    }
+
+    // append_encoded appends the framed bytes of a data entry as they are, keeping its number,
+    // padding the current data page if the entry doesn't fit in it
+    pub(crate) fn append_encoded(&mut self, b: &[u8]) -> io::Result<()> {
+        let length = b.len() as u64;
+        if length > self.page_size {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "entry of {} bytes exceeds the data page size of {} bytes",
+                    length, self.page_size
+                ),
+            ));
+        }
+
+        let pos = self.header.total_length;
+        let page_end = next_page(pos, self.page_size);
+        if pos + length > page_end {
+            let padding = vec![PacketType::PtPadding as u8; (page_end - pos) as usize];
+            self.writer.write_all(&padding)?;
+            self.header.total_length = page_end;
+        }
+        self.writer.write_all(b)?;
+        self.header.total_length += length;
+        self.header.total_entries += 1;
+
+        Ok(())
+    }
+
+    // flush writes the updated header and flushes the written entries
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.writer.write_all(&self.header.encode_to_binary())?;
+        self.writer
+            .seek(SeekFrom::Start(self.header.total_length))?;
+        self.writer.flush()
+    }
+
+    // get_mut returns the underlying writer
+    pub(crate) fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    // close flushes the file and returns the underlying writer
+    pub fn close(mut self) -> io::Result<W> {
+        self.flush()?;
+        Ok(self.writer)
+    }
+}
+
+// next_page returns the position of the data page following the given position
+fn next_page(pos: u64, page_size: u64) -> u64 {
+    let offset = pos - PAGE_HEADER_SIZE;
+    PAGE_HEADER_SIZE + (offset / page_size + 1) * page_size
+}
+
+fn invalid_data<E: std::error::Error + Send + Sync + 'static>(e: E) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream_client::{ChainId, EntryType, StreamType};
+    use std::io::Cursor;
+
+    fn entry(number: u64, data: Vec<u8>) -> Entry {
+        Entry {
+            packet_type: PacketType::PtData as u8,
+            length: (FIXED_SIZE_FILE_ENTRY + data.len()) as u32,
+            entry_type: EntryType::Event1,
+            number,
+            data,
+            ..Default::default()
+        }
+    }
+
+    // stream_file builds a datastream file with the entries of each data page
+    fn stream_file(pages: &[Vec<Entry>]) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut total_entries = 0;
+        for (i, entries) in pages.iter().enumerate() {
+            for e in entries {
+                data.extend_from_slice(&e.encode_to_binary());
+                total_entries += 1;
+            }
+            if i < pages.len() - 1 {
+                data.resize(
+                    (i + 1) * PAGE_DATA_SIZE as usize,
+                    PacketType::PtPadding as u8,
+                );
+            }
+        }
+
+        let header = HeaderEntry {
+            packet_type: PacketType::PtHeader as u8,
+            head_length: HEADER_SIZE as u32,
+            version: 1,
+            system_id: ChainId(1101),
+            stream_type: StreamType::Sequencer,
+            total_length: PAGE_HEADER_SIZE + data.len() as u64,
+            total_entries,
+        };
+        let mut file = header.encode_to_binary();
+        file.resize(PAGE_HEADER_SIZE as usize, 0);
+        file.extend_from_slice(&data);
+        file
+    }
+
+    #[test]
+    fn test_file_stream_reader_skips_padding() {
+        let pages = vec![
+            vec![entry(0, vec![1, 2, 3]), entry(1, vec![])],
+            vec![entry(2, vec![4; 100])],
+            vec![entry(3, vec![5])],
+        ];
+        let mut reader = FileStreamReader::new(Cursor::new(stream_file(&pages))).unwrap();
+        assert_eq!(reader.header().system_id, ChainId(1101));
+        assert_eq!(reader.header().total_entries, 4);
+
+        let entries: Vec<Entry> = reader.entries().map(|e| e.unwrap()).collect();
+        assert_eq!(entries, pages.concat());
+    }
+
+    #[test]
+    fn test_file_stream_reader_truncated_file() {
+        let mut file = stream_file(&[vec![entry(0, vec![1, 2, 3]), entry(1, vec![6; 10])]]);
+        file.truncate(file.len() - 5);
+
+        let mut reader = FileStreamReader::new(Cursor::new(file)).unwrap();
+        let entries: Vec<io::Result<Entry>> = reader.entries().collect();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].as_ref().unwrap().number, 0);
+        assert_eq!(
+            entries[1].as_ref().unwrap_err().kind(),
+            ErrorKind::UnexpectedEof
+        );
+    }
+
+    #[test]
+    fn test_file_stream_reader_random_data() {
+        let file = stream_file(&[vec![entry(0, vec![1, 2, 3])]]);
+        let data_start = PAGE_HEADER_SIZE as usize;
+
+        // Random bytes after the header are rejected without panicking
+        for _ in 0..1000 {
+            let mut file = file.clone();
+            let len = rand::random::<usize>() % 40;
+            file.truncate(data_start + len);
+            for b in file[data_start..].iter_mut() {
+                *b = rand::random();
+            }
+            if len > 0 {
+                file[data_start] = PacketType::PtData as u8;
+            }
+
+            let mut reader = FileStreamReader::new(Cursor::new(file)).unwrap();
+            let _ = reader.entries().count();
+        }
+
+        // An entry ending past the data of the stream
+        let mut file = file.clone();
+        file[data_start + 1..data_start + 5].copy_from_slice(&u32::MAX.to_be_bytes());
+        let mut reader = FileStreamReader::new(Cursor::new(file)).unwrap();
+        let err = reader.entries().next().unwrap().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_file_stream_writer_round_trip() {
+        let mut writer =
+            FileStreamWriter::new(Cursor::new(Vec::new()), 1, 1101, StreamType::Sequencer).unwrap();
+        let big = vec![0xab; PAGE_DATA_SIZE as usize - FIXED_SIZE_FILE_ENTRY - 10];
+        assert_eq!(
+            writer
+                .append_entry(EntryType::Event1, vec![1, 2, 3])
+                .unwrap(),
+            0
+        );
+        // Each entry doesn't fit in the rest of the previous page
+        assert_eq!(
+            writer.append_entry(EntryType::Event2, big.clone()).unwrap(),
+            1
+        );
+        assert_eq!(writer.append_entry(EntryType::Bookmark, vec![]).unwrap(), 2);
+        assert_eq!(writer.header().total_entries, 3);
+
+        let file = writer.close().unwrap().into_inner();
+        let mut reader = FileStreamReader::new(Cursor::new(file)).unwrap();
+        assert_eq!(reader.header().system_id, ChainId(1101));
+        assert_eq!(reader.header().total_entries, 3);
+        assert_eq!(
+            reader.header().total_length,
+            PAGE_HEADER_SIZE + 2 * PAGE_DATA_SIZE + FIXED_SIZE_FILE_ENTRY as u64
+        );
+
+        let entries: Vec<Entry> = reader.entries().map(|e| e.unwrap()).collect();
+        let mut expected = vec![entry(0, vec![1, 2, 3]), entry(1, big), entry(2, vec![])];
+        expected[1].entry_type = EntryType::Event2;
+        expected[2].entry_type = EntryType::Bookmark;
+        assert_eq!(entries, expected);
+    }
+
+    #[test]
+    fn test_file_stream_writer_matches_layout() {
+        let pages = vec![vec![entry(0, vec![1, 2, 3]), entry(1, vec![4; 10])]];
+        let mut writer =
+            FileStreamWriter::new(Cursor::new(Vec::new()), 1, 1101, StreamType::Sequencer).unwrap();
+        for e in &pages[0] {
+            writer.append_entry(e.entry_type, e.data.clone()).unwrap();
+        }
+
+        assert_eq!(writer.close().unwrap().into_inner(), stream_file(&pages));
+    }
+
+    #[test]
+    fn test_page_size() {
+        let mut writer =
+            FileStreamWriter::new(Cursor::new(Vec::new()), 1, 1101, StreamType::Sequencer)
+                .unwrap()
+                .with_page_size(64)
+                .unwrap();
+        // The second entry doesn't fit in the rest of the first page, padded to its boundary
+        for data in [vec![1; 20], vec![2; 20], vec![3; 47]] {
+            writer.append_entry(EntryType::Event1, data).unwrap();
+        }
+        assert!(writer.append_entry(EntryType::Event1, vec![4; 48]).is_err());
+        let file = writer.close().unwrap().into_inner();
+        let data_start = PAGE_HEADER_SIZE as usize;
+        assert!(file[data_start + 37..data_start + 64]
+            .iter()
+            .all(|b| *b == PacketType::PtPadding as u8));
+        assert_eq!(file.len(), data_start + 3 * 64);
+
+        let mut reader = FileStreamReader::new(Cursor::new(file))
+            .unwrap()
+            .with_page_size(64)
+            .unwrap();
+        let entries: Vec<Entry> = reader.entries().map(|e| e.unwrap()).collect();
+        assert_eq!(
+            entries,
+            vec![
+                entry(0, vec![1; 20]),
+                entry(1, vec![2; 20]),
+                entry(2, vec![3; 47])
+            ]
+        );
+
+        for page_size in [0, 32, 100, 4095] {
+            assert!(validate_page_size(page_size).is_err(), "{page_size}");
+        }
+        assert!(validate_page_size(PAGE_DATA_SIZE).is_ok());
+        assert!(FileStreamReader::new(Cursor::new(stream_file(&[vec![]])))
+            .unwrap()
+            .with_page_size(1000)
+            .is_err());
+    }
+
+    #[test]
+    fn test_file_stream_reader_open() {
+        let path = std::env::temp_dir().join(format!("datastream-{}.bin", std::process::id()));
+        std::fs::write(&path, stream_file(&[vec![entry(0, vec![7])]])).unwrap();
+
+        let mut reader = FileStreamReader::open(&path).unwrap();
+        let entries: Vec<Entry> = reader.entries().map(|e| e.unwrap()).collect();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(entries, vec![entry(0, vec![7])]);
+    }
+}
@@ -1,145 +1,686 @@
-use byteorder::{BigEndian, ByteOrder};
-use log;
-use log::{debug, error};
-use std::convert::TryInto;
+pub use crate::protocol::{Command, CommandError, ENTRY_TYPE_NOT_FOUND};
+use crate::protocol::{EntryType, PacketType, StreamType};
+pub use crate::stream_client::ClientStatus;
+use crate::stream_client::{
+    Entry, HeaderEntry, ResultEntry, FIXED_SIZE_FILE_ENTRY, HEADER_SIZE, MAX_BOOKMARK_LENGTH,
+};
+use std::convert::TryFrom;
 use std::io;
-use std::net;
-use std::sync;
-use std::time;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, watch, Semaphore};
+use tracing::{debug, error, info};
 
-// Command type for the TCP client commands
-pub type Command = u64;
+pub const MAX_CONNECTIONS: usize = 100; // Maximum number of connected clients
+pub const STREAM_BUFFER: usize = 256; // Buffers for the stream channel
 
-// ClientStatus type for the status of the client
-pub type ClientStatus = u64;
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AOStatus {
+    // Atomic operation status
+    AoNone = 1,
+    AoStarted,
+    AoCommitting,
+    AoRollbacking = 0xff,
+}
 
-// AOStatus type for the atomic operation internal states
-pub type AOStatus = u64;
+// ServerError enum represents the server errors
+#[derive(Debug, Error)]
+pub enum ServerError {
+    #[error("Server already started")]
+    AlreadyStarted,
+    #[error("Error network")]
+    NetworkError(std::io::Error),
+    #[error("Atomic operation not allowed with status {0:?}")]
+    InvalidAtomicOpStatus(AOStatus),
+}
 
-// EntryType type for the entry event types
-pub type EntryType = u32;
+// ClientCommand type for a command received from a client with its parameters
+#[derive(Debug)]
+struct ClientCommand {
+    cmd: Result<Command, u64>,
+    stream_type: u64,
+    from_entry: u64,
+    bookmark: Vec<u8>,
+}
 
-// StreamType type for the stream types
-pub type StreamType = u64;
+// ServerState type for the entries served and the header describing them
+struct ServerState {
+    header: HeaderEntry,
+    entries: Vec<Entry>,
+    ao_status: AOStatus,
+    ao_entries: Vec<Entry>, // Entries of the atomic operation, served once committed
+}
 
-// CommandError type for the command responses
-pub type CommandError = u32;
+// Shared type for the state shared between the server and the client connections
+struct Shared {
+    state: Mutex<ServerState>,
+    stream_type: StreamType,
+    total_entries: watch::Sender<u64>, // Notifies the client connections about new entries
+    connections: Arc<Semaphore>,       // Limits the connected clients to MAX_CONNECTIONS
+}
 
-// EntryTypeNotFound is the entry type value for CmdEntry/CmdBookmark when entry/bookmark not found
-pub const ENTRY_TYPE_NOT_FOUND: u32 = u32::MAX;
+impl Shared {
+    fn header(&self) -> HeaderEntry {
+        self.state.lock().unwrap().header.clone()
+    }
 
-pub const MAX_CONNECTIONS: usize = 100; // Maximum number of connected clients
-pub const STREAM_BUFFER: usize = 256; // Buffers for the stream channel
-pub const MAX_BOOKMARK_LENGTH: usize = 16; // Maximum number of bytes for a bookmark
-
-#[derive(Debug, Clone, Copy)]
-pub enum Command {
-    CmdStart = 1,     // CmdStart for the start from entry TCP client command
-    CmdStop,          // CmdStop for the stop TCP client command
-    CmdHeader,        // CmdHeader for the header TCP client command
-    CmdStartBookmark, // CmdStartBookmark for the start from bookmark TCP client command
-    CmdEntry,         // CmdEntry for the get entry TCP client command
-    CmdBookmark,      // CmdBookmark for the get bookmark TCP client command
-}
+    fn entry(&self, number: u64) -> Option<Entry> {
+        let state = self.state.lock().unwrap();
+        state.entries.get(number as usize).cloned()
+    }
 
-#[derive(Debug, Clone, Copy)]
-pub enum CommandError {
-    CmdErrOK = 0,             // CmdErrOK for no error
-    CmdErrAlreadyStarted,     // CmdErrAlreadyStarted for client already started error
-    CmdErrAlreadyStopped,     // CmdErrAlreadyStopped for client already stopped error
-    CmdErrBadFromEntry,       // CmdErrBadFromEntry for invalid starting entry number
-    CmdErrBadFromBookmark,    // CmdErrBadFromBookmark for invalid starting bookmark
-    CmdErrInvalidCommand = 9, // CmdErrInvalidCommand for invalid/unknown command error
+    fn bookmark(&self, bookmark: &[u8]) -> Option<Entry> {
+        let state = self.state.lock().unwrap();
+        state
+            .entries
+            .iter()
+            .find(|e| e.entry_type == EntryType::Bookmark && e.data == bookmark)
+            .cloned()
+    }
 }
 
-#[derive(Debug, Clone, Copy)]
-pub enum ClientStatus {
-    CsSyncing = 1,
-    CsSynced,
-    CsStopped,
-    CsKilled = 0xff,
+// StreamServer type to serve the stream entries to the TCP clients
+pub struct StreamServer {
+    port: u16, // Server port to listen
+    local_addr: Option<SocketAddr>,
+    shared: Arc<Shared>,
 }
 
-#[derive(Debug, Clone, Copy)]
-pub enum AOStatus {
-    // Atomic operation status
-    AoNone = 1,
-    AoStarted,
-    AoCommitting,
-    AoRollbacking = 0xff,
-}
+impl StreamServer {
+    pub fn new(port: u16, version: u8, system_id: u64, stream_type: StreamType) -> StreamServer {
+        let header = HeaderEntry {
+            packet_type: PacketType::PtHeader as u8,
+            head_length: HEADER_SIZE as u32,
+            version,
+            system_id: system_id.into(),
+            stream_type,
+            total_length: HEADER_SIZE as u64,
+            total_entries: 0,
+        };
+        let (total_entries, _) = watch::channel(0);
+
+        StreamServer {
+            port,
+            local_addr: None,
+            shared: Arc::new(Shared {
+                state: Mutex::new(ServerState {
+                    header,
+                    entries: Vec::new(),
+                    ao_status: AOStatus::AoNone,
+                    ao_entries: Vec::new(),
+                }),
+                stream_type,
+                total_entries,
+                connections: Arc::new(Semaphore::new(MAX_CONNECTIONS)),
+            }),
+        }
+    }
 
-// StrClientStatus for client status description
-use std::collections::HashMap;
+    // start binds the TCP listener and starts accepting clients in the background
+    pub async fn start(&mut self) -> Result<(), ServerError> {
+        if self.local_addr.is_some() {
+            return Err(ServerError::AlreadyStarted);
+        }
+
+        let listener = TcpListener::bind(("0.0.0.0", self.port))
+            .await
+            .map_err(ServerError::NetworkError)?;
+        let local_addr = listener.local_addr().map_err(ServerError::NetworkError)?;
+        self.local_addr = Some(local_addr);
+        info!("Listening on port: {}", local_addr.port());
+
+        let shared = self.shared.clone();
+        tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((conn, addr)) => {
+                        let Ok(permit) = shared.connections.clone().try_acquire_owned() else {
+                            error!("{} Maximum number of connections reached", addr);
+                            continue;
+                        };
+                        info!("{} New connection", addr);
+                        let shared = shared.clone();
+                        tokio::spawn(async move {
+                            handle_connection(conn, addr, shared).await;
+                            drop(permit);
+                        });
+                    }
+                    Err(e) => {
+                        error!("Error accepting new connection: {}", e);
+                    }
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    // local_addr returns the address the server is listening on once started
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+
+    // start_atomic_op starts an atomic operation to add entries to the stream
+    pub fn start_atomic_op(&self) -> Result<(), ServerError> {
+        let mut state = self.shared.state.lock().unwrap();
+        if state.ao_status != AOStatus::AoNone {
+            return Err(ServerError::InvalidAtomicOpStatus(state.ao_status));
+        }
+
+        state.ao_status = AOStatus::AoStarted;
+        Ok(())
+    }
+
+    // add_stream_entry adds a new data entry to the started atomic operation and returns its
+    // entry number, the entry is served to the clients once the operation is committed
+    pub fn add_stream_entry(
+        &self,
+        entry_type: EntryType,
+        data: Vec<u8>,
+    ) -> Result<u64, ServerError> {
+        let mut state = self.shared.state.lock().unwrap();
+        if state.ao_status != AOStatus::AoStarted {
+            return Err(ServerError::InvalidAtomicOpStatus(state.ao_status));
+        }
+
+        let number = state.header.total_entries + state.ao_entries.len() as u64;
+        let entry = Entry {
+            packet_type: PacketType::PtData as u8,
+            length: (FIXED_SIZE_FILE_ENTRY + data.len()) as u32,
+            entry_type,
+            number,
+            data,
+            raw: None,
+            received_at: None,
+        };
+        state.ao_entries.push(entry);
+
+        Ok(number)
+    }
+
+    // commit_atomic_op makes the entries of the atomic operation visible to the clients
+    pub fn commit_atomic_op(&self) -> Result<(), ServerError> {
+        let mut state = self.shared.state.lock().unwrap();
+        if state.ao_status != AOStatus::AoStarted {
+            return Err(ServerError::InvalidAtomicOpStatus(state.ao_status));
+        }
+
+        state.ao_status = AOStatus::AoCommitting;
+        let entries = std::mem::take(&mut state.ao_entries);
+        for entry in entries {
+            state.header.total_length += entry.length as u64;
+            state.header.total_entries += 1;
+            state.entries.push(entry);
+        }
+        state.ao_status = AOStatus::AoNone;
+
+        self.shared
+            .total_entries
+            .send_replace(state.header.total_entries);
+        Ok(())
+    }
+
+    // rollback_atomic_op discards the entries of the atomic operation
+    pub fn rollback_atomic_op(&self) -> Result<(), ServerError> {
+        let mut state = self.shared.state.lock().unwrap();
+        if state.ao_status != AOStatus::AoStarted {
+            return Err(ServerError::InvalidAtomicOpStatus(state.ao_status));
+        }
+
+        state.ao_status = AOStatus::AoRollbacking;
+        state.ao_entries.clear();
+        state.ao_status = AOStatus::AoNone;
+        Ok(())
+    }
+
+    // ao_status returns the status of the atomic operation
+    pub fn ao_status(&self) -> AOStatus {
+        self.shared.state.lock().unwrap().ao_status
+    }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub enum ClientStatus {
-    CsSyncing,
-    CsSynced,
-    CsStopped,
-    CsKilled,
+    // get_header returns the current stream header
+    pub fn get_header(&self) -> HeaderEntry {
+        self.shared.header()
+    }
+
+    // get_entry returns the data entry with the given entry number
+    pub fn get_entry(&self, number: u64) -> Option<Entry> {
+        self.shared.entry(number)
+    }
 }
 
-// let mut str_client_status: HashMap<ClientStatus, &str> = HashMap::new();
-// str_client_status.insert(ClientStatus::CsSyncing, "Syncing");
-// str_client_status.insert(ClientStatus::CsSynced, "Synced");
-// str_client_status.insert(ClientStatus::CsStopped, "Stopped");
-// str_client_status.insert(ClientStatus::CsKilled, "Killed");
+// handle_connection serves the commands of a connected client until it disconnects
+async fn handle_connection(conn: TcpStream, addr: SocketAddr, shared: Arc<Shared>) {
+    let (reader, mut writer) = conn.into_split();
+
+    // Commands are read in their own task so the entries can be streamed while waiting for them
+    let (cmd_tx, mut cmd_rx) = mpsc::channel(1);
+    tokio::spawn(read_commands(reader, cmd_tx));
+
+    let mut total_entries = shared.total_entries.subscribe();
+    let mut streaming: Option<u64> = None; // Next entry number to stream
+
+    loop {
+        // Send the entries pending to stream
+        if let Some(next) = streaming.as_mut() {
+            while let Some(e) = shared.entry(*next) {
+                if let Err(e) = writer.write_all(&e.encode_to_binary()).await {
+                    error!("{} Error sending entry: {}", addr, e);
+                    return;
+                }
+                *next += 1;
+            }
+        }
 
-const FIXED_SIZE_RESULT_ENTRY: usize = 9; // Replace with actual value
+        tokio::select! {
+            cmd = cmd_rx.recv() => {
+                let Some(cmd) = cmd else {
+                    break;
+                };
+                match process_command(&mut writer, &shared, &mut streaming, cmd).await {
+                    Ok(true) => {}
+                    Ok(false) => break,
+                    Err(e) => {
+                        error!("{} Error processing command: {}", addr, e);
+                        break;
+                    }
+                }
+            }
+            res = total_entries.changed(), if streaming.is_some() => {
+                if res.is_err() {
+                    break;
+                }
+            }
+        }
+    }
 
-struct ResultEntry {
-    packet_type: u8,
-    length: u32,
-    error_num: u32,
-    error_str: Vec<u8>,
+    info!("{} Connection closed", addr);
 }
 
-enum Command {
-    CmdStart, // Replace with actual value
-    CmdBookmark, // Replace with actual value
-              // Add other commands here
+// read_commands reads the client commands and forwards them until the connection is closed
+async fn read_commands(mut reader: OwnedReadHalf, cmd_tx: mpsc::Sender<ClientCommand>) {
+    loop {
+        let cmd = match read_command(&mut reader).await {
+            Ok(cmd) => cmd,
+            Err(e) => {
+                debug!("Error reading command: {}", e);
+                return;
+            }
+        };
+        let unknown = cmd.cmd.is_err();
+        if cmd_tx.send(cmd).await.is_err() || unknown {
+            return;
+        }
+    }
 }
 
-impl ResultEntry {
-    fn from_bytes(b: &[u8]) -> Result<Self, &'static str> {
-        if b.len() < FIXED_SIZE_RESULT_ENTRY {
-            error!("Invalid binary result entry");
-            return Err("Invalid binary result entry");
+// read_command reads a command and its parameters from the client connection
+async fn read_command(reader: &mut OwnedReadHalf) -> io::Result<ClientCommand> {
+    let cmd = Command::try_from(reader.read_u64().await?);
+    let stream_type = reader.read_u64().await?;
+
+    let mut from_entry = 0;
+    let mut bookmark = Vec::new();
+    match cmd {
+        Ok(Command::CmdStart) | Ok(Command::CmdEntry) => {
+            from_entry = reader.read_u64().await?;
+        }
+        Ok(Command::CmdStartBookmark) | Ok(Command::CmdBookmark) => {
+            let length = reader.read_u32().await? as usize;
+            if length > MAX_BOOKMARK_LENGTH {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("bookmark length {} exceeds the maximum", length),
+                ));
+            }
+            bookmark = vec![0; length];
+            reader.read_exact(&mut bookmark).await?;
         }
+        _ => {}
+    }
 
-        let packet_type = b[0];
-        let length = BigEndian::read_u32(&b[1..5]);
-        let error_num = BigEndian::read_u32(&b[5..9]);
-        let error_str = b[9..].to_vec();
+    Ok(ClientCommand {
+        cmd,
+        stream_type,
+        from_entry,
+        bookmark,
+    })
+}
 
-        if error_str.len() as u32 != length - FIXED_SIZE_RESULT_ENTRY as u32 {
-            error!("Error decoding binary result entry");
-            return Err("Error decoding binary result entry");
+// process_command executes a client command and returns if the connection should be kept open
+async fn process_command(
+    writer: &mut OwnedWriteHalf,
+    shared: &Shared,
+    streaming: &mut Option<u64>,
+    cmd: ClientCommand,
+) -> io::Result<bool> {
+    let command = match cmd.cmd {
+        Ok(command) => command,
+        Err(v) => {
+            send_result(
+                writer,
+                CommandError::CmdErrInvalidCommand,
+                "Invalid command",
+            )
+            .await?;
+            info!("Invalid command {}", v);
+            return Ok(false);
         }
+    };
+    debug!("Command {:?} received", command);
 
-        Ok(Self {
-            packet_type,
-            length,
-            error_num,
-            error_str,
-        })
+    if cmd.stream_type != u64::from(shared.stream_type) {
+        send_result(
+            writer,
+            CommandError::CmdErrInvalidCommand,
+            "Invalid stream type",
+        )
+        .await?;
+        return Ok(false);
     }
 
-    fn print(&self) {
-        debug!("--- RESULT ENTRY -------------------------");
-        debug!("packetType: [{}]", self.packet_type);
-        debug!("length: [{}]", self.length);
-        debug!("errorNum: [{}]", self.error_num);
-        debug!("errorStr: [{}]", String::from_utf8_lossy(&self.error_str));
+    match command {
+        Command::CmdStart => {
+            if streaming.is_some() {
+                send_result(
+                    writer,
+                    CommandError::CmdErrAlreadyStarted,
+                    "Already started",
+                )
+                .await?;
+            } else if cmd.from_entry > shared.header().total_entries {
+                send_result(writer, CommandError::CmdErrBadFromEntry, "Bad from entry").await?;
+            } else {
+                send_result(writer, CommandError::CmdErrOK, "").await?;
+                *streaming = Some(cmd.from_entry);
+            }
+        }
+        Command::CmdStartBookmark => {
+            if streaming.is_some() {
+                send_result(
+                    writer,
+                    CommandError::CmdErrAlreadyStarted,
+                    "Already started",
+                )
+                .await?;
+            } else {
+                match shared.bookmark(&cmd.bookmark) {
+                    Some(e) => {
+                        send_result(writer, CommandError::CmdErrOK, "").await?;
+                        *streaming = Some(e.number);
+                    }
+                    None => {
+                        send_result(
+                            writer,
+                            CommandError::CmdErrBadFromBookmark,
+                            "Bad from bookmark",
+                        )
+                        .await?;
+                    }
+                }
+            }
+        }
+        Command::CmdStop => {
+            if streaming.is_none() {
+                send_result(
+                    writer,
+                    CommandError::CmdErrAlreadyStopped,
+                    "Already stopped",
+                )
+                .await?;
+            } else {
+                send_result(writer, CommandError::CmdErrOK, "").await?;
+                *streaming = None;
+            }
+        }
+        Command::CmdHeader => {
+            if streaming.is_some() {
+                send_result(
+                    writer,
+                    CommandError::CmdErrAlreadyStarted,
+                    "Already started",
+                )
+                .await?;
+            } else {
+                send_result(writer, CommandError::CmdErrOK, "").await?;
+                writer
+                    .write_all(&shared.header().encode_to_binary())
+                    .await?;
+            }
+        }
+        Command::CmdEntry => {
+            if streaming.is_some() {
+                send_result(
+                    writer,
+                    CommandError::CmdErrAlreadyStarted,
+                    "Already started",
+                )
+                .await?;
+            } else {
+                send_result(writer, CommandError::CmdErrOK, "").await?;
+                send_data_rsp(writer, shared.entry(cmd.from_entry)).await?;
+            }
+        }
+        Command::CmdBookmark => {
+            if streaming.is_some() {
+                send_result(
+                    writer,
+                    CommandError::CmdErrAlreadyStarted,
+                    "Already started",
+                )
+                .await?;
+            } else {
+                send_result(writer, CommandError::CmdErrOK, "").await?;
+                send_data_rsp(writer, shared.bookmark(&cmd.bookmark)).await?;
+            }
+        }
     }
+
+    Ok(true)
+}
+
+// send_result sends a command result entry to the client
+async fn send_result(
+    writer: &mut OwnedWriteHalf,
+    err: CommandError,
+    error_str: &str,
+) -> io::Result<()> {
+    let result = ResultEntry {
+        packet_type: PacketType::PtResult as u8,
+        length: 0,
+        error_num: u32::from(err),
+        error_str: error_str.as_bytes().to_vec(),
+    };
+    writer.write_all(&result.encode_to_binary()).await
 }
 
-impl Command {
-    fn is_a_command(&self) -> bool {
-        match self {
-            Self::CmdStart..=Self::CmdBookmark => true,
-            _ => false,
+// send_data_rsp sends a data entry as a command response, or a not found entry if there is none
+async fn send_data_rsp(writer: &mut OwnedWriteHalf, entry: Option<Entry>) -> io::Result<()> {
+    let mut e = entry.unwrap_or(Entry {
+        entry_type: EntryType::NotFound,
+        ..Default::default()
+    });
+    e.packet_type = PacketType::PtDataRsp as u8;
+    writer.write_all(&e.encode_to_binary()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream_client::{Bookmark, ChainId, ClientError, StreamClient};
+    use std::time::Duration;
+
+    async fn start_server(entries: u64) -> (StreamServer, String) {
+        let mut server = StreamServer::new(0, 1, 1101, StreamType::Sequencer);
+        server.start_atomic_op().unwrap();
+        for i in 0..entries {
+            server
+                .add_stream_entry(EntryType::Event1, i.to_be_bytes().to_vec())
+                .unwrap();
+        }
+        server.commit_atomic_op().unwrap();
+        server.start().await.unwrap();
+        let addr = format!("127.0.0.1:{}", server.local_addr().unwrap().port());
+        (server, addr)
+    }
+
+    #[tokio::test]
+    async fn test_stream_server_header_and_entry() {
+        let (_server, addr) = start_server(3).await;
+
+        let mut client = StreamClient::new(addr).unwrap();
+        client.connect_server().await.unwrap();
+
+        let header = client.exec_command_get_header().await.unwrap();
+        assert_eq!(header.system_id, ChainId(1101));
+        assert_eq!(header.total_entries, 3);
+        assert_eq!(
+            header.total_length,
+            (HEADER_SIZE + 3 * (FIXED_SIZE_FILE_ENTRY + 8)) as u64
+        );
+
+        let e = client.exec_command_get_entry(2).await.unwrap();
+        assert_eq!(e.number, 2);
+        assert_eq!(e.entry_type, EntryType::Event1);
+        assert_eq!(e.data, 2u64.to_be_bytes().to_vec());
+
+        let res = client.exec_command_get_entry(3).await;
+        assert!(matches!(res, Err(ClientError::EntryNotFound)));
+    }
+
+    #[tokio::test]
+    async fn test_stream_server_streams_entries() {
+        let (server, addr) = start_server(5).await;
+
+        let mut client = StreamClient::new(addr).unwrap();
+        let mut rx = client.subscribe();
+        let client_task = tokio::spawn(async move {
+            _ = client.start().await;
+        });
+
+        for number in 0..5 {
+            let e = rx.recv().await.unwrap();
+            assert_eq!(e.number, number);
         }
+
+        // New entries are streamed as they are added
+        server.start_atomic_op().unwrap();
+        server
+            .add_stream_entry(EntryType::Event2, vec![0xaa])
+            .unwrap();
+        server.commit_atomic_op().unwrap();
+        let e = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(e.number, 5);
+        assert_eq!(e.entry_type, EntryType::Event2);
+        assert_eq!(e.data, vec![0xaa]);
+
+        client_task.abort();
+    }
+
+    #[tokio::test]
+    async fn test_stream_server_stop_and_bookmark() {
+        let (server, addr) = start_server(2).await;
+        let bookmark = Bookmark::new(1, 7u64.to_be_bytes().to_vec());
+        server.start_atomic_op().unwrap();
+        server
+            .add_stream_entry(EntryType::Bookmark, bookmark.encode())
+            .unwrap();
+        server.commit_atomic_op().unwrap();
+
+        let mut client = StreamClient::new(addr).unwrap();
+        client.connect_server().await.unwrap();
+
+        let e = client.exec_command_get_bookmark(bookmark).await.unwrap();
+        assert_eq!(e.number, 2);
+
+        let res = client.exec_command_stop().await;
+        assert!(matches!(
+            res,
+            Err(ClientError::CommandFailed(
+                CommandError::CmdErrAlreadyStopped,
+                _
+            ))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_atomic_op_commit() {
+        let (server, addr) = start_server(2).await;
+
+        let mut client = StreamClient::new(addr).unwrap();
+        client.connect_server().await.unwrap();
+
+        server.start_atomic_op().unwrap();
+        assert_eq!(server.ao_status(), AOStatus::AoStarted);
+        assert_eq!(
+            server.add_stream_entry(EntryType::Event1, vec![2]).unwrap(),
+            2
+        );
+        assert_eq!(
+            server.add_stream_entry(EntryType::Event2, vec![3]).unwrap(),
+            3
+        );
+        assert!(matches!(
+            server.start_atomic_op(),
+            Err(ServerError::InvalidAtomicOpStatus(AOStatus::AoStarted))
+        ));
+
+        // The entries aren't visible until committed
+        assert_eq!(server.get_header().total_entries, 2);
+        assert!(server.get_entry(2).is_none());
+        let res = client.exec_command_get_entry(2).await;
+        assert!(matches!(res, Err(ClientError::EntryNotFound)));
+
+        server.commit_atomic_op().unwrap();
+        assert_eq!(server.ao_status(), AOStatus::AoNone);
+        let header = client.exec_command_get_header().await.unwrap();
+        assert_eq!(header.total_entries, 4);
+        assert_eq!(
+            header.total_length,
+            (HEADER_SIZE + 2 * (FIXED_SIZE_FILE_ENTRY + 8) + 2 * (FIXED_SIZE_FILE_ENTRY + 1))
+                as u64
+        );
+        let e = client.exec_command_get_entry(3).await.unwrap();
+        assert_eq!(e.entry_type, EntryType::Event2);
+        assert_eq!(e.data, vec![3]);
+    }
+
+    #[tokio::test]
+    async fn test_atomic_op_rollback() {
+        let (server, _addr) = start_server(2).await;
+
+        // Entries are only added within an atomic operation
+        assert!(matches!(
+            server.add_stream_entry(EntryType::Event1, vec![2]),
+            Err(ServerError::InvalidAtomicOpStatus(AOStatus::AoNone))
+        ));
+        assert!(matches!(
+            server.commit_atomic_op(),
+            Err(ServerError::InvalidAtomicOpStatus(AOStatus::AoNone))
+        ));
+        assert!(server.rollback_atomic_op().is_err());
+
+        server.start_atomic_op().unwrap();
+        server.add_stream_entry(EntryType::Event1, vec![2]).unwrap();
+        server.rollback_atomic_op().unwrap();
+        assert_eq!(server.ao_status(), AOStatus::AoNone);
+        assert_eq!(server.get_header().total_entries, 2);
+        assert!(server.get_entry(2).is_none());
+
+        // The entry numbers of the discarded entries are reused
+        server.start_atomic_op().unwrap();
+        assert_eq!(
+            server.add_stream_entry(EntryType::Event2, vec![4]).unwrap(),
+            2
+        );
+        server.commit_atomic_op().unwrap();
+        assert_eq!(server.get_entry(2).unwrap().data, vec![4]);
     }
 }
@@ -0,0 +1,323 @@
+// Mock data stream server to unit test client logic without a live server, enabled with the
+// test-util feature.
+//
+// Every command received is answered with the next response scripted for it, the last response
+// of a command is repeated once its script is exhausted. A header and a few data entries are
+// scripted like:
+//
+//     let server = MockStreamServer::builder()
+//         .on(Command::CmdHeader, vec![MockPacket::ok(), MockPacket::header(2)])
+//         .on(
+//             Command::CmdStart,
+//             vec![
+//                 MockPacket::ok(),
+//                 MockPacket::data(0, EntryType::Event1, vec![0xa0]),
+//                 MockPacket::data(1, EntryType::Event1, vec![0xa1]),
+//             ],
+//         )
+//         .start()
+//         .await?;
+//     let mut client = StreamClient::new(server.addr())?;
+//
+// Commands without a script are answered with a CmdErrInvalidCommand result.
+use crate::protocol::{Command, CommandError, EntryType, PacketType, StreamType};
+use crate::stream_client::{Entry, HeaderEntry, ResultEntry, HEADER_SIZE, MAX_BOOKMARK_LENGTH};
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use tracing::debug;
+
+// MockPacket type for a packet of a scripted response
+#[derive(Debug, Clone, PartialEq)]
+pub enum MockPacket {
+    Result(ResultEntry),
+    Header(HeaderEntry),
+    Entry(Entry),
+    Raw(Vec<u8>), // Bytes sent as they are, to script malformed packets
+}
+
+impl MockPacket {
+    // ok returns a successful command result
+    pub fn ok() -> MockPacket {
+        MockPacket::Result(ResultEntry {
+            packet_type: PacketType::PtResult as u8,
+            error_num: u32::from(CommandError::CmdErrOK),
+            ..Default::default()
+        })
+    }
+
+    // error returns a failed command result with its error string
+    pub fn error(error: CommandError, error_str: &str) -> MockPacket {
+        MockPacket::Result(ResultEntry {
+            packet_type: PacketType::PtResult as u8,
+            error_num: u32::from(error),
+            error_str: error_str.as_bytes().to_vec(),
+            ..Default::default()
+        })
+    }
+
+    // header returns a version 1 sequencer stream header with total_entries
+    pub fn header(total_entries: u64) -> MockPacket {
+        MockPacket::Header(HeaderEntry {
+            packet_type: PacketType::PtHeader as u8,
+            head_length: HEADER_SIZE as u32,
+            version: 1,
+            stream_type: StreamType::Sequencer,
+            total_entries,
+            ..Default::default()
+        })
+    }
+
+    // data returns a streamed data entry
+    pub fn data(number: u64, entry_type: EntryType, data: Vec<u8>) -> MockPacket {
+        MockPacket::Entry(Entry {
+            packet_type: PacketType::PtData as u8,
+            entry_type,
+            number,
+            data,
+            ..Default::default()
+        })
+    }
+
+    // data_rsp returns the data entry response of an entry or bookmark command
+    pub fn data_rsp(number: u64, entry_type: EntryType, data: Vec<u8>) -> MockPacket {
+        MockPacket::Entry(Entry {
+            packet_type: PacketType::PtDataRsp as u8,
+            entry_type,
+            number,
+            data,
+            ..Default::default()
+        })
+    }
+
+    // encode_to_binary encodes the packet as sent to the client
+    pub fn encode_to_binary(&self) -> Vec<u8> {
+        match self {
+            MockPacket::Result(r) => r.encode_to_binary(),
+            MockPacket::Header(h) => h.encode_to_binary(),
+            MockPacket::Entry(e) => e.encode_to_binary(),
+            MockPacket::Raw(b) => b.clone(),
+        }
+    }
+}
+
+// ReceivedCommand type for a command received by the mock server
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReceivedCommand {
+    pub command: u64,
+    pub stream_type: u64,
+    pub from_entry: u64,   // Entry number of the start and entry commands
+    pub bookmark: Vec<u8>, // Bookmark of the start bookmark and bookmark commands
+}
+
+type Scripts = HashMap<u64, VecDeque<Vec<MockPacket>>>;
+
+// MockStreamServer type for a local server answering the commands with scripted responses
+#[derive(Debug)]
+pub struct MockStreamServer {
+    addr: SocketAddr,
+    received: Arc<Mutex<Vec<ReceivedCommand>>>,
+    task: JoinHandle<()>,
+}
+
+impl MockStreamServer {
+    // builder returns a MockStreamServerBuilder to script the responses
+    pub fn builder() -> MockStreamServerBuilder {
+        MockStreamServerBuilder::default()
+    }
+
+    // addr returns the server address to connect the client to
+    pub fn addr(&self) -> String {
+        self.addr.to_string()
+    }
+
+    // received returns the commands received so far, from all the connections
+    pub fn received(&self) -> Vec<ReceivedCommand> {
+        self.received.lock().unwrap().clone()
+    }
+}
+
+impl Drop for MockStreamServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+// MockStreamServerBuilder type to script the responses of a MockStreamServer
+#[derive(Debug, Default)]
+pub struct MockStreamServerBuilder {
+    scripts: Scripts,
+    latency: Duration, // Delay before sending every response
+}
+
+impl MockStreamServerBuilder {
+    // on adds the packets sent in response to the next command received of type cmd
+    pub fn on(mut self, cmd: Command, response: Vec<MockPacket>) -> Self {
+        self.scripts
+            .entry(cmd as u64)
+            .or_default()
+            .push_back(response);
+        self
+    }
+
+    // latency delays every response, to simulate a distant server
+    pub fn latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    // start binds the server to an ephemeral local port and starts answering the connections
+    pub async fn start(self) -> io::Result<MockStreamServer> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let scripts = Arc::new(Mutex::new(self.scripts));
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let latency = self.latency;
+
+        let task = {
+            let received = received.clone();
+            tokio::spawn(async move {
+                while let Ok((conn, _)) = listener.accept().await {
+                    tokio::spawn(serve(conn, scripts.clone(), received.clone(), latency));
+                }
+            })
+        };
+
+        Ok(MockStreamServer {
+            addr,
+            received,
+            task,
+        })
+    }
+}
+
+// serve answers the commands of a connection until it's closed
+async fn serve(
+    mut conn: TcpStream,
+    scripts: Arc<Mutex<Scripts>>,
+    received: Arc<Mutex<Vec<ReceivedCommand>>>,
+    latency: Duration,
+) {
+    loop {
+        let cmd = match read_command(&mut conn).await {
+            Ok(cmd) => cmd,
+            Err(e) => {
+                debug!("Mock server connection closed: {}", e);
+                return;
+            }
+        };
+
+        let response = next_response(&mut scripts.lock().unwrap(), cmd.command);
+        received.lock().unwrap().push(cmd);
+
+        if !latency.is_zero() {
+            tokio::time::sleep(latency).await;
+        }
+        let b: Vec<u8> = response.iter().flat_map(|p| p.encode_to_binary()).collect();
+        if conn.write_all(&b).await.is_err() {
+            return;
+        }
+    }
+}
+
+// next_response pops the next scripted response of a command, keeping the last one
+fn next_response(scripts: &mut Scripts, command: u64) -> Vec<MockPacket> {
+    match scripts.get_mut(&command) {
+        Some(script) if script.len() > 1 => script.pop_front().unwrap_or_default(),
+        Some(script) if !script.is_empty() => script[0].clone(),
+        _ => vec![MockPacket::error(
+            CommandError::CmdErrInvalidCommand,
+            "no scripted response",
+        )],
+    }
+}
+
+// read_command reads a command and its parameters from the client connection
+async fn read_command(conn: &mut TcpStream) -> io::Result<ReceivedCommand> {
+    let mut cmd = ReceivedCommand {
+        command: conn.read_u64().await?,
+        stream_type: conn.read_u64().await?,
+        ..Default::default()
+    };
+
+    if cmd.command == Command::CmdStart as u64 || cmd.command == Command::CmdEntry as u64 {
+        cmd.from_entry = conn.read_u64().await?;
+    } else if cmd.command == Command::CmdStartBookmark as u64
+        || cmd.command == Command::CmdBookmark as u64
+    {
+        let length = conn.read_u32().await? as usize;
+        if length > MAX_BOOKMARK_LENGTH {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("bookmark length {} exceeds the maximum", length),
+            ));
+        }
+        cmd.bookmark = vec![0; length];
+        conn.read_exact(&mut cmd.bookmark).await?;
+    }
+
+    Ok(cmd)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream_client::{Bookmark, ClientError, StreamClient};
+
+    #[tokio::test]
+    async fn test_mock_stream_server() {
+        let server = MockStreamServer::builder()
+            .on(
+                Command::CmdHeader,
+                vec![MockPacket::ok(), MockPacket::header(5)],
+            )
+            .on(
+                Command::CmdHeader,
+                vec![MockPacket::ok(), MockPacket::header(6)],
+            )
+            .on(
+                Command::CmdEntry,
+                vec![
+                    MockPacket::ok(),
+                    MockPacket::data_rsp(3, EntryType::Event1, vec![3]),
+                ],
+            )
+            .start()
+            .await
+            .unwrap();
+
+        let mut client = StreamClient::new(server.addr()).unwrap();
+        client.connect_server().await.unwrap();
+
+        // The scripted responses are sent in order, repeating the last one
+        for total_entries in [5, 6, 6] {
+            let header = client.exec_command_get_header().await.unwrap();
+            assert_eq!(header.total_entries, total_entries);
+        }
+
+        let e = client.exec_command_get_entry(3).await.unwrap();
+        assert_eq!(e.data, vec![3]);
+
+        // Commands without a script fail
+        assert!(matches!(
+            client
+                .exec_command_get_bookmark(Bookmark::new(1, vec![]))
+                .await,
+            Err(ClientError::CommandFailed(
+                CommandError::CmdErrInvalidCommand,
+                _
+            ))
+        ));
+
+        let received = server.received();
+        assert_eq!(received.len(), 5);
+        assert_eq!(received[3].command, Command::CmdEntry as u64);
+        assert_eq!(received[3].from_entry, 3);
+        assert_eq!(received[4].bookmark, vec![0x01]);
+    }
+}
@@ -0,0 +1,428 @@
+// Typed decoders of the zkEVM sequencer stream entries, enabled with the zkevm feature (default).
+// The entries data are encoded in little endian by the Go node:
+//
+//     Event1     L2BlockStart
+//     Event2     L2Transaction
+//     Event(3)   L2BlockEnd
+//
+// Every type is decoded with Entry::decode_data or FromEntryData::from_entry.
+use crate::entry_data::{DecodeError, FromEntryData};
+use crate::stream_client::EntryType;
+use byteorder::{ByteOrder, LittleEndian};
+
+pub const L2_BLOCK_START_SIZE: usize = 122; // Size of the L2BlockStart entry data
+
+// L2BlockStart type for the data of the zkEVM sequencer L2 block start entries (Event1),
+// encoded in little endian by the Go node
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct L2BlockStart {
+    pub batch_number: u64,
+    pub l2_block_number: u64,
+    pub timestamp: i64,
+    pub delta_timestamp: u32,
+    pub l1_info_tree_index: u32,
+    pub l1_block_hash: [u8; 32],
+    pub global_exit_root: [u8; 32],
+    pub coinbase: [u8; 20],
+    pub fork_id: u16,
+    pub chain_id: u32,
+}
+
+impl L2BlockStart {
+    // encode encodes the L2 block start to the entry data format
+    pub fn encode(&self) -> Vec<u8> {
+        let mut b = Vec::with_capacity(L2_BLOCK_START_SIZE);
+        b.extend_from_slice(&self.batch_number.to_le_bytes());
+        b.extend_from_slice(&self.l2_block_number.to_le_bytes());
+        b.extend_from_slice(&self.timestamp.to_le_bytes());
+        b.extend_from_slice(&self.delta_timestamp.to_le_bytes());
+        b.extend_from_slice(&self.l1_info_tree_index.to_le_bytes());
+        b.extend_from_slice(&self.l1_block_hash);
+        b.extend_from_slice(&self.global_exit_root);
+        b.extend_from_slice(&self.coinbase);
+        b.extend_from_slice(&self.fork_id.to_le_bytes());
+        b.extend_from_slice(&self.chain_id.to_le_bytes());
+        b
+    }
+}
+
+impl FromEntryData for L2BlockStart {
+    fn from_entry_data(entry_type: EntryType, b: &[u8]) -> Result<Self, DecodeError> {
+        if entry_type != EntryType::Event1 {
+            return Err(DecodeError::UnexpectedEntryType(entry_type));
+        }
+        if b.len() != L2_BLOCK_START_SIZE {
+            return Err(DecodeError::InvalidLength(L2_BLOCK_START_SIZE, b.len()));
+        }
+
+        let mut l1_block_hash = [0u8; 32];
+        l1_block_hash.copy_from_slice(&b[32..64]);
+        let mut global_exit_root = [0u8; 32];
+        global_exit_root.copy_from_slice(&b[64..96]);
+        let mut coinbase = [0u8; 20];
+        coinbase.copy_from_slice(&b[96..116]);
+
+        Ok(L2BlockStart {
+            batch_number: LittleEndian::read_u64(&b[0..8]),
+            l2_block_number: LittleEndian::read_u64(&b[8..16]),
+            timestamp: LittleEndian::read_i64(&b[16..24]),
+            delta_timestamp: LittleEndian::read_u32(&b[24..28]),
+            l1_info_tree_index: LittleEndian::read_u32(&b[28..32]),
+            l1_block_hash,
+            global_exit_root,
+            coinbase,
+            fork_id: LittleEndian::read_u16(&b[116..118]),
+            chain_id: LittleEndian::read_u32(&b[118..122]),
+        })
+    }
+}
+
+pub const L2_TX_FIXED_SIZE: usize = 38; // Size of the L2Transaction entry data without the encoded tx
+pub const L2_BLOCK_END_SIZE: usize = 72; // Size of the L2BlockEnd entry data
+pub const ENTRY_TYPE_L2_BLOCK_END: u32 = 3; // Entry type number of the L2BlockEnd entries
+
+// L2Transaction type for the data of the zkEVM sequencer L2 transaction entries (Event2)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct L2Transaction {
+    pub effective_gas_price_percentage: u8,
+    pub is_valid: bool,
+    pub state_root: [u8; 32],
+    pub encoded: Vec<u8>, // RLP encoded transaction
+}
+
+impl L2Transaction {
+    // encode encodes the L2 transaction to the entry data format
+    pub fn encode(&self) -> Vec<u8> {
+        let mut b = Vec::with_capacity(L2_TX_FIXED_SIZE + self.encoded.len());
+        b.push(self.effective_gas_price_percentage);
+        b.push(self.is_valid as u8);
+        b.extend_from_slice(&self.state_root);
+        b.extend_from_slice(&(self.encoded.len() as u32).to_le_bytes());
+        b.extend_from_slice(&self.encoded);
+        b
+    }
+}
+
+impl FromEntryData for L2Transaction {
+    fn from_entry_data(entry_type: EntryType, b: &[u8]) -> Result<Self, DecodeError> {
+        if entry_type != EntryType::Event2 {
+            return Err(DecodeError::UnexpectedEntryType(entry_type));
+        }
+        if b.len() < L2_TX_FIXED_SIZE {
+            return Err(DecodeError::InvalidLength(L2_TX_FIXED_SIZE, b.len()));
+        }
+
+        let encoded_length = LittleEndian::read_u32(&b[34..38]) as usize;
+        if b.len() - L2_TX_FIXED_SIZE != encoded_length {
+            return Err(DecodeError::InvalidLength(
+                L2_TX_FIXED_SIZE.saturating_add(encoded_length),
+                b.len(),
+            ));
+        }
+
+        let mut state_root = [0u8; 32];
+        state_root.copy_from_slice(&b[2..34]);
+
+        Ok(L2Transaction {
+            effective_gas_price_percentage: b[0],
+            is_valid: match b[1] {
+                0 => false,
+                1 => true,
+                v => return Err(DecodeError::InvalidData(format!("is_valid flag {}", v))),
+            },
+            state_root,
+            encoded: b[L2_TX_FIXED_SIZE..].to_vec(),
+        })
+    }
+}
+
+// L2BlockEnd type for the data of the zkEVM sequencer L2 block end entries (Event(3))
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct L2BlockEnd {
+    pub l2_block_number: u64,
+    pub block_hash: [u8; 32],
+    pub state_root: [u8; 32],
+}
+
+impl L2BlockEnd {
+    // encode encodes the L2 block end to the entry data format
+    pub fn encode(&self) -> Vec<u8> {
+        let mut b = Vec::with_capacity(L2_BLOCK_END_SIZE);
+        b.extend_from_slice(&self.l2_block_number.to_le_bytes());
+        b.extend_from_slice(&self.block_hash);
+        b.extend_from_slice(&self.state_root);
+        b
+    }
+}
+
+impl FromEntryData for L2BlockEnd {
+    fn from_entry_data(entry_type: EntryType, b: &[u8]) -> Result<Self, DecodeError> {
+        if entry_type != EntryType::Event(ENTRY_TYPE_L2_BLOCK_END) {
+            return Err(DecodeError::UnexpectedEntryType(entry_type));
+        }
+        if b.len() != L2_BLOCK_END_SIZE {
+            return Err(DecodeError::InvalidLength(L2_BLOCK_END_SIZE, b.len()));
+        }
+
+        let mut block_hash = [0u8; 32];
+        block_hash.copy_from_slice(&b[8..40]);
+        let mut state_root = [0u8; 32];
+        state_root.copy_from_slice(&b[40..72]);
+
+        Ok(L2BlockEnd {
+            l2_block_number: LittleEndian::read_u64(&b[0..8]),
+            block_hash,
+            state_root,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream_client::Entry;
+
+    // Hand-built entries data, not captured from a node, one field per line. Every field holds
+    // distinct bytes so a field read at the wrong offset or in the wrong byte order is caught.
+    const L2_BLOCK_START_FIXTURE: &str = concat!(
+        "40e2010000000000",                                                 // batch_number
+        "87d6120000000000",                                                 // l2_block_number
+        "00f1536500000000",                                                 // timestamp
+        "02000000",                                                         // delta_timestamp
+        "02010000",                                                         // l1_info_tree_index
+        "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f", // l1_block_hash
+        "202122232425262728292a2b2c2d2e2f303132333435363738393a3b3c3d3e3f", // global_exit_root
+        "404142434445464748494a4b4c4d4e4f50515253",                         // coinbase
+        "0900",                                                             // fork_id
+        "4d040000",                                                         // chain_id
+    );
+    const L2_TRANSACTION_FIXTURE: &str = concat!(
+        "ff", // effective_gas_price_percentage
+        "01", // is_valid
+        "606162636465666768696a6b6c6d6e6f707172737475767778797a7b7c7d7e7f", // state_root
+        "05000000", // encoded length
+        "a0a1a2a3a4", // encoded, opaque to the decoder
+    );
+    const L2_BLOCK_END_FIXTURE: &str = concat!(
+        "87d6120000000000", // l2_block_number
+        "808182838485868788898a8b8c8d8e8f909192939495969798999a9b9c9d9e9f", // block_hash
+        "606162636465666768696a6b6c6d6e6f707172737475767778797a7b7c7d7e7f", // state_root
+    );
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn hash(s: &str) -> [u8; 32] {
+        hex(s).try_into().unwrap()
+    }
+
+    fn l2_block_start() -> L2BlockStart {
+        L2BlockStart {
+            batch_number: 101,
+            l2_block_number: 2002,
+            timestamp: 1_700_000_000,
+            delta_timestamp: 3,
+            l1_info_tree_index: 7,
+            l1_block_hash: [0x11; 32],
+            global_exit_root: [0x22; 32],
+            coinbase: [0x33; 20],
+            fork_id: 9,
+            chain_id: 1101,
+        }
+    }
+
+    #[test]
+    fn test_decode_l2_block_start() {
+        let block = l2_block_start();
+        let entry = Entry {
+            entry_type: EntryType::Event1,
+            number: 5,
+            data: block.encode(),
+            ..Default::default()
+        };
+        assert_eq!(entry.data.len(), L2_BLOCK_START_SIZE);
+        assert_eq!(entry.decode_data::<L2BlockStart>().unwrap(), block);
+        assert_eq!(entry.decode_data::<Vec<u8>>().unwrap(), entry.data);
+    }
+
+    #[test]
+    fn test_decode_l2_block_start_fixture() {
+        let entry = Entry {
+            entry_type: EntryType::Event1,
+            data: hex(L2_BLOCK_START_FIXTURE),
+            ..Default::default()
+        };
+        assert_eq!(entry.data.len(), L2_BLOCK_START_SIZE);
+
+        let block = entry.decode_data::<L2BlockStart>().unwrap();
+        assert_eq!(block.batch_number, 123_456);
+        assert_eq!(block.l2_block_number, 1_234_567);
+        assert_eq!(block.timestamp, 1_700_000_000);
+        assert_eq!(block.delta_timestamp, 2);
+        assert_eq!(block.l1_info_tree_index, 258);
+        assert_eq!(
+            block.l1_block_hash,
+            hash("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f")
+        );
+        assert_eq!(
+            block.global_exit_root,
+            hash("202122232425262728292a2b2c2d2e2f303132333435363738393a3b3c3d3e3f")
+        );
+        assert_eq!(
+            block.coinbase.to_vec(),
+            hex("404142434445464748494a4b4c4d4e4f50515253")
+        );
+        assert_eq!(block.fork_id, 9);
+        assert_eq!(block.chain_id, 1101);
+        assert_eq!(block.encode(), entry.data);
+    }
+
+    #[test]
+    fn test_decode_l2_block_start_errors() {
+        let mut entry = Entry {
+            entry_type: EntryType::Event2,
+            data: l2_block_start().encode(),
+            ..Default::default()
+        };
+        assert_eq!(
+            entry.decode_data::<L2BlockStart>(),
+            Err(DecodeError::UnexpectedEntryType(EntryType::Event2))
+        );
+
+        entry.entry_type = EntryType::Event1;
+        entry.data.truncate(100);
+        assert_eq!(
+            entry.decode_data::<L2BlockStart>(),
+            Err(DecodeError::InvalidLength(L2_BLOCK_START_SIZE, 100))
+        );
+    }
+
+    #[test]
+    fn test_decode_l2_transaction() {
+        // effective gas price 255, valid, state root 0xaa.., 3 encoded bytes
+        let data = [
+            vec![0xff, 0x01],
+            vec![0xaa; 32],
+            vec![0x03, 0x00, 0x00, 0x00],
+            vec![0xc0, 0x01, 0x02],
+        ]
+        .concat();
+        let entry = Entry {
+            entry_type: EntryType::Event2,
+            data,
+            ..Default::default()
+        };
+
+        let tx = L2Transaction::from_entry(&entry).unwrap();
+        assert_eq!(tx.effective_gas_price_percentage, 255);
+        assert!(tx.is_valid);
+        assert_eq!(tx.state_root, [0xaa; 32]);
+        assert_eq!(tx.encoded, vec![0xc0, 0x01, 0x02]);
+        assert_eq!(tx.encode(), entry.data);
+    }
+
+    #[test]
+    fn test_decode_l2_transaction_fixture() {
+        let entry = Entry {
+            entry_type: EntryType::Event2,
+            data: hex(L2_TRANSACTION_FIXTURE),
+            ..Default::default()
+        };
+
+        let tx = entry.decode_data::<L2Transaction>().unwrap();
+        assert_eq!(tx.effective_gas_price_percentage, 255);
+        assert!(tx.is_valid);
+        assert_eq!(
+            tx.state_root,
+            hash("606162636465666768696a6b6c6d6e6f707172737475767778797a7b7c7d7e7f")
+        );
+        assert_eq!(tx.encoded, vec![0xa0, 0xa1, 0xa2, 0xa3, 0xa4]);
+        assert_eq!(tx.encode(), entry.data);
+    }
+
+    #[test]
+    fn test_decode_l2_transaction_errors() {
+        let tx = L2Transaction {
+            encoded: vec![0x01; 10],
+            ..Default::default()
+        };
+        let mut entry = Entry {
+            entry_type: EntryType::Event1,
+            data: tx.encode(),
+            ..Default::default()
+        };
+        assert_eq!(
+            L2Transaction::from_entry(&entry),
+            Err(DecodeError::UnexpectedEntryType(EntryType::Event1))
+        );
+
+        // The encoded length doesn't match the data
+        entry.entry_type = EntryType::Event2;
+        entry.data.truncate(L2_TX_FIXED_SIZE + 5);
+        assert_eq!(
+            L2Transaction::from_entry(&entry),
+            Err(DecodeError::InvalidLength(
+                L2_TX_FIXED_SIZE + 10,
+                L2_TX_FIXED_SIZE + 5
+            ))
+        );
+
+        entry.data.truncate(20);
+        assert_eq!(
+            L2Transaction::from_entry(&entry),
+            Err(DecodeError::InvalidLength(L2_TX_FIXED_SIZE, 20))
+        );
+    }
+
+    #[test]
+    fn test_decode_l2_block_end() {
+        let data = [
+            2002u64.to_le_bytes().to_vec(),
+            vec![0x44; 32],
+            vec![0x55; 32],
+        ]
+        .concat();
+        let entry = Entry {
+            entry_type: EntryType::Event(ENTRY_TYPE_L2_BLOCK_END),
+            data,
+            ..Default::default()
+        };
+
+        let block_end = entry.decode_data::<L2BlockEnd>().unwrap();
+        assert_eq!(block_end.l2_block_number, 2002);
+        assert_eq!(block_end.block_hash, [0x44; 32]);
+        assert_eq!(block_end.state_root, [0x55; 32]);
+        assert_eq!(block_end.encode(), entry.data);
+
+        assert_eq!(
+            L2BlockStart::from_entry(&entry),
+            Err(DecodeError::UnexpectedEntryType(EntryType::Event(3)))
+        );
+    }
+
+    #[test]
+    fn test_decode_l2_block_end_fixture() {
+        let entry = Entry {
+            entry_type: EntryType::Event(ENTRY_TYPE_L2_BLOCK_END),
+            data: hex(L2_BLOCK_END_FIXTURE),
+            ..Default::default()
+        };
+        assert_eq!(entry.data.len(), L2_BLOCK_END_SIZE);
+
+        let block_end = entry.decode_data::<L2BlockEnd>().unwrap();
+        assert_eq!(block_end.l2_block_number, 1_234_567);
+        assert_eq!(
+            block_end.block_hash,
+            hash("808182838485868788898a8b8c8d8e8f909192939495969798999a9b9c9d9e9f")
+        );
+        assert_eq!(
+            block_end.state_root,
+            hash("606162636465666768696a6b6c6d6e6f707172737475767778797a7b7c7d7e7f")
+        );
+        assert_eq!(block_end.encode(), entry.data);
+    }
+}